@@ -1,96 +1,270 @@
-use crate::types::DbResult;
-use crate::utils::sqlx_public;
+use crate::types::{
+    ConnConfig, ConnSettings, ConnSummary, ConnectInfo, ExecResult, QueryResult, ServerInfo,
+    SessionState,
+};
+use crate::utils::sqlx_session::{self, TxControl};
+use crate::utils::{sqlx_cancel, sqlx_config, sqlx_cursor, sqlx_error::DbError, sqlx_public};
 
 #[tauri::command]
-pub async fn sqlx_connect(conn_name: String, url: String) -> DbResult {
-    let mut res = DbResult::new();
+pub async fn sqlx_connect(
+    conn_name: String,
+    config: ConnConfig,
+    settings: Option<ConnSettings>,
+) -> Result<ConnectInfo, DbError> {
+    sqlx_public::connect(&conn_name, &config, settings.unwrap_or_default())
+        .await
+        .inspect_err(|e| eprintln!("Error occurred in sqlx_connect: {:?}", e))
+}
 
-    match sqlx_public::connect(&conn_name, &url).await {
-        Ok(()) => res.data = "ok".to_string(),
-        Err(e) => {
-            eprintln!("Error occurred in sqlx_connect: {:?}", e);
-            res.error_message = e.to_string()
-        }
-    }
+// 测试连接并返回服务端信息, 不会登记连接, 不受连接名重复的限制
+// Test the connection and return the server information, nothing is registered so connection names can't collide
+#[tauri::command]
+pub async fn sqlx_test_connection(
+    config: ConnConfig,
+    settings: Option<ConnSettings>,
+) -> Result<ServerInfo, DbError> {
+    sqlx_public::test_connection(&config, settings.unwrap_or_default())
+        .await
+        .inspect_err(|e| eprintln!("Error occurred in sqlx_test_connection: {:?}", e))
+}
 
-    res
+// 把连接 URL 解析为连接参数, 用于粘贴 URL 的场景
+// Parse a connection URL into connection parameters, for pasting a URL
+#[tauri::command]
+pub fn sqlx_parse_url(url: String) -> Result<ConnConfig, DbError> {
+    sqlx_config::parse_url(&url)
 }
 
 #[tauri::command]
-pub async fn sqlx_disconnect(conn_name: String) -> DbResult {
-    let mut res = DbResult::new();
+pub async fn sqlx_disconnect(conn_name: String) -> Result<bool, DbError> {
+    sqlx_public::disconnect(&conn_name)
+        .await
+        .inspect_err(|e| eprintln!("Error occurred in sqlx_disconnect: {:?}", e))
+}
 
-    match sqlx_public::disconnect(&conn_name).await {
-        Ok(o) => res.data = o.to_string(),
-        Err(e) => {
-            eprintln!("Error occurred in sqlx_disconnect: {:?}", e);
-            res.error_message = e.to_string()
-        }
-    }
+// 列出已登记的连接, 前端重新加载后用它判断哪些连接还在
+// List the registered connections, the frontend uses it to tell which connections are still live after a reload
+#[tauri::command]
+pub async fn sqlx_list_connections() -> Vec<ConnSummary> {
+    sqlx_public::list_connections().await
+}
 
-    res
+// 重新连接, config 和 settings 为空时使用上次连接的参数
+// Reconnect, the previous parameters are used when config and settings are empty
+#[tauri::command]
+pub async fn sqlx_reconnect(
+    conn_name: String,
+    config: Option<ConnConfig>,
+    settings: Option<ConnSettings>,
+) -> Result<ConnectInfo, DbError> {
+    sqlx_public::reconnect(&conn_name, config, settings)
+        .await
+        .inspect_err(|e| eprintln!("Error occurred in sqlx_reconnect: {:?}", e))
+}
+
+#[tauri::command]
+pub async fn sqlx_rename_connection(conn_name: String, new_name: String) -> Result<(), DbError> {
+    sqlx_public::rename(&conn_name, &new_name)
+        .await
+        .inspect_err(|e| eprintln!("Error occurred in sqlx_rename_connection: {:?}", e))
 }
 
 #[tauri::command]
 pub async fn sqlx_query(
     conn_name: String,
     sql: String,
-    streaming: bool,
+    max_rows: Option<usize>,
+    session_id: Option<String>,
+    statement_id: Option<String>,
+    timeout_ms: Option<u64>,
+    confirm_token: Option<String>,
+) -> Result<QueryResult, DbError> {
+    sqlx_public::query(
+        &conn_name,
+        &sql,
+        max_rows,
+        session_id.as_deref(),
+        statement_id.as_deref(),
+        timeout_ms,
+        confirm_token.as_deref(),
+    )
+    .await
+    .map_err(|e| {
+        eprintln!("Error occurred in sqlx_query: {:?}", e);
+        e.locate(&sql)
+    })
+}
+
+#[tauri::command]
+pub async fn sqlx_open_cursor(
+    conn_name: String,
+    sql: String,
+    confirm_token: Option<String>,
+) -> Result<String, DbError> {
+    sqlx_cursor::open_cursor(&conn_name, &sql, confirm_token.as_deref())
+        .await
+        .map_err(|e| {
+            eprintln!("Error occurred in sqlx_open_cursor: {:?}", e);
+            e.locate(&sql)
+        })
+}
+
+#[tauri::command]
+pub async fn sqlx_fetch_page(
+    conn_name: String,
+    cursor_id: String,
     page: Option<usize>,
     page_size: Option<usize>,
-) -> DbResult {
-    let mut res = DbResult::new();
-
-    match sqlx_public::query(&conn_name, &sql, streaming, page, page_size).await {
-        Ok(o) => {
-            res.column_name = o.column_name;
-            res.data = o.data;
-        }
-        Err(e) => {
-            eprintln!("Error occurred in sqlx_query: {:?}", e);
-            res.error_message = e.to_string()
-        }
-    }
-
-    res
-}
-
-#[tauri::command]
-pub async fn sqlx_exec(conn_name: String, sql: String) -> DbResult {
-    let mut res = DbResult::new();
-
-    match sqlx_public::exec(&conn_name, &sql).await {
-        Ok(o) => {
-            res.data = match serde_json::to_string(&o) {
-                Ok(oo) => oo,
-                Err(_) => "".to_string(),
-            };
-        }
-        Err(e) => {
-            eprintln!("Error occurred in sqlx_exec: {:?}", e);
-            res.error_message = e.to_string()
-        }
-    }
-
-    res
-}
-
-#[tauri::command]
-pub async fn sqlx_exec_many(conn_name: String, sql: String) -> DbResult {
-    let mut res = DbResult::new();
-
-    match sqlx_public::execute_many(&conn_name, &sql).await {
-        Ok(o) => {
-            res.data = match serde_json::to_string(&o) {
-                Ok(oo) => oo,
-                Err(_) => "".to_string(),
-            };
-        }
-        Err(e) => {
-            eprintln!("Error occurred in sqlx_exec_many: {:?}", e);
-            res.error_message = e.to_string()
-        }
-    }
-
-    res
+) -> Result<QueryResult, DbError> {
+    sqlx_cursor::fetch_page(
+        &conn_name,
+        &cursor_id,
+        page.unwrap_or(1),
+        page_size.unwrap_or(200),
+    )
+    .await
+    .inspect_err(|e| eprintln!("Error occurred in sqlx_fetch_page: {:?}", e))
+}
+
+#[tauri::command]
+pub async fn sqlx_close_cursor(conn_name: String, cursor_id: String) -> bool {
+    sqlx_cursor::close_cursor(&conn_name, &cursor_id).await
+}
+
+#[tauri::command]
+pub async fn sqlx_exec(
+    conn_name: String,
+    sql: String,
+    session_id: Option<String>,
+    statement_id: Option<String>,
+    timeout_ms: Option<u64>,
+    confirm_token: Option<String>,
+) -> Result<ExecResult, DbError> {
+    sqlx_public::exec(
+        &conn_name,
+        &sql,
+        session_id.as_deref(),
+        statement_id.as_deref(),
+        timeout_ms,
+        confirm_token.as_deref(),
+    )
+    .await
+    .map_err(|e| {
+        eprintln!("Error occurred in sqlx_exec: {:?}", e);
+        e.locate(&sql)
+    })
+}
+
+#[tauri::command]
+pub async fn sqlx_exec_many(
+    conn_name: String,
+    sql: String,
+    session_id: Option<String>,
+    statement_id: Option<String>,
+    timeout_ms: Option<u64>,
+    confirm_token: Option<String>,
+) -> Result<ExecResult, DbError> {
+    sqlx_public::execute_many(
+        &conn_name,
+        &sql,
+        session_id.as_deref(),
+        statement_id.as_deref(),
+        timeout_ms,
+        confirm_token.as_deref(),
+    )
+    .await
+    .inspect_err(|e| eprintln!("Error occurred in sqlx_exec_many: {:?}", e))
+}
+
+#[tauri::command]
+pub async fn sqlx_cancel(conn_name: String, statement_id: String) -> Result<bool, DbError> {
+    sqlx_cancel::cancel(&conn_name, &statement_id)
+        .await
+        .inspect_err(|e| eprintln!("Error occurred in sqlx_cancel: {:?}", e))
+}
+
+#[tauri::command]
+pub async fn sqlx_session_open(conn_name: String) -> Result<String, DbError> {
+    sqlx_session::open_session(&conn_name)
+        .await
+        .inspect_err(|e| eprintln!("Error occurred in sqlx_session_open: {:?}", e))
+}
+
+#[tauri::command]
+pub async fn sqlx_session_close(conn_name: String, session_id: String) -> bool {
+    sqlx_session::close_session(&conn_name, &session_id).await
+}
+
+#[tauri::command]
+pub async fn sqlx_session_state(
+    conn_name: String,
+    session_id: String,
+) -> Result<SessionState, DbError> {
+    sqlx_session::session_state(&conn_name, &session_id).await
+}
+
+#[tauri::command]
+pub async fn sqlx_session_begin(
+    conn_name: String,
+    session_id: String,
+) -> Result<SessionState, DbError> {
+    session_control(&conn_name, &session_id, TxControl::Begin).await
+}
+
+#[tauri::command]
+pub async fn sqlx_session_commit(
+    conn_name: String,
+    session_id: String,
+) -> Result<SessionState, DbError> {
+    session_control(&conn_name, &session_id, TxControl::Commit { chain: false }).await
+}
+
+#[tauri::command]
+pub async fn sqlx_session_rollback(
+    conn_name: String,
+    session_id: String,
+) -> Result<SessionState, DbError> {
+    session_control(
+        &conn_name,
+        &session_id,
+        TxControl::Rollback { chain: false },
+    )
+    .await
+}
+
+#[tauri::command]
+pub async fn sqlx_session_savepoint(
+    conn_name: String,
+    session_id: String,
+    name: String,
+) -> Result<SessionState, DbError> {
+    session_control(&conn_name, &session_id, TxControl::Savepoint(name)).await
+}
+
+#[tauri::command]
+pub async fn sqlx_session_rollback_to(
+    conn_name: String,
+    session_id: String,
+    name: String,
+) -> Result<SessionState, DbError> {
+    session_control(&conn_name, &session_id, TxControl::RollbackTo(name)).await
+}
+
+#[tauri::command]
+pub async fn sqlx_session_release(
+    conn_name: String,
+    session_id: String,
+    name: String,
+) -> Result<SessionState, DbError> {
+    session_control(&conn_name, &session_id, TxControl::Release(name)).await
+}
+
+async fn session_control(
+    conn_name: &str,
+    session_id: &str,
+    control: TxControl,
+) -> Result<SessionState, DbError> {
+    sqlx_session::control(conn_name, session_id, control)
+        .await
+        .inspect_err(|e| eprintln!("Error occurred in session control: {:?}", e))
 }
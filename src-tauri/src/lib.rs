@@ -4,11 +4,29 @@ mod commands;
 mod types;
 mod utils;
 
+use std::time::Duration;
+use tauri::{Emitter, RunEvent};
+
+// 连接健康状态变化时发给前端的事件
+// Event sent to the frontend when the health of a connection changes
+const HEALTH_EVENT: &str = "sqlx-health";
+
+// 退出时关闭连接最多等待的时间, 避免被还在执行的语句卡住
+// How long to wait for the connections to close on exit, so a running statement can't hang it
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_opener::init())
+        .setup(|app| {
+            let handle = app.handle().clone();
+            tauri::async_runtime::spawn(utils::sqlx_health::run(move |event| {
+                let _ = handle.emit(HEALTH_EVENT, event);
+            }));
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             commands::aes_gcm::aes_gcm_encrypt_string,
             commands::aes_gcm::aes_gcm_decrypt_base64,
@@ -21,11 +39,39 @@ pub fn run() {
             // commands::password::verify_password,
             commands::sha::sha256,
             commands::sql::sqlx_connect,
+            commands::sql::sqlx_parse_url,
+            commands::sql::sqlx_test_connection,
             commands::sql::sqlx_disconnect,
+            commands::sql::sqlx_list_connections,
+            commands::sql::sqlx_reconnect,
+            commands::sql::sqlx_rename_connection,
             commands::sql::sqlx_exec,
             commands::sql::sqlx_exec_many,
+            commands::sql::sqlx_cancel,
             commands::sql::sqlx_query,
+            commands::sql::sqlx_open_cursor,
+            commands::sql::sqlx_fetch_page,
+            commands::sql::sqlx_close_cursor,
+            commands::sql::sqlx_session_open,
+            commands::sql::sqlx_session_close,
+            commands::sql::sqlx_session_state,
+            commands::sql::sqlx_session_begin,
+            commands::sql::sqlx_session_commit,
+            commands::sql::sqlx_session_rollback,
+            commands::sql::sqlx_session_savepoint,
+            commands::sql::sqlx_session_rollback_to,
+            commands::sql::sqlx_session_release,
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|_app, event| {
+            // 退出前回滚事务并关闭连接, 避免服务端看到中断的连接
+            // Roll back transactions and close the connections before exiting, so servers don't see aborted connections
+            if let RunEvent::Exit = event {
+                tauri::async_runtime::block_on(async {
+                    let _ =
+                        tokio::time::timeout(CLOSE_TIMEOUT, utils::sqlx_public::close_all()).await;
+                });
+            }
+        });
 }
@@ -1,49 +1,424 @@
+use crate::utils::sqlx_common::DbType;
+use crate::utils::sqlx_error::DbError;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
+// 查询(query)的结果
+// Result of a query
 #[derive(Serialize, Deserialize, Debug, Default)]
-pub struct DbResult {
-    // 列表数组的 json, 仅查询的时候有数据
-    // JSON for list arrays, with data available only during queries
-    #[serde(rename = "columnName")]
-    pub column_name: String,
-
-    // 查询结果, 实际是 json 字符串
-    // The query result is actually a JSON string
-    #[serde(rename = "data")]
-    pub data: String,
-
-    // 错误消息
-    // error message
-    #[serde(rename = "errorMessage")]
-    pub error_message: String,
-}
+pub struct QueryResult {
+    // 按顺序排列的列信息
+    // Column info in order
+    #[serde(rename = "columns")]
+    pub columns: Vec<ColumnInfo>,
 
-impl DbResult {
-    pub fn new() -> Self {
-        Self::default()
-    }
-}
+    // 每行是按列顺序排列的数组
+    // Each row is an array in column order
+    #[serde(rename = "rows")]
+    pub rows: Vec<Vec<serde_json::Value>>,
 
-#[derive(Serialize, Deserialize, Debug, Default)]
-pub struct QueryResult {
-    // 列表数组的 json, 仅查询的时候有数据
-    // JSON for list arrays, with data available only during queries
-    #[serde(rename = "columnName")]
-    pub column_name: String,
+    // 执行耗时, 单位毫秒
+    // Execution time in milliseconds
+    #[serde(rename = "elapsedMs")]
+    pub elapsed_ms: f64,
 
-    // 查询结果, 实际是 json 字符串
-    // The query result is actually a JSON string
-    #[serde(rename = "data")]
-    pub data: String,
+    // 是否还有未返回的行
+    // Whether there are rows that were not returned
+    #[serde(rename = "truncated")]
+    pub truncated: bool,
 }
 
+// 执行(exec)的结果
+// Result of an execution
 #[derive(Serialize, Deserialize, Debug, Default)]
 pub struct ExecResult {
     #[serde(rename = "affectedRows")]
     pub affected_rows: u64,
 
+    // PostgreSQL 需要 RETURNING 子句, 这里始终为 None
+    // PostgreSQL requires a RETURNING clause, always None here
     #[serde(rename = "lastInsertId")]
-    pub last_insert_id: u64,
+    pub last_insert_id: Option<u64>,
+
+    // 执行耗时, 单位毫秒
+    // Execution time in milliseconds
+    #[serde(rename = "elapsedMs")]
+    pub elapsed_ms: f64,
+
+    // 在会话中执行后会话是否处于事务中, 不在会话中执行时为 None
+    // Whether the session is in a transaction after executing, None when not run in a session
+    #[serde(rename = "inTransaction")]
+    pub in_transaction: Option<bool>,
+}
+
+// 会话的事务状态
+// Transaction state of a session
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct SessionState {
+    #[serde(rename = "id")]
+    pub id: String,
+
+    #[serde(rename = "inTransaction")]
+    pub in_transaction: bool,
+
+    // 当前事务中的保存点, 按创建顺序排列
+    // Savepoints of the current transaction, in creation order
+    #[serde(rename = "savepoints")]
+    pub savepoints: Vec<String>,
+}
+
+// 连接参数, 由后端用 sqlx 的 ConnectOptions 构建连接, 不再由前端拼接 URL
+// Connection parameters, the backend builds the connection with the sqlx ConnectOptions
+// instead of the frontend concatenating a URL
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ConnConfig {
+    #[serde(rename = "engine")]
+    pub engine: DbType,
+
+    #[serde(rename = "host")]
+    pub host: Option<String>,
+
+    #[serde(rename = "port")]
+    pub port: Option<u16>,
+
+    #[serde(rename = "user")]
+    pub user: Option<String>,
+
+    #[serde(rename = "password")]
+    pub password: Option<String>,
+
+    // SQLite 是数据库文件路径, 或 :memory:
+    // The database file path for SQLite, or :memory:
+    #[serde(rename = "database")]
+    pub database: Option<String>,
+
+    // 使用 PostgreSQL 的写法 (disable / prefer / require / verify-ca / verify-full),
+    // 也接受 MySQL 的写法 (DISABLED / PREFERRED / REQUIRED / VERIFY_CA / VERIFY_IDENTITY)
+    // PostgreSQL names (disable / prefer / require / verify-ca / verify-full),
+    // MySQL names (DISABLED / PREFERRED / REQUIRED / VERIFY_CA / VERIFY_IDENTITY) are accepted too
+    #[serde(rename = "sslMode")]
+    pub ssl_mode: Option<String>,
+
+    // Unix 套接字路径, PostgreSQL 是套接字所在的目录
+    // Unix socket path, the directory containing the socket for PostgreSQL
+    #[serde(rename = "socket")]
+    pub socket: Option<String>,
+
+    // TLS 证书, 只用于 PostgreSQL 和 MySQL
+    // TLS certificates, only used for PostgreSQL and MySQL
+    #[serde(rename = "tls")]
+    pub tls: Option<TlsConfig>,
+
+    // 通过 SSH 跳板机连接, host 和 port 是从跳板机看到的数据库地址
+    // Connect through an SSH jump host, host and port are the database address as seen from the jump host
+    #[serde(rename = "ssh")]
+    pub ssh: Option<SshConfig>,
+
+    // 其它连接参数, 例如 PostgreSQL 的 application_name, MySQL 的 charset, SQLite 的 mode
+    // Extra connection options, e.g. application_name for PostgreSQL, charset for MySQL, mode for SQLite
+    #[serde(rename = "options", default)]
+    pub options: BTreeMap<String, String>,
+}
+
+// TLS 证书文件的路径, 文件内容是 PEM 格式
+// 设置了 CA 证书但没有设置 sslMode 时, 使用 verify-ca
+// Paths of the TLS certificate files, in PEM format
+// verify-ca is used when the CA certificate is set but sslMode isn't
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct TlsConfig {
+    // 用来验证服务端证书的根证书
+    // Root certificate used to verify the server certificate
+    #[serde(rename = "caCert")]
+    pub ca_cert: Option<String>,
+
+    // 双向 TLS 的客户端证书和私钥, 需要同时设置
+    // Client certificate and private key for mutual TLS, must be set together
+    #[serde(rename = "clientCert")]
+    pub client_cert: Option<String>,
+
+    #[serde(rename = "clientKey")]
+    pub client_key: Option<String>,
+}
+
+// SSH 隧道的设置
+// SSH tunnel settings
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SshConfig {
+    #[serde(rename = "host")]
+    pub host: String,
+
+    // 默认 22 | Defaults to 22
+    #[serde(rename = "port")]
+    pub port: Option<u16>,
+
+    #[serde(rename = "user")]
+    pub user: String,
+
+    #[serde(rename = "auth")]
+    pub auth: SshAuth,
+
+    // known_hosts 文件路径, 默认 ~/.ssh/known_hosts
+    // Path of the known_hosts file, defaults to ~/.ssh/known_hosts
+    #[serde(rename = "knownHosts")]
+    pub known_hosts: Option<String>,
+
+    // 主机不在 known_hosts 中时把它加进去, 相当于 StrictHostKeyChecking=accept-new
+    // 密钥不匹配时始终报错
+    // Add the host to known_hosts when it isn't there, like StrictHostKeyChecking=accept-new
+    // A mismatching key is always an error
+    #[serde(rename = "acceptNewHost")]
+    pub accept_new_host: Option<bool>,
+}
+
+// SSH 认证方式
+// SSH authentication method
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "method", rename_all = "lowercase")]
+pub enum SshAuth {
+    Password {
+        #[serde(rename = "password")]
+        password: String,
+    },
+    // 私钥文件, 支持 OpenSSH 和 PEM 格式
+    // Private key file, OpenSSH and PEM formats are supported
+    Key {
+        #[serde(rename = "privateKey")]
+        private_key: String,
+
+        #[serde(rename = "passphrase")]
+        passphrase: Option<String>,
+    },
+    // 使用 ssh-agent 中的密钥 | Use the keys in ssh-agent
+    Agent,
+}
+
+// 连接成功后返回的信息
+// Information returned after connecting
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct ConnectInfo {
+    // 协商的 TLS 状态, SQLite 或无法获取时为 None
+    // Negotiated TLS state, None for SQLite or when it can't be read
+    #[serde(rename = "tls")]
+    pub tls: Option<TlsState>,
+}
+
+// 测试连接返回的服务端信息
+// Server information returned by the connection test
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct ServerInfo {
+    // 一次往返的耗时, 单位毫秒
+    // Time of one round trip in milliseconds
+    #[serde(rename = "latencyMs")]
+    pub latency_ms: f64,
+
+    #[serde(rename = "version")]
+    pub version: String,
+
+    #[serde(rename = "user")]
+    pub user: Option<String>,
+
+    #[serde(rename = "database")]
+    pub database: Option<String>,
+
+    // 服务端字符集, 例如 UTF8 / utf8mb4
+    // Server character set, e.g. UTF8 / utf8mb4
+    #[serde(rename = "encoding")]
+    pub encoding: Option<String>,
+
+    #[serde(rename = "timeZone")]
+    pub time_zone: Option<String>,
+
+    #[serde(rename = "tls")]
+    pub tls: Option<TlsState>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct TlsState {
+    #[serde(rename = "enabled")]
+    pub enabled: bool,
+
+    // 例如 TLSv1.3 | e.g. TLSv1.3
+    #[serde(rename = "version")]
+    pub version: Option<String>,
+
+    #[serde(rename = "cipher")]
+    pub cipher: Option<String>,
+}
+
+// 连接的设置, 连接时由前端传入
+// Connection settings, passed by the frontend on connect
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct ConnSettings {
+    // 默认的语句超时, 单位毫秒, 0 表示不限制, 为空时沿用服务端的设置
+    // 由服务端执行: Postgres 的 statement_timeout, MySQL 的 max_execution_time (只对 SELECT 生效)
+    // Default statement timeout in milliseconds, 0 means no limit, empty keeps the server's setting
+    // Enforced by the server: statement_timeout on Postgres, max_execution_time on MySQL (SELECT only)
+    #[serde(rename = "statementTimeoutMs")]
+    pub statement_timeout_ms: Option<u64>,
+
+    // 是否使用预处理语句, 默认使用
+    // PgBouncer 的 transaction 模式下需要关闭, 关闭后语句通过文本协议发送
+    // Whether to use prepared statements, enabled by default
+    // Must be disabled behind PgBouncer in transaction mode, statements are then sent over the text protocol
+    #[serde(rename = "preparedStatements")]
+    pub prepared_statements: Option<bool>,
+
+    // 连接池每打开一个连接都执行的 SQL, 可以包含多条语句
+    // 例如 SET search_path / SET TIME ZONE / SET sql_mode / PRAGMA foreign_keys=ON / SET ROLE
+    // SQL run on every connection the pool opens, may contain several statements
+    // e.g. SET search_path / SET TIME ZONE / SET sql_mode / PRAGMA foreign_keys=ON / SET ROLE
+    #[serde(rename = "initSql")]
+    pub init_sql: Option<String>,
+
+    // 只读连接, Postgres/MySQL 以只读事务开始会话, SQLite 以 mode=ro 打开
+    // exec 和 execMany 在发送到服务端前拒绝修改数据或结构的语句
+    // Read-only connection, Postgres/MySQL sessions start as read-only transactions, SQLite opens with mode=ro
+    // exec and execMany reject statements that modify data or schema before they reach the server
+    #[serde(rename = "readOnly")]
+    pub read_only: Option<bool>,
+
+    #[serde(rename = "pool", default)]
+    pub pool: PoolSettings,
+}
+
+impl ConnSettings {
+    pub fn use_prepared(&self) -> bool {
+        self.prepared_statements.unwrap_or(true)
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only.unwrap_or(false)
+    }
+
+    pub fn init_sql(&self) -> Option<&str> {
+        self.init_sql
+            .as_deref()
+            .filter(|sql| !sql.trim().is_empty())
+    }
+}
+
+// 连接池的设置, 为空时使用默认值
+// 超时和生存时间的单位都是毫秒, idleTimeoutMs 和 maxLifetimeMs 为 0 表示不限制
+// Pool settings, defaults are used for empty values
+// Timeouts and lifetimes are in milliseconds, 0 for idleTimeoutMs and maxLifetimeMs means no limit
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct PoolSettings {
+    #[serde(rename = "minConnections")]
+    pub min_connections: Option<u32>,
+
+    #[serde(rename = "maxConnections")]
+    pub max_connections: Option<u32>,
+
+    #[serde(rename = "acquireTimeoutMs")]
+    pub acquire_timeout_ms: Option<u64>,
+
+    #[serde(rename = "idleTimeoutMs")]
+    pub idle_timeout_ms: Option<u64>,
+
+    #[serde(rename = "maxLifetimeMs")]
+    pub max_lifetime_ms: Option<u64>,
+
+    // 从连接池取出连接前先检查连接是否可用
+    // Check that the connection is alive before taking it out of the pool
+    #[serde(rename = "testBeforeAcquire")]
+    pub test_before_acquire: Option<bool>,
+}
+
+// 已登记的连接, 不包含连接参数, 避免把密码发回前端
+// A registered connection, without the parameters so the password isn't sent back to the frontend
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ConnSummary {
+    #[serde(rename = "name")]
+    pub name: String,
+
+    #[serde(rename = "engine")]
+    pub engine: DbType,
+
+    #[serde(rename = "serverVersion")]
+    pub server_version: Option<String>,
+
+    // Unix 毫秒时间戳 | Unix timestamp in milliseconds
+    #[serde(rename = "connectedAt")]
+    pub connected_at: u64,
+
+    #[serde(rename = "pool")]
+    pub pool: PoolStats,
+}
+
+// 连接池的连接数
+// Connection counts of the pool
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct PoolStats {
+    // 已打开的连接数, 包括空闲和使用中的
+    // Open connections, both idle and in use
+    #[serde(rename = "size")]
+    pub size: u32,
+
+    #[serde(rename = "idle")]
+    pub idle: u32,
+
+    // 被查询, 会话或游标占用的连接数
+    // Connections held by queries, sessions or cursors
+    #[serde(rename = "inUse")]
+    pub in_use: u32,
+}
+
+// 连接的健康状态
+// Health status of a connection
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthStatus {
+    // 检查失败, 开始重连 | The check failed, reconnecting starts
+    Down,
+    // 重连失败, 等待下次重试 | Reconnecting failed, waiting for the next retry
+    Reconnecting,
+    // 重连成功 | Reconnected
+    Up,
+}
+
+// 健康检查发给前端的事件
+// Event sent to the frontend by the health check
+#[derive(Serialize, Debug, Clone)]
+pub struct HealthEvent {
+    #[serde(rename = "connName")]
+    pub conn_name: String,
+
+    #[serde(rename = "status")]
+    pub status: HealthStatus,
+
+    // 已经重连失败的次数 | Number of failed reconnect attempts
+    #[serde(rename = "attempts")]
+    pub attempts: u32,
+
+    // 距离下次重连的毫秒数 | Milliseconds until the next reconnect attempt
+    #[serde(rename = "retryInMs")]
+    pub retry_in_ms: Option<u64>,
+
+    #[serde(rename = "error")]
+    pub error: Option<DbError>,
+}
+
+// 查询结果中的一列
+// A column of the query result
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct ColumnInfo {
+    #[serde(rename = "name")]
+    pub name: String,
+
+    // 数据库声明的类型名
+    // Type name declared by the database
+    #[serde(rename = "typeName")]
+    pub type_name: String,
+
+    // 是否可为空, 数据库无法判断时为 None
+    // Whether it is nullable, None when the database can't tell
+    #[serde(rename = "nullable")]
+    pub nullable: Option<bool>,
+
+    // 列的序号, 从 0 开始
+    // Column ordinal, starting from 0
+    #[serde(rename = "ordinal")]
+    pub ordinal: usize,
 }
 
 // AES-GCM 加密解密的结果, 第一项为结果, 第二项为错误消息
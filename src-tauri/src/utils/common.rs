@@ -5,12 +5,12 @@ use colored::*;
 pub fn print_sql(content: &str, item_code: i8) {
     let mut header_text = "Executed SQL start";
 
-    if item_code == 1 && content != "" {
+    if item_code == 1 && !content.is_empty() {
         header_text = content;
     }
 
     let mut footer_text = "Executed SQL end";
-    if item_code == 4 && content != "" {
+    if item_code == 4 && !content.is_empty() {
         footer_text = content;
     }
 
@@ -3,8 +3,22 @@ pub mod fs;
 // pub mod password;
 mod common;
 pub mod sha;
+pub mod sqlx_cancel;
 pub mod sqlx_common;
+pub mod sqlx_config;
+pub mod sqlx_cursor;
+pub mod sqlx_error;
+pub mod sqlx_guard;
+pub mod sqlx_health;
 pub mod sqlx_mysql;
 pub mod sqlx_pg;
+pub mod sqlx_pg_array;
+pub mod sqlx_pg_custom;
+pub mod sqlx_pg_geometry;
+pub mod sqlx_pg_range;
+pub mod sqlx_pg_tsearch;
+pub mod sqlx_pg_value;
 pub mod sqlx_public;
+pub mod sqlx_session;
 pub mod sqlx_sqlite;
+pub mod sqlx_tunnel;
@@ -0,0 +1,463 @@
+use super::{
+    sqlx_common::{DbConnection, DbPool, DbType, PooledConn},
+    sqlx_error::{DbError, DbErrorCode},
+    sqlx_session::SessionConn,
+};
+use crate::types::ConnSettings;
+use once_cell::sync::Lazy;
+use sqlx::mysql::MySqlConnection;
+use sqlx::postgres::PgConnection;
+use sqlx::{Connection, Executor, Row};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex as AsyncMutex;
+
+// SQLite 进度回调的调用间隔, 单位是虚拟机指令数
+// Interval of the SQLite progress callback, in virtual machine instructions
+const SQLITE_PROGRESS_OPS: i32 = 1000;
+
+// 语句超时的错误: Postgres 的 query_canceled, MySQL 的 ER_QUERY_TIMEOUT, MariaDB 的 ER_STATEMENT_TIMEOUT
+// Statement timeout errors: query_canceled on Postgres, ER_QUERY_TIMEOUT on MySQL, ER_STATEMENT_TIMEOUT on MariaDB
+const PG_QUERY_CANCELED: &str = "57014";
+const MYSQL_QUERY_TIMEOUT: u16 = 3024;
+const MARIADB_STATEMENT_TIMEOUT: u16 = 1969;
+
+// Postgres 事务已中止, 除了 ROLLBACK 以外的语句都会失败
+// The Postgres transaction is aborted, every statement other than ROLLBACK fails
+const PG_IN_FAILED_TRANSACTION: &str = "25P02";
+
+// 服务端用来识别连接的 id
+// Id the server uses to identify the connection
+#[derive(Clone, Copy)]
+pub enum BackendId {
+    // pg_backend_pid()
+    Postgres(i32),
+    // CONNECTION_ID()
+    MySql(u64),
+    // SQLite 在进程内运行, 通过进度回调检查取消标记
+    // SQLite runs in process, the progress callback checks the cancel flag
+    Sqlite,
+}
+
+/// 查询连接在服务端的 id, 取消语句时用它找到连接
+/// Look up the id the server uses for the connection, cancelling a statement uses it to find the connection
+///
+/// 会话打开时查询一次并一直保留, 从连接池临时取出的连接在语句需要取消时才查询
+/// Sessions look it up once when opened and keep it, connections taken from the pool
+/// only look it up when the statement can be cancelled
+///
+/// # 参数
+/// - `conn`: 要查询的连接, 不能在已中止的事务中 | Connection to look up, must not be in an aborted transaction
+///
+pub async fn backend_id(conn: &mut PooledConn) -> Result<BackendId, DbError> {
+    // 用文本协议查询, 关闭预处理语句时也能使用
+    // Query over the text protocol, so it also works with prepared statements disabled
+    Ok(match conn {
+        PooledConn::Postgres(conn) => {
+            let row = conn.fetch_one("SELECT pg_backend_pid()").await?;
+            BackendId::Postgres(row.try_get(0)?)
+        }
+        PooledConn::MySql(conn) => {
+            let row = conn.fetch_one("SELECT CONNECTION_ID()").await?;
+            BackendId::MySql(row.try_get(0)?)
+        }
+        PooledConn::Sqlite(_) => BackendId::Sqlite,
+    })
+}
+
+/// 设置语句超时的 SQL, 由服务端执行超时 | SQL setting the statement timeout, which the server enforces
+///
+/// MySQL 的 max_execution_time 只对只读的 SELECT 生效, MariaDB 使用单位为秒的 max_statement_time,
+/// 按 VERSION() 选择变量后通过 PREPARE 执行, 两者都能在一次往返中设置
+///
+/// max_execution_time of MySQL only applies to read-only SELECT statements, MariaDB uses max_statement_time in seconds,
+/// the variable is picked by VERSION() and set through PREPARE, so both are set in one round trip
+///
+/// # 参数
+/// - `db_type`: 数据库类型, 不能是 SQLite | Database type, must not be SQLite
+/// - `timeout_ms`: 超时毫秒数, 0 表示不限制, None 恢复服务端的默认值 | Timeout in milliseconds, 0 means no limit, None restores the server default
+///
+pub fn timeout_sql(db_type: DbType, timeout_ms: Option<u64>) -> String {
+    match db_type {
+        DbType::Postgres => match timeout_ms {
+            Some(ms) => format!("SET statement_timeout = {}", ms),
+            None => "SET statement_timeout = DEFAULT".to_string(),
+        },
+        _ => {
+            let (mariadb, mysql) = match timeout_ms {
+                Some(ms) => (format!("{}.{:03}", ms / 1000, ms % 1000), ms.to_string()),
+                None => ("DEFAULT".to_string(), "DEFAULT".to_string()),
+            };
+            format!(
+                "SET @dibim_timeout = IF(VERSION() LIKE '%MariaDB%', \
+                 'SET SESSION max_statement_time = {}', 'SET SESSION max_execution_time = {}');\n\
+                 PREPARE dibim_timeout FROM @dibim_timeout;\n\
+                 EXECUTE dibim_timeout;\n\
+                 DEALLOCATE PREPARE dibim_timeout;\n\
+                 SET @dibim_timeout = NULL",
+                mariadb, mysql
+            )
+        }
+    }
+}
+
+struct RunningStatement {
+    conn_name: String,
+    // 关闭预处理语句时为 None, 连接可能经过 PgBouncer 这样的代理, 查到的 id 不一定是执行语句的连接
+    // None when prepared statements are disabled, the connection may go through a proxy such as PgBouncer
+    // and the id looked up isn't necessarily the connection that runs the statement
+    backend: Option<BackendId>,
+    cancelled: Arc<AtomicBool>,
+    // cancel 发送取消请求期间持有, finish 也要拿到它才能注销语句,
+    // 避免语句已经结束, 连接被别的语句复用后才收到取消请求
+    // Held by cancel while sending the cancel request, finish also takes it before unregistering,
+    // so the request can't land after the statement ended and the connection was reused
+    cancel_lock: Arc<AsyncMutex<()>>,
+}
+
+// 正在执行的语句: 语句 id -> 语句
+// 临界区内没有 await, 使用标准库的锁, 以便在 Drop 中移除
+// Running statements: statement id -> statement
+// No await inside the critical sections, so the std lock is used and entries can be removed in Drop
+static RUNNING: Lazy<Mutex<HashMap<String, RunningStatement>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+// 正在执行的语句的登记, 丢弃时移除
+// Registration of a running statement, removed when dropped
+pub struct RunningGuard {
+    conn_name: String,
+    statement_id: Option<String>,
+    // 生效的超时, 用于错误消息 | The timeout in effect, used in the error message
+    timeout_ms: Option<u64>,
+    // 语句结束后恢复连接默认超时的 SQL | SQL restoring the connection's default timeout after the statement
+    restore: Option<String>,
+    cancelled: Arc<AtomicBool>,
+    // SQLite 的进度回调发现超时后设置 | Set by the SQLite progress callback once the timeout passes
+    timed_out: Arc<AtomicBool>,
+    cancel_lock: Arc<AsyncMutex<()>>,
+    progress_handler: bool,
+}
+
+/// 登记即将执行的语句, 之后可以通过 `cancel` 取消, 并设置它的超时
+/// Register a statement about to run, so it can be cancelled by `cancel`, and set its timeout
+///
+/// Postgres 和 MySQL 的超时由服务端执行, 连接的默认值在建立连接时设置,
+/// 传入的超时和默认值不同时才在语句前后各多一次往返; SQLite 在进度回调中检查
+///
+/// The timeout is enforced by the server on Postgres and MySQL, the connection default is set when connecting,
+/// only a timeout different from the default costs a round trip before and after the statement;
+/// SQLite checks it in the progress callback
+///
+/// # 参数
+/// - `conn_name`: 连接名 | Connection name
+/// - `statement_id`: 前端生成的语句 id, None 表示不需要取消 | Statement id generated by the frontend, None when cancelling isn't needed
+/// - `timeout_ms`: 语句超时, None 使用连接的默认值, 0 表示不限制 | Statement timeout, None uses the connection default, 0 means no limit
+/// - `settings`: 连接的设置 | Settings of the connection
+/// - `target`: 执行语句的连接 | Connection that runs the statement
+///
+pub async fn track(
+    conn_name: &str,
+    statement_id: Option<&str>,
+    timeout_ms: Option<u64>,
+    settings: &ConnSettings,
+    target: &mut SessionConn,
+) -> Result<RunningGuard, DbError> {
+    let mut guard = RunningGuard {
+        conn_name: conn_name.to_string(),
+        statement_id: None,
+        timeout_ms: timeout_ms
+            .or(settings.statement_timeout_ms)
+            .filter(|&ms| ms > 0),
+        restore: None,
+        cancelled: Arc::new(AtomicBool::new(false)),
+        timed_out: Arc::new(AtomicBool::new(false)),
+        cancel_lock: Arc::new(AsyncMutex::new(())),
+        progress_handler: false,
+    };
+
+    if let Some(id) = statement_id {
+        if RUNNING.lock().unwrap().contains_key(id) {
+            return Err(DbError::new(
+                DbErrorCode::InvalidArgument,
+                format!("Statement '{}' is already running", id),
+            ));
+        }
+    }
+
+    let db_type = target.conn().db_type();
+    if db_type == DbType::Sqlite {
+        if statement_id.is_some() || guard.timeout_ms.is_some() {
+            set_progress_handler(&mut guard, target.conn()).await?;
+        }
+    } else if timeout_ms.is_some() && timeout_ms != settings.statement_timeout_ms {
+        let sql = timeout_sql(db_type, timeout_ms);
+        match target.conn().execute_raw(&sql).await.map_err(DbError::from) {
+            Ok(()) => guard.restore = Some(timeout_sql(db_type, settings.statement_timeout_ms)),
+            // 事务已中止时 SET 也会失败, 这时能执行的只有 ROLLBACK, 不设置超时直接执行
+            // SET fails too when the transaction is aborted, only ROLLBACK can run then, so run it without a timeout
+            Err(e) if e.details.sql_state.as_deref() == Some(PG_IN_FAILED_TRANSACTION) => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    if let Some(id) = statement_id {
+        let backend = match db_type {
+            DbType::Sqlite => Some(BackendId::Sqlite),
+            _ if !settings.use_prepared() => None,
+            _ => Some(target.backend_id().await?),
+        };
+        RUNNING.lock().unwrap().insert(
+            id.to_string(),
+            RunningStatement {
+                conn_name: conn_name.to_string(),
+                backend,
+                cancelled: Arc::clone(&guard.cancelled),
+                cancel_lock: Arc::clone(&guard.cancel_lock),
+            },
+        );
+        guard.statement_id = Some(id.to_string());
+    }
+
+    Ok(guard)
+}
+
+// 回调返回 false 时 SQLite 中断当前语句
+// SQLite interrupts the current statement when the callback returns false
+async fn set_progress_handler(
+    guard: &mut RunningGuard,
+    conn: &mut PooledConn,
+) -> Result<(), DbError> {
+    let PooledConn::Sqlite(conn) = conn else {
+        return Ok(());
+    };
+
+    let cancelled = Arc::clone(&guard.cancelled);
+    let timed_out = Arc::clone(&guard.timed_out);
+    let deadline = guard
+        .timeout_ms
+        .map(|ms| Instant::now() + Duration::from_millis(ms));
+    conn.lock_handle()
+        .await?
+        .set_progress_handler(SQLITE_PROGRESS_OPS, move || {
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                timed_out.store(true, Ordering::Relaxed);
+            }
+            !cancelled.load(Ordering::Relaxed) && !timed_out.load(Ordering::Relaxed)
+        });
+    guard.progress_handler = true;
+    Ok(())
+}
+
+impl RunningGuard {
+    // 取消标记, 执行多条语句时在每条语句之前检查, 取消请求落在两条语句之间时也能停下
+    // The cancel flag, checked before each statement of a batch, so the batch also stops
+    // when the cancel request lands between two statements
+    pub fn cancel_flag(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.cancelled)
+    }
+
+    // 语句结束后调用, 恢复连接的默认超时, 被取消或超时的语句返回对应的错误码
+    // Called after the statement finishes, restores the connection's default timeout,
+    // cancelled or timed out statements get the matching error code
+    pub async fn finish<T>(
+        mut self,
+        target: &mut SessionConn,
+        res: Result<T, DbError>,
+    ) -> Result<T, DbError> {
+        // 等正在发送的取消请求结束后再注销, 连接随后才会放回连接池
+        // Unregister after an in-flight cancel request is done, the connection only returns to the pool afterwards
+        if let Some(id) = self.statement_id.take() {
+            let _held = self.cancel_lock.lock().await;
+            RUNNING.lock().unwrap().remove(&id);
+        }
+
+        // 语句让事务中止时恢复会失败, 事务回滚时 SET 也会一起撤销
+        // Restoring fails when the statement aborted the transaction, the SET is undone when it rolls back
+        if let Some(sql) = self.restore.take() {
+            if let Err(e) = target.conn().execute_raw(&sql).await {
+                eprintln!("Failed to restore the statement timeout: {:?}", e);
+            }
+        }
+
+        if self.progress_handler {
+            if let PooledConn::Sqlite(conn) = target.conn() {
+                if let Ok(mut handle) = conn.lock_handle().await {
+                    handle.remove_progress_handler();
+                }
+            }
+        }
+
+        match res {
+            Err(mut e) if self.cancelled.load(Ordering::Relaxed) => {
+                e.code = DbErrorCode::Cancelled;
+                Err(e)
+            }
+            Err(e) if self.timed_out.load(Ordering::Relaxed) || is_timeout(&e) => {
+                Err(self.timeout_error(e))
+            }
+            res => res,
+        }
+    }
+
+    fn timeout_error(&self, mut err: DbError) -> DbError {
+        err.code = DbErrorCode::Timeout;
+        if let Some(ms) = self.timeout_ms {
+            err.message = format!("Statement timed out after {} ms", ms);
+        }
+        err
+    }
+}
+
+// 服务端因超时取消了语句 | The server cancelled the statement because it timed out
+fn is_timeout(err: &DbError) -> bool {
+    err.details.sql_state.as_deref() == Some(PG_QUERY_CANCELED)
+        || matches!(
+            err.details.vendor_code,
+            Some(MYSQL_QUERY_TIMEOUT | MARIADB_STATEMENT_TIMEOUT)
+        )
+}
+
+impl Drop for RunningGuard {
+    fn drop(&mut self) {
+        if let Some(id) = &self.statement_id {
+            RUNNING.lock().unwrap().remove(id);
+        }
+    }
+}
+
+/// 取消正在执行的语句 | Cancel a running statement
+///
+/// 语句已经结束或不存在时返回 false, 连接关闭了预处理语句时不能取消, 返回错误
+/// Returns false when the statement has finished or doesn't exist,
+/// returns an error on connections with prepared statements disabled, which can't cancel
+///
+/// # 参数
+/// - `conn_name`: 连接名 | Connection name
+/// - `statement_id`: 执行语句时传入的 id | The id passed when running the statement
+///
+pub async fn cancel(conn_name: &str, statement_id: &str) -> Result<bool, DbError> {
+    let (cancel_lock, backend) =
+        match RUNNING.lock().unwrap().get(statement_id) {
+            Some(stmt) if stmt.conn_name == conn_name => match stmt.backend {
+                Some(backend) => (Arc::clone(&stmt.cancel_lock), backend),
+                None => return Err(DbError::new(
+                    DbErrorCode::InvalidState,
+                    "Cancelling needs prepared statements, the connection may go through a pooler \
+                     such as PgBouncer where the server connection isn't known",
+                )),
+            },
+            _ => return Ok(false),
+        };
+
+    // 拿到锁之后语句可能已经结束, 需要重新检查
+    // The statement may have finished while waiting for the lock, so check again
+    let _held = cancel_lock.lock().await;
+    match RUNNING.lock().unwrap().get(statement_id) {
+        Some(stmt) if Arc::ptr_eq(&stmt.cancel_lock, &cancel_lock) => {
+            stmt.cancelled.store(true, Ordering::Relaxed);
+        }
+        _ => return Ok(false),
+    }
+
+    cancel_backend(conn_name, backend).await?;
+    Ok(true)
+}
+
+// 重命名连接时更新正在执行的语句所属的连接
+// Update the connection of the running statements when the connection is renamed
+pub fn rename_running(conn_name: &str, new_name: &str) {
+    for stmt in RUNNING.lock().unwrap().values_mut() {
+        if stmt.conn_name == conn_name {
+            stmt.conn_name = new_name.to_string();
+        }
+    }
+}
+
+// 请求服务端取消连接上正在执行的语句, SQLite 只需设置取消标记
+// Ask the server to cancel the statement running on the connection, SQLite only needs the cancel flag
+async fn cancel_backend(conn_name: &str, backend: BackendId) -> Result<(), DbError> {
+    let db_conn = DbPool::global()
+        .get(conn_name)
+        .await
+        .ok_or_else(|| DbError::connection_not_found(conn_name))?;
+
+    // 连接池可能已经用满, 所以用单独建立的连接发送取消请求
+    // The pool may be exhausted, so the cancel request is sent over a separately opened connection
+    match (backend, db_conn) {
+        (BackendId::Postgres(pid), DbConnection::Postgres(pool)) => {
+            let mut side = PgConnection::connect_with(&pool.connect_options()).await?;
+            sqlx::query("SELECT pg_cancel_backend($1)")
+                .bind(pid)
+                .execute(&mut side)
+                .await?;
+            side.close().await?;
+        }
+        (BackendId::MySql(id), DbConnection::MySql(pool)) => {
+            let mut side = MySqlConnection::connect_with(&pool.connect_options()).await?;
+            side.execute(format!("KILL QUERY {}", id).as_str()).await?;
+            side.close().await?;
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::sqlx_config::DbConnectOptions;
+    use sqlx::sqlite::SqliteConnectOptions;
+    use std::str::FromStr;
+
+    #[test]
+    fn timeout_sql_per_engine() {
+        assert_eq!(
+            timeout_sql(DbType::Postgres, Some(1500)),
+            "SET statement_timeout = 1500"
+        );
+        assert_eq!(
+            timeout_sql(DbType::Postgres, None),
+            "SET statement_timeout = DEFAULT"
+        );
+
+        // MariaDB 的单位是秒 | MariaDB counts in seconds
+        let sql = timeout_sql(DbType::MySql, Some(1500));
+        assert!(sql.contains("'SET SESSION max_statement_time = 1.500'"));
+        assert!(sql.contains("'SET SESSION max_execution_time = 1500'"));
+        assert!(timeout_sql(DbType::MySql, None).contains("max_execution_time = DEFAULT"));
+    }
+
+    #[tokio::test]
+    async fn sqlite_statement_times_out_in_the_progress_handler() {
+        let options = SqliteConnectOptions::from_str("sqlite::memory:").unwrap();
+        let settings: ConnSettings = serde_json::from_str(r#"{"statementTimeoutMs":50}"#).unwrap();
+        let db_conn = DbConnection::open(DbConnectOptions::Sqlite(options), &settings)
+            .await
+            .unwrap();
+        let mut target = SessionConn::Pooled(db_conn.acquire().await.unwrap());
+
+        // 没有终止条件的递归查询 | A recursive query without a stop condition
+        let endless = "WITH RECURSIVE c(x) AS (SELECT 1 UNION ALL SELECT x + 1 FROM c) \
+                       SELECT count(*) FROM c";
+        let running = track("sqlite-timeout", None, None, &settings, &mut target)
+            .await
+            .unwrap();
+        let res = target
+            .conn()
+            .execute_raw(endless)
+            .await
+            .map_err(DbError::from);
+        let err = running.finish(&mut target, res).await.unwrap_err();
+        assert_eq!(err.code, DbErrorCode::Timeout);
+        assert_eq!(err.message, "Statement timed out after 50 ms");
+
+        // 进度回调已经移除 | The progress handler has been removed
+        target.conn().execute_raw("SELECT 1").await.unwrap();
+
+        drop(target);
+        db_conn.close().await;
+    }
+}
@@ -1,11 +1,28 @@
+use crate::types::{ColumnInfo, ConnConfig, ConnSettings, ConnSummary, PoolSettings, PoolStats};
+use crate::utils::sqlx_cancel::timeout_sql;
+use crate::utils::sqlx_config::DbConnectOptions;
+use crate::utils::sqlx_error::{DbError, DbErrorCode};
+use futures_util::{future::join_all, Stream, StreamExt};
 use once_cell::sync::Lazy;
-use sqlx::mysql::{MySqlPool, MySqlPoolOptions};
-use sqlx::postgres::{PgPool, PgPoolOptions};
-use sqlx::sqlite::{SqlitePool, SqlitePoolOptions};
+use serde::{Deserialize, Serialize};
+use sqlx::mysql::MySqlPool;
+use sqlx::pool::{PoolConnection, PoolOptions};
+use sqlx::postgres::PgPool;
+use sqlx::sqlite::SqlitePool;
+use sqlx::{
+    Column, Connection, Database, Describe, Executor, MySql, Postgres, Row, Sqlite, TypeInfo,
+};
 use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use tokio::sync::Mutex;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+// 未设置时连接池的最大连接数
+// Maximum pool connections when not set
+const DEFAULT_MAX_CONNECTIONS: u32 = 5;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
 pub enum DbType {
     Postgres,
     MySql,
@@ -19,10 +36,257 @@ pub enum DbConnection {
     Sqlite(SqlitePool),
 }
 
+impl DbConnection {
+    pub fn db_type(&self) -> DbType {
+        match self {
+            DbConnection::Postgres(_) => DbType::Postgres,
+            DbConnection::MySql(_) => DbType::MySql,
+            DbConnection::Sqlite(_) => DbType::Sqlite,
+        }
+    }
+
+    // 按设置创建连接池, 不登记名称
+    // Create the pool with the settings, without recording a name
+    pub async fn open(options: DbConnectOptions, settings: &ConnSettings) -> Result<Self, DbError> {
+        let pool_options = &settings.pool;
+        // 不使用预处理语句时也关闭语句缓存
+        // Also disable the statement cache when prepared statements are not used
+        let no_cache = !settings.use_prepared();
+        let read_only = settings.is_read_only();
+        // 默认的语句超时由服务端执行, SQLite 在 sqlx_cancel 中通过进度回调实现
+        // The default statement timeout is enforced by the server, SQLite uses the progress callback in sqlx_cancel
+        let timeout = settings.statement_timeout_ms;
+
+        Ok(match options {
+            DbConnectOptions::Postgres(mut options) => {
+                if no_cache {
+                    options = options.statement_cache_capacity(0);
+                }
+                let enforced = [
+                    timeout.map(|ms| timeout_sql(DbType::Postgres, Some(ms))),
+                    read_only.then(|| {
+                        "SET SESSION CHARACTERISTICS AS TRANSACTION READ ONLY".to_string()
+                    }),
+                ];
+                let pool_options = after_connect(
+                    pool_options_for::<Postgres>(pool_options)?,
+                    connect_sql(settings.init_sql(), &enforced),
+                );
+                let pool = pool_options.connect_with(options).await?;
+                DbConnection::Postgres(pool)
+            }
+            DbConnectOptions::MySql(mut options) => {
+                if no_cache {
+                    options = options.statement_cache_capacity(0);
+                }
+                let enforced = [
+                    timeout.map(|ms| timeout_sql(DbType::MySql, Some(ms))),
+                    read_only.then(|| "SET SESSION TRANSACTION READ ONLY".to_string()),
+                ];
+                let pool_options = after_connect(
+                    pool_options_for::<MySql>(pool_options)?,
+                    connect_sql(settings.init_sql(), &enforced),
+                );
+                let pool = pool_options.connect_with(options).await?;
+                DbConnection::MySql(pool)
+            }
+            DbConnectOptions::Sqlite(mut options) => {
+                if no_cache {
+                    options = options.statement_cache_capacity(0);
+                }
+                // 以 mode=ro 打开数据库文件 | Open the database file with mode=ro
+                options = options.read_only(read_only);
+                let pool_options = after_connect(
+                    pool_options_for::<Sqlite>(pool_options)?,
+                    connect_sql(settings.init_sql(), &[]),
+                );
+                let pool = pool_options.connect_with(options).await?;
+                DbConnection::Sqlite(pool)
+            }
+        })
+    }
+
+    // 关闭连接池, 等待所有连接放回
+    // Close the pool, waits for all connections to be returned
+    pub async fn close(&self) {
+        match self {
+            DbConnection::Postgres(pool) => pool.close().await,
+            DbConnection::MySql(pool) => pool.close().await,
+            DbConnection::Sqlite(pool) => {
+                // 把 WAL 文件写回数据库并清空, 连接都在使用中时跳过
+                // Write the WAL file back into the database and truncate it, skipped when all connections are in use
+                if let Some(mut conn) = pool.try_acquire() {
+                    let _ = conn.execute("PRAGMA wal_checkpoint(TRUNCATE)").await;
+                }
+                pool.close().await
+            }
+        }
+    }
+
+    // 连接池当前的连接数 | Current connection counts of the pool
+    pub fn stats(&self) -> PoolStats {
+        let (size, idle) = match self {
+            DbConnection::Postgres(pool) => (pool.size(), pool.num_idle()),
+            DbConnection::MySql(pool) => (pool.size(), pool.num_idle()),
+            DbConnection::Sqlite(pool) => (pool.size(), pool.num_idle()),
+        };
+        let idle = idle as u32;
+
+        PoolStats {
+            size,
+            idle,
+            in_use: size.saturating_sub(idle),
+        }
+    }
+
+    // 连接都在使用中, 再取连接需要等待
+    // All connections are in use, taking another one would have to wait
+    pub fn is_saturated(&self) -> bool {
+        let max = match self {
+            DbConnection::Postgres(pool) => pool.options().get_max_connections(),
+            DbConnection::MySql(pool) => pool.options().get_max_connections(),
+            DbConnection::Sqlite(pool) => pool.options().get_max_connections(),
+        };
+        let stats = self.stats();
+        stats.idle == 0 && stats.size >= max
+    }
+
+    // 从连接池取出一个连接, 用完后放回
+    // Check a connection out of the pool, it is returned when dropped
+    pub async fn acquire(&self) -> Result<PooledConn, sqlx::Error> {
+        Ok(match self {
+            DbConnection::Postgres(pool) => PooledConn::Postgres(pool.acquire().await?),
+            DbConnection::MySql(pool) => PooledConn::MySql(pool.acquire().await?),
+            DbConnection::Sqlite(pool) => PooledConn::Sqlite(pool.acquire().await?),
+        })
+    }
+}
+
+// 从连接池取出的单个连接
+// A single connection checked out of the pool
+pub enum PooledConn {
+    Postgres(PoolConnection<Postgres>),
+    MySql(PoolConnection<MySql>),
+    Sqlite(PoolConnection<Sqlite>),
+}
+
+impl PooledConn {
+    pub fn db_type(&self) -> DbType {
+        match self {
+            PooledConn::Postgres(_) => DbType::Postgres,
+            PooledConn::MySql(_) => DbType::MySql,
+            PooledConn::Sqlite(_) => DbType::Sqlite,
+        }
+    }
+
+    // 执行一条非查询语句, 返回影响的行数和最后插入的 id
+    // prepared 为 false 时通过文本协议执行
+    // Execute a non query statement, returns the affected rows and the last insert id
+    // Runs over the text protocol when prepared is false
+    pub async fn execute(
+        &mut self,
+        sql: &str,
+        prepared: bool,
+    ) -> Result<(u64, Option<u64>), sqlx::Error> {
+        Ok(match self {
+            PooledConn::Postgres(conn) => {
+                let result = if prepared {
+                    sqlx::query(sql).execute(&mut **conn).await?
+                } else {
+                    conn.execute(sql).await?
+                };
+                // PostgreSQL 需要 RETURNING 子句
+                (result.rows_affected(), None)
+            }
+            PooledConn::MySql(conn) => {
+                let result = if prepared {
+                    sqlx::query(sql).execute(&mut **conn).await?
+                } else {
+                    conn.execute(sql).await?
+                };
+                (result.rows_affected(), Some(result.last_insert_id()))
+            }
+            PooledConn::Sqlite(conn) => {
+                let result = if prepared {
+                    sqlx::query(sql).execute(&mut **conn).await?
+                } else {
+                    conn.execute(sql).await?
+                };
+                (
+                    result.rows_affected(),
+                    Some(result.last_insert_rowid() as u64),
+                )
+            }
+        })
+    }
+
+    // 用文本协议执行语句, 用于 BEGIN / SAVEPOINT 等不能预处理的事务控制语句
+    // Execute the statement over the text protocol, used for transaction control statements
+    // such as BEGIN / SAVEPOINT that can't be prepared
+    pub async fn execute_raw(&mut self, sql: &str) -> Result<(), sqlx::Error> {
+        // 不带参数的 &str 走文本协议 | A &str without arguments runs over the text protocol
+        match self {
+            PooledConn::Postgres(conn) => {
+                conn.execute(sql).await?;
+            }
+            PooledConn::MySql(conn) => {
+                conn.execute(sql).await?;
+            }
+            PooledConn::Sqlite(conn) => {
+                conn.execute(sql).await?;
+            }
+        }
+        Ok(())
+    }
+
+    // 与服务端往返一次, 用于测量延迟和检查连接是否可用
+    // One round trip to the server, used to measure latency and check the connection is alive
+    pub async fn ping(&mut self) -> Result<(), sqlx::Error> {
+        match self {
+            PooledConn::Postgres(conn) => conn.ping().await,
+            PooledConn::MySql(conn) => conn.ping().await,
+            PooledConn::Sqlite(conn) => conn.ping().await,
+        }
+    }
+
+    // 丢弃时关闭连接而不是放回连接池, 用于在同步的 Drop 中处理状态未知的连接
+    // Close the connection on drop instead of returning it to the pool,
+    // for connections found in an unknown state inside a synchronous Drop
+    pub fn close_on_drop(&mut self) {
+        match self {
+            PooledConn::Postgres(conn) => conn.close_on_drop(),
+            PooledConn::MySql(conn) => conn.close_on_drop(),
+            PooledConn::Sqlite(conn) => conn.close_on_drop(),
+        }
+    }
+
+    // 关闭连接而不是放回连接池, 用于状态未知的连接 (例如回滚失败)
+    // Close the connection instead of returning it to the pool, used for connections
+    // in an unknown state (e.g. a failed rollback)
+    pub async fn close(self) {
+        let _ = match self {
+            PooledConn::Postgres(conn) => conn.close().await,
+            PooledConn::MySql(conn) => conn.close().await,
+            PooledConn::Sqlite(conn) => conn.close().await,
+        };
+    }
+}
+
 // 全局连接管理器
 // Global Connection Manager
 pub struct DbPool {
-    connections: Mutex<HashMap<String, DbConnection>>,
+    connections: Mutex<HashMap<String, ConnEntry>>,
+}
+
+// 已登记的连接, 保存连接参数和设置以便重新连接
+// A registered connection, the parameters and settings are kept for reconnecting
+struct ConnEntry {
+    conn: DbConnection,
+    config: ConnConfig,
+    settings: ConnSettings,
+    server_version: Option<String>,
+    // 连接时间, Unix 毫秒时间戳 | Connect time, Unix timestamp in milliseconds
+    connected_at: u64,
 }
 
 impl DbPool {
@@ -39,73 +303,364 @@ impl DbPool {
     // Connect to the database and record the name
     pub async fn connect(
         &self,
-        db_type: DbType,
         name: impl Into<String>,
-        url: &str,
-    ) -> Result<(), sqlx::Error> {
+        options: DbConnectOptions,
+        config: ConnConfig,
+        settings: ConnSettings,
+    ) -> Result<DbConnection, DbError> {
         let name = name.into();
         let mut connections = self.connections.lock().await;
         if connections.contains_key(&name) {
-            return Err(sqlx::Error::Configuration(
-                // 这里的提示文字在前端会匹配字符串, 要统一
-                format!(
-                    "Duplicate connection name: '{}'. Connection names must be unique.",
-                    name
-                )
-                .into(),
-            ));
+            return Err(duplicate_connection(&name));
         }
 
-        let connection = match db_type {
-            DbType::Postgres => {
-                let pool = PgPoolOptions::new().max_connections(5).connect(url).await?;
-                DbConnection::Postgres(pool)
-            }
-            DbType::MySql => {
-                let pool = MySqlPoolOptions::new()
-                    .max_connections(5)
-                    .connect(url)
-                    .await?;
-                DbConnection::MySql(pool)
-            }
-            DbType::Sqlite => {
-                let pool = SqlitePoolOptions::new()
-                    .max_connections(5)
-                    .connect(url)
-                    .await?;
-                DbConnection::Sqlite(pool)
-            }
-        };
+        let connection = DbConnection::open(options, &settings).await?;
+        connections.insert(
+            name,
+            ConnEntry {
+                conn: connection.clone(),
+                config,
+                settings,
+                server_version: None,
+                connected_at: now_ms(),
+            },
+        );
+
+        Ok(connection)
+    }
 
-        connections.insert(name, connection);
+    // 替换连接池, 返回旧的连接池, 连接不存在时返回 None
+    // Replace the pool, returns the old pool, None when the connection doesn't exist
+    pub async fn replace(&self, name: &str, conn: DbConnection) -> Option<DbConnection> {
+        let mut connections = self.connections.lock().await;
+        let entry = connections.get_mut(name)?;
+        entry.connected_at = now_ms();
+        Some(std::mem::replace(&mut entry.conn, conn))
+    }
+
+    // 同时替换连接池, 连接参数和设置, 用于带新参数重新连接, 连接不存在时返回 None
+    // Replace the pool together with the parameters and settings, used for reconnecting with new parameters,
+    // None when the connection doesn't exist
+    pub async fn replace_config(
+        &self,
+        name: &str,
+        conn: DbConnection,
+        config: ConnConfig,
+        settings: ConnSettings,
+    ) -> Option<DbConnection> {
+        let mut connections = self.connections.lock().await;
+        let entry = connections.get_mut(name)?;
+        entry.config = config;
+        entry.settings = settings;
+        entry.server_version = None;
+        entry.connected_at = now_ms();
+        Some(std::mem::replace(&mut entry.conn, conn))
+    }
+
+    // 已登记的连接名 | Names of the registered connections
+    pub async fn names(&self) -> Vec<String> {
+        self.connections.lock().await.keys().cloned().collect()
+    }
 
+    // 连接后查询到的服务端版本
+    // Server version queried after connecting
+    pub async fn set_server_version(&self, name: &str, version: String) {
+        if let Some(entry) = self.connections.lock().await.get_mut(name) {
+            entry.server_version = Some(version);
+        }
+    }
+
+    // 重命名连接, 新名称不能已被使用
+    // Rename the connection, the new name must not be in use
+    pub async fn rename(&self, name: &str, new_name: &str) -> Result<(), DbError> {
+        let mut connections = self.connections.lock().await;
+        if connections.contains_key(new_name) {
+            return Err(duplicate_connection(new_name));
+        }
+
+        let entry = connections
+            .remove(name)
+            .ok_or_else(|| DbError::connection_not_found(name))?;
+        connections.insert(new_name.to_string(), entry);
         Ok(())
     }
 
+    // 已登记的连接, 按名称排序
+    // Registered connections, sorted by name
+    pub async fn list(&self) -> Vec<ConnSummary> {
+        let mut list: Vec<ConnSummary> = self
+            .connections
+            .lock()
+            .await
+            .iter()
+            .map(|(name, entry)| ConnSummary {
+                name: name.clone(),
+                engine: entry.conn.db_type(),
+                server_version: entry.server_version.clone(),
+                connected_at: entry.connected_at,
+                pool: entry.conn.stats(),
+            })
+            .collect();
+        list.sort_by(|a, b| a.name.cmp(&b.name));
+        list
+    }
+
     // 断开指定连接
     // Disconnect the specified connection
     pub async fn disconnect(&self, name: &str) -> bool {
-        if let Some(conn) = self.connections.lock().await.remove(name) {
-            match conn {
-                DbConnection::Postgres(pool) => pool.close().await,
-                DbConnection::MySql(pool) => pool.close().await,
-                DbConnection::Sqlite(pool) => pool.close().await,
-            }
+        if let Some(entry) = self.connections.lock().await.remove(name) {
+            entry.conn.close().await;
             true
         } else {
             false
         }
     }
 
+    // 断开所有连接, 连接池并行关闭, 返回断开的连接数
+    // Disconnect all connections, the pools are closed in parallel, returns the number disconnected
+    pub async fn close_all(&self) -> usize {
+        let entries: Vec<ConnEntry> = self
+            .connections
+            .lock()
+            .await
+            .drain()
+            .map(|(_, entry)| entry)
+            .collect();
+        join_all(entries.iter().map(|entry| entry.conn.close())).await;
+        entries.len()
+    }
+
     // 获取连接（如果存在）
     // Get connection (if it exists)
     pub async fn get(&self, name: &str) -> Option<DbConnection> {
-        self.connections.lock().await.get(name).cloned()
+        self.connections
+            .lock()
+            .await
+            .get(name)
+            .map(|entry| entry.conn.clone())
+    }
+
+    // 获取连接的设置（如果存在）
+    // Get the settings of the connection (if it exists)
+    pub async fn settings(&self, name: &str) -> Option<ConnSettings> {
+        self.connections
+            .lock()
+            .await
+            .get(name)
+            .map(|entry| entry.settings.clone())
+    }
+
+    // 获取连接的参数和设置（如果存在）, 用于重新连接
+    // Get the parameters and settings of the connection (if it exists), used for reconnecting
+    pub async fn config(&self, name: &str) -> Option<(ConnConfig, ConnSettings)> {
+        self.connections
+            .lock()
+            .await
+            .get(name)
+            .map(|entry| (entry.config.clone(), entry.settings.clone()))
     }
 
     // 检查连接是否存在
     // Check if the connection exists
-    // pub async fn contains(&self, name: &str) -> bool {
-    //     self.connections.lock().await.contains_key(name)
-    // }
+    pub async fn contains(&self, name: &str) -> bool {
+        self.connections.lock().await.contains_key(name)
+    }
+}
+
+// Unix 毫秒时间戳 | Unix timestamp in milliseconds
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+// 前端通过错误码 duplicate_connection 判断, 不匹配这里的文字
+// The frontend checks the duplicate_connection error code instead of matching this text
+fn duplicate_connection(name: &str) -> DbError {
+    DbError::new(
+        DbErrorCode::DuplicateConnection,
+        format!(
+            "Duplicate connection name: '{}'. Connection names must be unique.",
+            name
+        ),
+    )
+}
+
+// 根据设置创建连接池选项, 未设置的项使用 sqlx 的默认值
+// Build the pool options from the settings, unset items use the sqlx defaults
+fn pool_options_for<DB: Database>(settings: &PoolSettings) -> Result<PoolOptions<DB>, DbError> {
+    let max_connections = settings.max_connections.unwrap_or(DEFAULT_MAX_CONNECTIONS);
+    let min_connections = settings.min_connections.unwrap_or(0);
+    if max_connections == 0 || min_connections > max_connections {
+        return Err(DbError::new(
+            DbErrorCode::InvalidConfig,
+            format!(
+                "Invalid pool size: min {} / max {}",
+                min_connections, max_connections
+            ),
+        ));
+    }
+
+    let mut options = PoolOptions::<DB>::new()
+        .max_connections(max_connections)
+        .min_connections(min_connections);
+
+    if let Some(ms) = settings.acquire_timeout_ms {
+        options = options.acquire_timeout(Duration::from_millis(ms));
+    }
+    if let Some(ms) = settings.idle_timeout_ms {
+        options = options.idle_timeout((ms > 0).then(|| Duration::from_millis(ms)));
+    }
+    if let Some(ms) = settings.max_lifetime_ms {
+        options = options.max_lifetime((ms > 0).then(|| Duration::from_millis(ms)));
+    }
+    if let Some(test) = settings.test_before_acquire {
+        options = options.test_before_acquire(test);
+    }
+
+    Ok(options)
+}
+
+// 通过 describe 的结果获取查询的列信息, 包括可空性, 结果为空时也能拿到列
+// 部分语句不支持 describe, 这时返回 None
+// 传入结果而不是执行器, 避免 `&mut` 连接上的泛型 future 无法满足 Send
+// Get the column info of the query from the describe result, including nullability, also works for empty results
+// Some statements don't support describe, in which case None is returned
+// Takes the result rather than an executor, generic futures over `&mut` connections can't satisfy Send
+pub fn describe_columns<DB: Database>(
+    describe: Result<Describe<DB>, sqlx::Error>,
+) -> Option<Vec<ColumnInfo>> {
+    let describe = describe.ok()?;
+
+    Some(
+        describe
+            .columns()
+            .iter()
+            .enumerate()
+            .map(|(idx, column)| ColumnInfo {
+                name: column.name().to_owned(),
+                type_name: column.type_info().name().to_owned(),
+                nullable: describe.nullable(idx),
+                ordinal: column.ordinal(),
+            })
+            .collect(),
+    )
+}
+
+// 从结果行获取列信息, 用于 describe 不可用的时候
+// Get the column info from a result row, used when describe is unavailable
+pub fn row_columns<R: Row>(row: &R) -> Vec<ColumnInfo> {
+    row.columns()
+        .iter()
+        .map(|column| ColumnInfo {
+            name: column.name().to_owned(),
+            type_name: column.type_info().name().to_owned(),
+            nullable: None,
+            ordinal: column.ordinal(),
+        })
+        .collect()
+}
+
+// 优先使用 describe 的列信息, 否则从第一行获取
+// Prefer the column info from describe, otherwise take it from the first row
+pub fn resolve_columns<R: Row>(described: Option<Vec<ColumnInfo>>, rows: &[R]) -> Vec<ColumnInfo> {
+    match described {
+        Some(columns) => columns,
+        None => rows.first().map(row_columns).unwrap_or_default(),
+    }
+}
+
+// 最多读取 max_rows 行, 同时返回是否还有剩余的行
+// Read at most `max_rows` rows, also returns whether any rows remain
+pub async fn fetch_limited<R, S>(
+    mut stream: S,
+    max_rows: usize,
+) -> Result<(Vec<R>, bool), sqlx::Error>
+where
+    S: Stream<Item = Result<R, sqlx::Error>> + Unpin,
+{
+    let mut rows = Vec::new();
+    while let Some(row) = stream.next().await {
+        if rows.len() == max_rows {
+            return Ok((rows, true));
+        }
+        rows.push(row?);
+    }
+
+    Ok((rows, false))
+}
+
+// 计算耗时的毫秒数
+// Elapsed time in milliseconds
+pub fn elapsed_ms(start: Instant) -> f64 {
+    start.elapsed().as_secs_f64() * 1000.0
+}
+
+// 每个物理连接建立后执行一次连接 SQL, 连接池补充新连接时也会执行
+// Run the connect SQL once after every physical connection is established,
+// also when the pool opens new connections later
+fn after_connect<DB: Database>(
+    pool_options: PoolOptions<DB>,
+    sql: Option<Arc<str>>,
+) -> PoolOptions<DB>
+where
+    for<'c> &'c mut DB::Connection: Executor<'c, Database = DB>,
+{
+    match sql {
+        Some(sql) => pool_options.after_connect(move |conn, _| {
+            let sql = Arc::clone(&sql);
+            Box::pin(async move { conn.execute(&*sql).await.map(|_| ()) })
+        }),
+        None => pool_options,
+    }
+}
+
+// 每个新连接都执行的 SQL, 通过文本协议发送, 以便一次执行多条语句
+// 超时和只读的设置放在初始化 SQL 之后, 避免被它覆盖
+// SQL run on every new connection over the text protocol, so several statements can run at once
+// The timeout and read-only settings come after the init SQL so they can't be overridden by it
+fn connect_sql(init_sql: Option<&str>, enforced: &[Option<String>]) -> Option<Arc<str>> {
+    let statements: Vec<&str> = init_sql
+        .map(|init| init.trim_end().trim_end_matches(';'))
+        .into_iter()
+        .chain(enforced.iter().flatten().map(String::as_str))
+        .collect();
+
+    (!statements.is_empty()).then(|| Arc::from(statements.join(";\n")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqliteConnectOptions;
+    use std::str::FromStr;
+
+    #[tokio::test]
+    async fn init_sql_runs_on_every_connection() {
+        let options = SqliteConnectOptions::from_str("sqlite::memory:").unwrap();
+        let settings: ConnSettings = serde_json::from_str(
+            r#"{"initSql":"PRAGMA cache_size = -1234","pool":{"maxConnections":2}}"#,
+        )
+        .unwrap();
+        let db_conn = DbConnection::open(DbConnectOptions::Sqlite(options), &settings)
+            .await
+            .unwrap();
+
+        // 同时持有两个连接, 保证它们是不同的物理连接
+        // Hold both connections at once, so they are different physical connections
+        let mut first = db_conn.acquire().await.unwrap();
+        let mut second = db_conn.acquire().await.unwrap();
+        for conn in [&mut first, &mut second] {
+            let PooledConn::Sqlite(conn) = conn else {
+                unreachable!()
+            };
+            let row = conn.fetch_one("PRAGMA cache_size").await.unwrap();
+            assert_eq!(row.get::<i64, _>(0), -1234);
+        }
+        assert_eq!(db_conn.stats().size, 2);
+
+        drop((first, second));
+        db_conn.close().await;
+    }
 }
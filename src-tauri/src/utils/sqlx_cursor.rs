@@ -0,0 +1,312 @@
+use super::{
+    sqlx_common::{describe_columns, elapsed_ms, row_columns, DbConnection, DbPool},
+    sqlx_error::{DbError, DbErrorCode},
+    sqlx_guard::{check_destructive, check_read_only},
+    sqlx_mysql, sqlx_pg,
+    sqlx_pg_custom::{invalidate_types, resolve_money_scale_in_pool, resolve_types_in_pool},
+    sqlx_sqlite,
+};
+use crate::types::{ColumnInfo, QueryResult};
+use futures_util::stream::Peekable;
+use futures_util::{Stream, StreamExt};
+use once_cell::sync::Lazy;
+use sqlx::mysql::MySqlRow;
+use sqlx::postgres::{PgPool, PgRow};
+use sqlx::sqlite::SqliteRow;
+use sqlx::Executor;
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+// 游标空闲超过这个时间会被关闭
+// Cursors idle for longer than this are closed
+const CURSOR_IDLE_TTL: Duration = Duration::from_secs(10 * 60);
+
+pub type RowStream<R> = Pin<Box<dyn Stream<Item = Result<R, sqlx::Error>> + Send>>;
+
+// 可以预读一行, 用于判断是否还有剩余的行
+// Can peek one row ahead, used to tell whether any rows remain
+enum CursorStream {
+    // 连接池用于查询自定义类型 | The pool is used to look up custom types
+    Postgres(Peekable<RowStream<PgRow>>, PgPool),
+    MySql(Peekable<RowStream<MySqlRow>>),
+    Sqlite(Peekable<RowStream<SqliteRow>>),
+}
+
+struct CursorState {
+    conn: DbConnection,
+    sql: Arc<str>,
+    columns: Option<Vec<ColumnInfo>>,
+    prepared: bool,
+    offset: usize,
+    last_used: Instant,
+    stream: Option<CursorStream>,
+}
+
+type CursorMap = HashMap<String, Arc<Mutex<CursorState>>>;
+
+// 游标按连接名分组: 连接名 -> 游标 id -> 游标状态
+// Cursors grouped by connection name: connection name -> cursor id -> cursor state
+static CURSORS: Lazy<Mutex<HashMap<String, CursorMap>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+static NEXT_CURSOR_ID: AtomicU64 = AtomicU64::new(1);
+
+// 打开游标并返回它的 id, 破坏性的语句需要确认令牌, 见 check_destructive
+// Open a cursor and return its id, destructive statements need a confirmation token, see check_destructive
+pub async fn open_cursor(
+    conn_name: &str,
+    sql: &str,
+    confirm_token: Option<&str>,
+) -> Result<String, DbError> {
+    let conn = DbPool::global()
+        .get(conn_name)
+        .await
+        .ok_or_else(|| DbError::connection_not_found(conn_name))?;
+    let settings = DbPool::global()
+        .settings(conn_name)
+        .await
+        .unwrap_or_default();
+    // 和 query 一样在只读连接上检查 | Checked on read-only connections just like query
+    if settings.is_read_only() {
+        check_read_only(sql, conn.db_type())?;
+    }
+    check_destructive(conn_name, sql, conn.db_type(), confirm_token)?;
+    let prepared = settings.use_prepared();
+
+    // 打开时获取一次列信息, 之后翻页都使用它
+    // 不使用预处理语句时不能 describe, 改用读到的行的列信息
+    // Get the column info once on open, all pages use it afterwards
+    // describe isn't available without prepared statements, the columns of the rows read are used instead
+    let columns = match (&conn, prepared) {
+        (_, false) => None,
+        (DbConnection::Postgres(pool), true) => describe_columns(pool.describe(sql).await),
+        (DbConnection::MySql(pool), true) => describe_columns(pool.describe(sql).await),
+        (DbConnection::Sqlite(pool), true) => describe_columns(pool.describe(sql).await),
+    };
+
+    let cursor_id = format!("cursor-{}", NEXT_CURSOR_ID.fetch_add(1, Ordering::Relaxed));
+    let state = CursorState {
+        conn,
+        sql: Arc::from(sql),
+        columns,
+        prepared,
+        offset: 0,
+        last_used: Instant::now(),
+        stream: None,
+    };
+
+    let mut cursors = CURSORS.lock().await;
+    remove_idle(&mut cursors);
+    cursors
+        .entry(conn_name.to_string())
+        .or_default()
+        .insert(cursor_id.clone(), Arc::new(Mutex::new(state)));
+
+    Ok(cursor_id)
+}
+
+/// 读取游标的一页数据 | Read a page of data from the cursor
+///
+/// # 参数
+/// - `conn_name`: 游标所属的连接 | Connection the cursor belongs to
+/// - `cursor_id`: `open_cursor` 返回的 id | The id returned by `open_cursor`
+/// - `page`: 当前页码（从1开始） | Current page number (starting from 1)
+/// - `page_size`: 每页的条目数 | Number of entries per page
+///
+pub async fn fetch_page(
+    conn_name: &str,
+    cursor_id: &str,
+    page: usize,
+    page_size: usize,
+) -> Result<QueryResult, DbError> {
+    // 只在查找游标时持有全局锁, 不同游标的读取互不阻塞
+    // Only hold the global lock while looking up the cursor, so different cursors don't block each other
+    let cursor = {
+        let mut cursors = CURSORS.lock().await;
+        remove_idle(&mut cursors);
+        cursors
+            .get(conn_name)
+            .and_then(|m| m.get(cursor_id))
+            .cloned()
+            .ok_or_else(|| {
+                DbError::new(
+                    DbErrorCode::CursorNotFound,
+                    format!("Cursor '{}' not found", cursor_id),
+                )
+            })?
+    };
+
+    let mut state = cursor.lock().await;
+    state.last_used = Instant::now();
+
+    let target_offset = page.saturating_sub(1) * page_size;
+
+    // 往回翻页时需要重新执行查询
+    // Going back requires re-executing the query
+    if target_offset < state.offset || state.stream.is_none() {
+        let sql = Arc::clone(&state.sql);
+        let prepared = state.prepared;
+        state.stream = Some(match state.conn.clone() {
+            DbConnection::Postgres(pool) => CursorStream::Postgres(
+                sqlx_pg::row_stream(pool.clone(), sql, prepared).peekable(),
+                pool,
+            ),
+            DbConnection::MySql(pool) => {
+                CursorStream::MySql(sqlx_mysql::row_stream(pool, sql, prepared).peekable())
+            }
+            DbConnection::Sqlite(pool) => {
+                CursorStream::Sqlite(sqlx_sqlite::row_stream(pool, sql, prepared).peekable())
+            }
+        });
+        state.offset = 0;
+    }
+
+    let start = Instant::now();
+    let skip = target_offset - state.offset;
+    let res = match state.stream.as_mut().unwrap() {
+        CursorStream::Postgres(stream, pool) => match read_rows(stream, skip, page_size).await {
+            Ok((rows, consumed, has_more)) => {
+                let columns = rows.first().map(row_columns);
+                let json_rows = async {
+                    let types = resolve_types_in_pool(conn_name, pool, &rows).await?;
+                    let money_scale = resolve_money_scale_in_pool(conn_name, pool, &rows).await?;
+                    match sqlx_pg::process_rows(&rows, &types, money_scale) {
+                        // 缓存的复合类型已被修改, 重新查询类型后再转换一次
+                        // A cached composite was altered, query the types again and convert once more
+                        Err(e) if sqlx_pg::is_stale(&e) => {
+                            invalidate_types(conn_name);
+                            let types = resolve_types_in_pool(conn_name, pool, &rows).await?;
+                            Ok(sqlx_pg::process_rows(&rows, &types, money_scale)?)
+                        }
+                        res => Ok(res?),
+                    }
+                };
+                json_rows
+                    .await
+                    .map(|json_rows| (columns, json_rows, consumed, has_more))
+            }
+            Err(e) => Err(e),
+        },
+        CursorStream::MySql(stream) => {
+            read_rows(stream, skip, page_size)
+                .await
+                .and_then(|(rows, consumed, has_more)| {
+                    let columns = rows.first().map(row_columns);
+                    Ok((columns, sqlx_mysql::process_rows(rows)?, consumed, has_more))
+                })
+        }
+        CursorStream::Sqlite(stream) => {
+            read_rows(stream, skip, page_size)
+                .await
+                .and_then(|(rows, consumed, has_more)| {
+                    let columns = rows.first().map(row_columns);
+                    Ok((
+                        columns,
+                        sqlx_sqlite::process_rows(rows)?,
+                        consumed,
+                        has_more,
+                    ))
+                })
+        }
+    };
+
+    // 出错的流不能继续使用, 下次读取时重新执行查询
+    // A failed stream can't be reused, re-execute the query on the next read
+    let (row_columns, json_rows, consumed, has_more) = match res {
+        Ok(o) => o,
+        Err(e) => {
+            state.stream = None;
+            return Err(e.locate(&state.sql));
+        }
+    };
+    state.offset += consumed;
+
+    // describe 不可用时, 使用第一次读到的行的列信息
+    // When describe is unavailable, use the column info of the first rows read
+    if state.columns.is_none() {
+        state.columns = row_columns;
+    }
+
+    Ok(QueryResult {
+        columns: state.columns.clone().unwrap_or_default(),
+        rows: json_rows,
+        elapsed_ms: elapsed_ms(start),
+        truncated: has_more,
+    })
+}
+
+// 关闭游标
+// Close the cursor
+pub async fn close_cursor(conn_name: &str, cursor_id: &str) -> bool {
+    let mut cursors = CURSORS.lock().await;
+    let removed = match cursors.get_mut(conn_name) {
+        Some(m) => m.remove(cursor_id).is_some(),
+        None => false,
+    };
+    cursors.retain(|_, m| !m.is_empty());
+    removed
+}
+
+// 关闭连接的所有游标, 断开连接时调用
+// Close all cursors of the connection, called on disconnect
+pub async fn close_cursors(conn_name: &str) {
+    CURSORS.lock().await.remove(conn_name);
+}
+
+// 重命名连接时把游标移到新名称下
+// Move the cursors to the new name when the connection is renamed
+pub async fn rename_cursors(conn_name: &str, new_name: &str) {
+    let mut cursors = CURSORS.lock().await;
+    if let Some(m) = cursors.remove(conn_name) {
+        cursors.insert(new_name.to_string(), m);
+    }
+}
+
+// 跳过 skip 行后读取最多 take 行, 同时返回消耗的行数和是否还有剩余的行
+// Read up to `take` rows after skipping `skip`, also returns the number of rows consumed and whether any rows remain
+async fn read_rows<R>(
+    stream: &mut Peekable<RowStream<R>>,
+    skip: usize,
+    take: usize,
+) -> Result<(Vec<R>, usize, bool), DbError> {
+    let mut consumed = 0;
+    for _ in 0..skip {
+        match stream.next().await {
+            Some(row) => {
+                row?;
+                consumed += 1;
+            }
+            None => return Ok((Vec::new(), consumed, false)),
+        }
+    }
+
+    let mut rows = Vec::with_capacity(take);
+    for _ in 0..take {
+        match stream.next().await {
+            Some(row) => {
+                rows.push(row?);
+                consumed += 1;
+            }
+            None => return Ok((rows, consumed, false)),
+        }
+    }
+
+    let has_more = Pin::new(stream).peek().await.is_some();
+
+    Ok((rows, consumed, has_more))
+}
+
+// 移除空闲超时的游标, 正在读取的游标会被跳过
+// Remove cursors that have been idle for too long, cursors being read are skipped
+fn remove_idle(cursors: &mut HashMap<String, CursorMap>) {
+    for m in cursors.values_mut() {
+        m.retain(|_, cursor| match cursor.try_lock() {
+            Ok(state) => state.last_used.elapsed() < CURSOR_IDLE_TTL,
+            Err(_) => true,
+        });
+    }
+    cursors.retain(|_, m| !m.is_empty());
+}
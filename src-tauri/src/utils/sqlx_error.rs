@@ -0,0 +1,232 @@
+use serde::Serialize;
+use sqlparser::parser::ParserError;
+use sqlx::mysql::MySqlDatabaseError;
+use sqlx::postgres::{PgDatabaseError, PgErrorPosition};
+use std::fmt;
+
+// 稳定的错误码, 前端根据它判断错误类型, 不要再匹配错误消息
+// Stable error code, the frontend uses it to tell errors apart instead of matching messages
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DbErrorCode {
+    // 连接名重复 | Duplicate connection name
+    DuplicateConnection,
+    // 连接不存在 | Connection not found
+    ConnectionNotFound,
+    // 游标不存在或已超时关闭 | Cursor not found or closed after idling
+    CursorNotFound,
+    // 会话不存在或已关闭 | Session not found or already closed
+    SessionNotFound,
+    // 参数无效, 例如保存点名称 | Invalid argument, e.g. a savepoint name
+    InvalidArgument,
+    // 当前状态下不能执行该操作, 例如在事务中再次 BEGIN
+    // The operation is not allowed in the current state, e.g. BEGIN inside a transaction
+    InvalidState,
+    // 连接配置无效 | Invalid connection configuration
+    InvalidConfig,
+    // SQL 解析失败 | Failed to parse the SQL
+    ParseError,
+    // 只读连接上执行了修改数据或结构的语句
+    // A statement that modifies data or schema was run on a read-only connection
+    ReadOnly,
+    // 破坏性的语句需要确认, detail 中是确认令牌, 带上它重新执行
+    // A destructive statement needs confirmation, detail holds the token to run it again with
+    ConfirmationRequired,
+    // 语句被 sqlx_cancel 取消 | The statement was cancelled by sqlx_cancel
+    Cancelled,
+    // 语句超时, 已在服务端取消 | The statement timed out and was cancelled on the server
+    Timeout,
+    // SSH 隧道建立失败或主机密钥不匹配 | Failed to open the SSH tunnel or the host key doesn't match
+    TunnelError,
+    // SSH 主机不在 known_hosts 中, 确认指纹后可以设置 acceptNewHost 重试
+    // The SSH host is not in known_hosts, retry with acceptNewHost after confirming the fingerprint
+    UnknownHostKey,
+    // 数据库驱动或服务端返回的错误 | Error returned by the driver or the server
+    DriverError,
+}
+
+// 所有数据库命令返回的错误
+// Error returned by all database commands
+#[derive(Serialize, Debug, Clone)]
+pub struct DbError {
+    #[serde(rename = "code")]
+    pub code: DbErrorCode,
+
+    #[serde(rename = "message")]
+    pub message: String,
+
+    // 可选的字段放在堆上, 让 Result<T, DbError> 保持小巧, 序列化后和其它字段平铺在一起
+    // The optional fields live on the heap so Result<T, DbError> stays small,
+    // they are serialized flat alongside the other fields
+    #[serde(flatten)]
+    pub details: Box<DbErrorDetails>,
+}
+
+// DbError 的可选字段 | The optional fields of DbError
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct DbErrorDetails {
+    // 驱动提供的 SQLSTATE, SQLite 是扩展结果码
+    // SQLSTATE provided by the driver, the extended result code for SQLite
+    #[serde(rename = "sqlState")]
+    pub sql_state: Option<String>,
+
+    // 出错位置在 SQL 中的字符序号, 从 1 开始
+    // Character position of the error in the SQL, starting from 1
+    #[serde(rename = "position")]
+    pub position: Option<usize>,
+
+    #[serde(rename = "detail")]
+    pub detail: Option<String>,
+
+    #[serde(rename = "hint")]
+    pub hint: Option<String>,
+
+    #[serde(rename = "constraint")]
+    pub constraint: Option<String>,
+
+    #[serde(rename = "schema")]
+    pub schema: Option<String>,
+
+    #[serde(rename = "table")]
+    pub table: Option<String>,
+
+    #[serde(rename = "column")]
+    pub column: Option<String>,
+
+    // 数据库自己的错误编号, 目前只有 MySQL 提供, 例如 1062 表示唯一键冲突
+    // The database's own error number, only MySQL provides it for now, e.g. 1062 for a duplicate key
+    #[serde(rename = "vendorCode")]
+    pub vendor_code: Option<u16>,
+
+    // 破坏性的语句需要确认时发出的令牌, 带上它重新执行即可
+    // Token issued when destructive SQL needs confirming, run it again with the token to proceed
+    #[serde(rename = "confirmToken")]
+    pub confirm_token: Option<String>,
+
+    // sqlparser 只给出行列号, 由 locate 换算成字符位置
+    // sqlparser only gives a line and column, converted to a character position by locate
+    #[serde(skip)]
+    line_column: Option<(usize, usize)>,
+}
+
+impl DbError {
+    pub fn new(code: DbErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            details: Box::default(),
+        }
+    }
+
+    pub fn connection_not_found(conn_name: &str) -> Self {
+        Self::new(
+            DbErrorCode::ConnectionNotFound,
+            format!("Connection '{}' not found", conn_name),
+        )
+    }
+
+    // 根据 SQL 补充出错位置, 用于只有行列号 (sqlparser) 或只返回了出错片段 (MySQL) 的情况
+    // Fill in the error position from the SQL, for errors with only a line and column (sqlparser)
+    // or only the failing snippet (MySQL)
+    pub fn locate(mut self, sql: &str) -> Self {
+        if self.details.position.is_some() {
+            return self;
+        }
+
+        if let Some((line, column)) = self.details.line_column {
+            let line_start: usize = sql
+                .split_inclusive('\n')
+                .take(line.saturating_sub(1))
+                .map(|l| l.chars().count())
+                .sum();
+            self.details.position = Some(line_start + column);
+            return self;
+        }
+
+        // MySQL: "... for the right syntax to use near 'FROM t' at line 1"
+        if let Some(start) = self.message.find(" near '") {
+            let rest = &self.message[start + " near '".len()..];
+            if let Some(end) = rest.rfind("' at line ") {
+                let snippet = &rest[..end];
+                if !snippet.is_empty() {
+                    if let Some(byte_idx) = sql.find(snippet) {
+                        self.details.position = Some(sql[..byte_idx].chars().count() + 1);
+                    }
+                }
+            }
+        }
+
+        self
+    }
+}
+
+impl fmt::Display for DbError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for DbError {}
+
+impl From<sqlx::Error> for DbError {
+    fn from(e: sqlx::Error) -> Self {
+        let mut err = Self::new(DbErrorCode::DriverError, e.to_string());
+
+        let db_err = match &e {
+            sqlx::Error::Database(db_err) => db_err,
+            _ => return err,
+        };
+
+        let details = &mut err.details;
+        details.sql_state = db_err.code().map(|c| c.to_string());
+        details.constraint = db_err.constraint().map(|c| c.to_string());
+        details.table = db_err.table().map(|c| c.to_string());
+
+        if let Some(pg_err) = db_err.try_downcast_ref::<PgDatabaseError>() {
+            details.position = match pg_err.position() {
+                Some(PgErrorPosition::Original(pos)) => Some(pos),
+                _ => None,
+            };
+            details.detail = pg_err.detail().map(|c| c.to_string());
+            details.hint = pg_err.hint().map(|c| c.to_string());
+            details.schema = pg_err.schema().map(|c| c.to_string());
+            details.column = pg_err.column().map(|c| c.to_string());
+        } else if let Some(mysql_err) = db_err.try_downcast_ref::<MySqlDatabaseError>() {
+            details.vendor_code = Some(mysql_err.number());
+        }
+
+        err
+    }
+}
+
+impl From<ParserError> for DbError {
+    fn from(e: ParserError) -> Self {
+        let message = e.to_string();
+        let mut err = Self::new(DbErrorCode::ParseError, message.clone());
+
+        // sqlparser 的错误消息以 " at Line: 1, Column: 5" 结尾
+        // sqlparser messages end with " at Line: 1, Column: 5"
+        if let Some(idx) = message.rfind(" at Line: ") {
+            let mut parts = message[idx + " at Line: ".len()..].split(", Column: ");
+            let line = parts.next().and_then(|s| s.trim().parse::<usize>().ok());
+            let column = parts.next().and_then(|s| s.trim().parse::<usize>().ok());
+            if let (Some(line), Some(column)) = (line, column) {
+                err.details.line_column = Some((line, column));
+            }
+        }
+
+        err
+    }
+}
+
+impl From<ssh2::Error> for DbError {
+    fn from(e: ssh2::Error) -> Self {
+        Self::new(DbErrorCode::TunnelError, e.to_string())
+    }
+}
+
+impl From<serde_json::Error> for DbError {
+    fn from(e: serde_json::Error) -> Self {
+        Self::new(DbErrorCode::DriverError, e.to_string())
+    }
+}
@@ -0,0 +1,491 @@
+use super::{
+    sha::sha256,
+    sqlx_common::DbType,
+    sqlx_error::{DbError, DbErrorCode},
+    sqlx_public::dialect,
+};
+use once_cell::sync::Lazy;
+use sqlparser::{
+    ast::{
+        AlterTableOperation, FromTable, Query, SetExpr, Statement, TransactionAccessMode,
+        TransactionMode,
+    },
+    parser::Parser,
+    tokenizer::{Token, Tokenizer},
+};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// 确认令牌的有效期, 足够用户读完确认框 | Lifetime of a confirmation token, long enough to read the confirm dialog
+const CONFIRM_TTL: Duration = Duration::from_secs(120);
+
+// 发出但还没使用的确认令牌: 令牌 -> 绑定的连接和语句
+// 临界区内没有 await, 使用标准库的锁
+// Issued and not yet used confirmation tokens: token -> the connection and statements it is bound to
+// No await inside the critical sections, so the std lock is used
+static PENDING_CONFIRMS: Lazy<Mutex<HashMap<String, PendingConfirm>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+struct PendingConfirm {
+    conn_name: String,
+    // SQL 的哈希, 令牌只对这一段 SQL 有效 | Hash of the SQL, the token is only valid for this SQL
+    sql_hash: String,
+    expires_at: Instant,
+}
+
+/// 检查只读连接上要执行的 SQL, 拒绝修改数据或结构的语句
+/// Check the SQL to be run on a read-only connection, rejecting statements that modify data or schema
+///
+/// 只放行已知只读的语句, 无法解析的 SQL 也会被拒绝
+/// 服务端的只读事务和 SQLite 的 mode=ro 仍然生效, 这里只是在发送前提前拒绝
+///
+/// Only statements known to be read-only pass, SQL that can't be parsed is rejected as well
+/// The server side read-only transaction and SQLite's mode=ro still apply, this only rejects earlier
+///
+/// # 参数
+/// - `sql`: 要执行的 sql, 可以包含多条语句 | The SQL to be run, may contain several statements
+/// - `db_type`: 数据库类型, 用于选择 SQL 方言 | Database type, used to pick the SQL dialect
+///
+pub fn check_read_only(sql: &str, db_type: DbType) -> Result<(), DbError> {
+    let statements =
+        Parser::parse_sql(&*dialect(db_type), sql).map_err(|e| DbError::from(e).locate(sql))?;
+
+    let keyword = match statements.iter().find(|stmt| !is_read_only(stmt)) {
+        Some(stmt) => stmt
+            .to_string()
+            .split_whitespace()
+            .next()
+            .unwrap_or_default()
+            .to_uppercase(),
+        None => match read_write_switch(sql, db_type) {
+            Some(keyword) => keyword.to_string(),
+            None => return Ok(()),
+        },
+    };
+
+    Err(DbError::new(
+        DbErrorCode::ReadOnly,
+        format!("{} is not allowed on a read-only connection", keyword),
+    ))
+}
+
+// 在语法树之外再按词法检查一遍: set_config() 可以在 SELECT 里关闭只读,
+// READ WRITE 可以出现在各种 SET 和 BEGIN 的写法中
+// A lexical check on top of the syntax tree: set_config() can turn read-only off inside a SELECT,
+// and READ WRITE can show up in the many forms of SET and BEGIN
+fn read_write_switch(sql: &str, db_type: DbType) -> Option<&'static str> {
+    let tokens: Vec<Token> = Tokenizer::new(&*dialect(db_type), sql)
+        .tokenize()
+        .ok()?
+        .into_iter()
+        .filter(|token| !matches!(token, Token::Whitespace(_)))
+        .collect();
+
+    tokens.windows(2).find_map(|pair| match pair {
+        [Token::Word(name), Token::LParen] if name.value.eq_ignore_ascii_case("set_config") => {
+            Some("set_config()")
+        }
+        [Token::Word(read), Token::Word(write)]
+            if read.quote_style.is_none()
+                && write.quote_style.is_none()
+                && read.value.eq_ignore_ascii_case("read")
+                && write.value.eq_ignore_ascii_case("write") =>
+        {
+            Some("READ WRITE")
+        }
+        _ => None,
+    })
+}
+
+fn is_read_only(stmt: &Statement) -> bool {
+    match stmt {
+        Statement::Query(query) => is_read_only_query(query),
+        // EXPLAIN ANALYZE 会真正执行语句 | EXPLAIN ANALYZE really runs the statement
+        Statement::Explain {
+            analyze, statement, ..
+        } => !analyze || is_read_only(statement),
+        Statement::ExplainTable { .. }
+        | Statement::ShowFunctions { .. }
+        | Statement::ShowVariable { .. }
+        | Statement::ShowStatus { .. }
+        | Statement::ShowVariables { .. }
+        | Statement::ShowCreate { .. }
+        | Statement::ShowColumns { .. }
+        | Statement::ShowDatabases { .. }
+        | Statement::ShowSchemas { .. }
+        | Statement::ShowTables { .. }
+        | Statement::ShowViews { .. }
+        | Statement::ShowCollation { .. }
+        | Statement::Use(_) => true,
+        // 事务控制语句, 但不能切换为读写事务
+        // Transaction control statements, but switching to a read-write transaction is not allowed
+        Statement::StartTransaction { modes, .. } => !is_read_write(modes),
+        Statement::SetTransaction { modes, .. } => !is_read_write(modes),
+        Statement::Commit { .. }
+        | Statement::Rollback { .. }
+        | Statement::Savepoint { .. }
+        | Statement::ReleaseSavepoint { .. } => true,
+        // 会话变量, 但不能关闭只读, 例如 default_transaction_read_only / transaction_read_only
+        // Session variables, but read-only can't be turned off, e.g. default_transaction_read_only / transaction_read_only
+        Statement::SetVariable { variables, .. } => variables.iter().all(|name| {
+            let name = name.to_string().to_lowercase();
+            !name.contains("read_only") && !name.contains("transaction")
+        }),
+        Statement::SetTimeZone { .. } | Statement::SetNames { .. } | Statement::SetRole { .. } => {
+            true
+        }
+        // 只允许读取 PRAGMA, 设置的值可能会写数据库文件
+        // Only reading a PRAGMA is allowed, setting a value may write the database file
+        Statement::Pragma { value, .. } => value.is_none(),
+        _ => false,
+    }
+}
+
+fn is_read_only_query(query: &Query) -> bool {
+    let ctes_read_only = query.with.as_ref().is_none_or(|with| {
+        with.cte_tables
+            .iter()
+            .all(|cte| is_read_only_query(&cte.query))
+    });
+    ctes_read_only && is_read_only_set_expr(&query.body)
+}
+
+fn is_read_only_set_expr(expr: &SetExpr) -> bool {
+    match expr {
+        // SELECT ... INTO 会创建表 | SELECT ... INTO creates a table
+        SetExpr::Select(select) => select.into.is_none(),
+        SetExpr::Query(query) => is_read_only_query(query),
+        SetExpr::SetOperation { left, right, .. } => {
+            is_read_only_set_expr(left) && is_read_only_set_expr(right)
+        }
+        SetExpr::Values(_) | SetExpr::Table(_) => true,
+        SetExpr::Insert(_) | SetExpr::Update(_) => false,
+    }
+}
+
+fn is_read_write(modes: &[TransactionMode]) -> bool {
+    modes.iter().any(|mode| {
+        matches!(
+            mode,
+            TransactionMode::AccessMode(TransactionAccessMode::ReadWrite)
+        )
+    })
+}
+
+/// 检查要执行的 SQL 中是否有破坏性的语句, 有则需要带上确认令牌才能执行
+/// Check the SQL to be run for destructive statements, which only run with a confirmation token
+///
+/// 破坏性的语句包括 DROP, TRUNCATE, ALTER TABLE ... DROP COLUMN, 以及没有 WHERE 的 DELETE/UPDATE
+/// 令牌是随机生成的, 在错误的 confirmToken 中返回, 只对这个连接和这一段 SQL 有效, 只能用一次, 两分钟后过期
+/// 无法解析的 SQL 按关键字粗略判断, 连词法分析都失败时同样需要确认
+///
+/// Destructive statements are DROP, TRUNCATE, ALTER TABLE ... DROP COLUMN, and DELETE/UPDATE without WHERE
+/// The token is random and returned in the error's confirmToken, it is only valid for this connection and this SQL,
+/// can be used once and expires after two minutes
+/// SQL that can't be parsed is classified roughly by its keywords, it needs confirming when even tokenizing fails
+///
+/// # 参数
+/// - `conn_name`: 连接名 | Connection name
+/// - `sql`: 要执行的 sql, 可以包含多条语句 | The SQL to be run, may contain several statements
+/// - `db_type`: 数据库类型, 用于选择 SQL 方言 | Database type, used to pick the SQL dialect
+/// - `confirm_token`: 上次返回的确认令牌 | The confirmation token returned last time
+///
+pub fn check_destructive(
+    conn_name: &str,
+    sql: &str,
+    db_type: DbType,
+    confirm_token: Option<&str>,
+) -> Result<(), DbError> {
+    let dangers: Vec<String> = match Parser::parse_sql(&*dialect(db_type), sql) {
+        Ok(statements) => statements
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, stmt)| {
+                let reason = destructive_reason(stmt)?;
+                Some(if statements.len() > 1 {
+                    format!("statement {} `{}`: {}", idx + 1, stmt, reason)
+                } else {
+                    format!("`{}`: {}", stmt, reason)
+                })
+            })
+            .collect(),
+        Err(e) => destructive_keywords(sql, db_type).unwrap_or_else(|| {
+            vec![format!(
+                "the SQL couldn't be parsed ({}), so it can't be checked for destructive statements",
+                e
+            )]
+        }),
+    };
+    if dangers.is_empty() {
+        return Ok(());
+    }
+
+    if let Some(token) = confirm_token {
+        if take_confirm_token(token, conn_name, sql) {
+            return Ok(());
+        }
+    }
+
+    let mut err = DbError::new(
+        DbErrorCode::ConfirmationRequired,
+        format!("Destructive SQL needs confirmation, {}", dangers.join("; ")),
+    );
+    err.details.confirm_token = Some(issue_confirm_token(conn_name, sql));
+    err.details.hint = Some("Run it again with the confirmation token to proceed".to_string());
+    Err(err)
+}
+
+// 生成随机令牌并记下它绑定的连接和 SQL, 顺便清理过期的令牌
+// Generate a random token and remember the connection and SQL it is bound to, expired tokens are dropped on the way
+fn issue_confirm_token(conn_name: &str, sql: &str) -> String {
+    let token = format!("{:032x}", rand::random::<u128>());
+    let now = Instant::now();
+
+    let mut pending = PENDING_CONFIRMS.lock().unwrap();
+    pending.retain(|_, confirm| confirm.expires_at > now);
+    pending.insert(
+        token.clone(),
+        PendingConfirm {
+            conn_name: conn_name.to_string(),
+            sql_hash: sha256(sql.to_string()),
+            expires_at: now + CONFIRM_TTL,
+        },
+    );
+    token
+}
+
+// 令牌无论是否有效都在这里用掉, 不能重放
+// The token is used up here whether it is valid or not, so it can't be replayed
+fn take_confirm_token(token: &str, conn_name: &str, sql: &str) -> bool {
+    match PENDING_CONFIRMS.lock().unwrap().remove(token) {
+        Some(confirm) => {
+            confirm.conn_name == conn_name
+                && confirm.sql_hash == sha256(sql.to_string())
+                && confirm.expires_at > Instant::now()
+        }
+        None => false,
+    }
+}
+
+// 无法解析的 SQL 按关键字粗略判断: 每条语句开头的关键字, 以及 DELETE/UPDATE 后面有没有 WHERE
+// 词法分析失败时 (例如引号没有闭合) 返回 None
+// SQL that can't be parsed is classified roughly by its keywords: the leading keyword of each statement,
+// and whether a WHERE follows DELETE/UPDATE
+// Returns None when tokenizing fails, e.g. for an unclosed quote
+fn destructive_keywords(sql: &str, db_type: DbType) -> Option<Vec<String>> {
+    let tokens = Tokenizer::new(&*dialect(db_type), sql).tokenize().ok()?;
+    // 字符串和加引号的标识符里的词不算关键字 | Words in strings and quoted identifiers are not keywords
+    let statements: Vec<Vec<String>> = tokens
+        .split(|token| matches!(token, Token::SemiColon))
+        .map(|stmt| {
+            stmt.iter()
+                .filter_map(|token| match token {
+                    Token::Word(word) if word.quote_style.is_none() => {
+                        Some(word.value.to_uppercase())
+                    }
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        })
+        .filter(|words| !words.is_empty())
+        .collect();
+
+    let count = statements.len();
+    let dangers = statements
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, words)| {
+            let has = |keyword: &str| words.iter().any(|word| word == keyword);
+            let reason = match words[0].as_str() {
+                "DROP" | "TRUNCATE" => format!("{} may permanently remove data", words[0]),
+                "ALTER" if has("DROP") => "ALTER ... DROP may permanently remove data".to_string(),
+                "DELETE" | "UPDATE" if !has("WHERE") => {
+                    format!("{} without a WHERE clause may change every row", words[0])
+                }
+                _ => return None,
+            };
+            Some(if count > 1 {
+                format!("statement {}: {}", idx + 1, reason)
+            } else {
+                reason
+            })
+        })
+        .collect();
+    Some(dangers)
+}
+
+fn destructive_reason(stmt: &Statement) -> Option<String> {
+    match stmt {
+        Statement::Drop {
+            object_type, names, ..
+        } => Some(format!(
+            "DROP {} permanently removes {}",
+            object_type,
+            display_names(names)
+        )),
+        Statement::Truncate { table_names, .. } => Some(format!(
+            "TRUNCATE removes every row of {}",
+            display_names(table_names.iter().map(|t| &t.name))
+        )),
+        Statement::AlterTable {
+            name, operations, ..
+        } => {
+            let columns: Vec<String> = operations
+                .iter()
+                .filter_map(|op| match op {
+                    AlterTableOperation::DropColumn { column_name, .. } => {
+                        Some(column_name.to_string())
+                    }
+                    _ => None,
+                })
+                .collect();
+            (!columns.is_empty()).then(|| {
+                format!(
+                    "DROP COLUMN permanently removes {} and its data from {}",
+                    columns.join(", "),
+                    name
+                )
+            })
+        }
+        Statement::Delete(delete) if delete.selection.is_none() => {
+            let tables = match &delete.from {
+                FromTable::WithFromKeyword(tables) | FromTable::WithoutKeyword(tables) => tables,
+            };
+            Some(format!(
+                "DELETE without a WHERE clause removes every row of {}",
+                display_names(tables.iter().map(|t| &t.relation))
+            ))
+        }
+        Statement::Update {
+            table, selection, ..
+        } if selection.is_none() => Some(format!(
+            "UPDATE without a WHERE clause changes every row of {}",
+            table.relation
+        )),
+        _ => None,
+    }
+}
+
+fn display_names<T: fmt::Display>(names: impl IntoIterator<Item = T>) -> String {
+    names
+        .into_iter()
+        .map(|name| name.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_only_code(sql: &str, db_type: DbType) -> Option<DbErrorCode> {
+        check_read_only(sql, db_type).err().map(|e| e.code)
+    }
+
+    #[test]
+    fn read_only_allows_reads() {
+        for sql in [
+            "SELECT 1",
+            "WITH t AS (SELECT 1) SELECT * FROM t",
+            "EXPLAIN SELECT * FROM t",
+            "SHOW search_path",
+            "SET search_path = public",
+            "BEGIN READ ONLY",
+            "SELECT current_setting('default_transaction_read_only')",
+            "SELECT 'read write' AS note",
+        ] {
+            assert_eq!(read_only_code(sql, DbType::Postgres), None, "{}", sql);
+        }
+        assert_eq!(read_only_code("PRAGMA user_version", DbType::Sqlite), None);
+    }
+
+    #[test]
+    fn read_only_rejects_writes() {
+        for sql in [
+            "INSERT INTO t VALUES (1)",
+            "SELECT 1; DELETE FROM t",
+            "SELECT * INTO t2 FROM t",
+            "EXPLAIN ANALYZE UPDATE t SET a = 1",
+            "SET default_transaction_read_only = off",
+            "SET SESSION CHARACTERISTICS AS TRANSACTION READ WRITE",
+            "BEGIN READ WRITE",
+            "SELECT set_config('default_transaction_read_only', 'off', false)",
+            "SELECT pg_catalog.set_config('transaction_read_only', 'off', true)",
+            "SELECT \"set_config\" ('default_transaction_read_only', 'off', false)",
+        ] {
+            assert_eq!(
+                read_only_code(sql, DbType::Postgres),
+                Some(DbErrorCode::ReadOnly),
+                "{}",
+                sql
+            );
+        }
+        assert_eq!(
+            read_only_code("PRAGMA user_version = 2", DbType::Sqlite),
+            Some(DbErrorCode::ReadOnly)
+        );
+
+        // 无法解析的 SQL 同样拒绝 | SQL that can't be parsed is rejected as well
+        for (sql, db_type) in [
+            (
+                "WITH d AS (DELETE FROM t RETURNING *) SELECT * FROM d",
+                DbType::Postgres,
+            ),
+            ("SET SESSION TRANSACTION READ WRITE", DbType::MySql),
+            ("not sql at all", DbType::Postgres),
+        ] {
+            assert_eq!(
+                read_only_code(sql, db_type),
+                Some(DbErrorCode::ParseError),
+                "{}",
+                sql
+            );
+        }
+    }
+
+    fn confirm(sql: &str, token: Option<&str>) -> Result<(), DbError> {
+        check_destructive("guard-test", sql, DbType::Postgres, token)
+    }
+
+    #[test]
+    fn destructive_needs_a_single_use_token() {
+        assert!(confirm("DELETE FROM t WHERE id = 1", None).is_ok());
+
+        let sql = "DROP TABLE t";
+        let err = confirm(sql, None).err().unwrap();
+        assert_eq!(err.code, DbErrorCode::ConfirmationRequired);
+        let token = err.details.confirm_token.unwrap();
+
+        // 令牌只对发出它的 SQL 有效, 用错一次就作废
+        // The token is only valid for the SQL it was issued for, and one wrong use voids it
+        assert!(confirm("TRUNCATE t", Some(&token)).is_err());
+        assert!(confirm(sql, Some(&token)).is_err());
+
+        let token = confirm(sql, None)
+            .err()
+            .unwrap()
+            .details
+            .confirm_token
+            .unwrap();
+        assert!(confirm(sql, Some(&token)).is_ok());
+        assert!(confirm(sql, Some(&token)).is_err());
+    }
+
+    #[test]
+    fn unparsable_sql_is_checked_by_keywords() {
+        let sql = "DROP TABLE t; this is not sql";
+        let err = confirm(sql, None).err().unwrap();
+        assert_eq!(err.code, DbErrorCode::ConfirmationRequired);
+        assert!(confirm(sql, err.details.confirm_token.as_deref()).is_ok());
+
+        // 不能解析但没有破坏性关键字的 SQL 直接执行 | Unparsable SQL without destructive keywords just runs
+        assert!(confirm("SELECT 'DROP TABLE t' FROM t WHERE x ~~~ 1", None).is_ok());
+        assert!(confirm("DELETE FROM t WHERE x ~~~ 1", None).is_ok());
+        assert!(confirm("DELETE FROM t x ~~~ 1", None).is_err());
+        assert!(confirm("UPDATE \"drop\" SET a = 1 WHERE b ~~~ 1", None).is_ok());
+        // 连词法分析都失败时需要确认 | Needs confirming when even tokenizing fails
+        assert!(confirm("SELECT 'unclosed", None).is_err());
+    }
+}
@@ -0,0 +1,214 @@
+use super::{
+    sqlx_common::DbPool,
+    sqlx_error::{DbError, DbErrorCode},
+    sqlx_public::reopen,
+};
+use crate::types::{HealthEvent, HealthStatus};
+use futures_util::future::join_all;
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::time::Instant;
+
+// 检查的轮询间隔 | Polling interval of the checks
+const TICK: Duration = Duration::from_secs(1);
+
+// 正常的连接每隔这么久检查一次
+// Healthy connections are checked this often
+const CHECK_INTERVAL: Duration = Duration::from_secs(15);
+
+// 单次检查的超时 | Timeout of a single check
+const PING_TIMEOUT: Duration = Duration::from_secs(5);
+
+// 连续失败这么多次才认为连接断开, 一次偶然的超时不会重建正常的连接池
+// The connection is only considered down after this many failures in a row,
+// so a single stray timeout doesn't tear down a healthy pool
+const FAILURE_THRESHOLD: u32 = 2;
+
+// 检查失败后隔这么久再检查一次 | A failed check is repeated this soon
+const RECHECK_INTERVAL: Duration = Duration::from_secs(2);
+
+// 单次重连的超时, 包括建立 SSH 隧道 | Timeout of a single reconnect, including the SSH tunnel
+const REOPEN_TIMEOUT: Duration = Duration::from_secs(30);
+
+// 重连的退避时间从 1 秒开始翻倍, 最长 1 分钟
+// The reconnect backoff starts at 1 second and doubles, up to 1 minute
+const BACKOFF_BASE: Duration = Duration::from_secs(1);
+const BACKOFF_MAX: Duration = Duration::from_secs(60);
+
+struct ConnHealth {
+    down: bool,
+    // 连续失败的检查次数 | Number of failed checks in a row
+    failures: u32,
+    attempts: u32,
+    next_check: Instant,
+}
+
+impl ConnHealth {
+    fn healthy() -> Self {
+        Self {
+            down: false,
+            failures: 0,
+            attempts: 0,
+            next_check: Instant::now() + CHECK_INTERVAL,
+        }
+    }
+}
+
+/// 在后台定期检查所有已登记的连接, 断开时按退避时间自动重连
+/// Periodically check all registered connections in the background, reconnect with backoff when they go down
+///
+/// # 参数
+/// - `notify`: 状态变化时调用, 由调用方转发为 Tauri 事件 | Called on status changes, forwarded as a Tauri event by the caller
+///
+pub async fn run<F>(notify: F)
+where
+    F: Fn(HealthEvent) + Send + Sync + 'static,
+{
+    let mut health: HashMap<String, ConnHealth> = HashMap::new();
+    let mut ticker = tokio::time::interval(TICK);
+
+    loop {
+        ticker.tick().await;
+
+        // 断开的连接不再检查, 新的连接在下一个间隔后检查
+        // Disconnected connections are no longer checked, new connections are checked after one interval
+        let names = DbPool::global().names().await;
+        health.retain(|name, _| names.contains(name));
+        for name in names {
+            health.entry(name).or_insert_with(ConnHealth::healthy);
+        }
+
+        // 到期的连接并行检查, 一个卡住的连接不会拖住其它连接
+        // Due connections are checked in parallel, so one stuck connection doesn't hold up the others
+        let now = Instant::now();
+        let due = health
+            .iter_mut()
+            .filter(|(_, state)| now >= state.next_check)
+            .map(|(name, state)| check(name, state, &notify));
+        join_all(due).await;
+    }
+}
+
+async fn check<F>(name: &str, state: &mut ConnHealth, notify: &F)
+where
+    F: Fn(HealthEvent),
+{
+    if !state.down {
+        match ping(name).await {
+            Ok(()) => {
+                state.failures = 0;
+                state.next_check = Instant::now() + CHECK_INTERVAL;
+                return;
+            }
+            Err(_) if state.failures + 1 < FAILURE_THRESHOLD => {
+                state.failures += 1;
+                state.next_check = Instant::now() + RECHECK_INTERVAL;
+                return;
+            }
+            Err(e) => {
+                state.down = true;
+                state.attempts = 0;
+                notify(event(name, HealthStatus::Down, 0, None, Some(e)));
+            }
+        }
+    }
+
+    let res = tokio::time::timeout(REOPEN_TIMEOUT, reopen(name))
+        .await
+        .unwrap_or_else(|_| {
+            Err(DbError::new(
+                DbErrorCode::Timeout,
+                format!(
+                    "Reconnecting timed out after {} ms",
+                    REOPEN_TIMEOUT.as_millis()
+                ),
+            ))
+        });
+    match res {
+        Ok(()) => {
+            *state = ConnHealth::healthy();
+            notify(event(name, HealthStatus::Up, 0, None, None));
+        }
+        // 连接在重连期间被断开 | The connection was disconnected while reconnecting
+        Err(e) if e.code == DbErrorCode::ConnectionNotFound => {}
+        Err(e) => {
+            state.attempts += 1;
+            let delay = backoff(state.attempts);
+            state.next_check = Instant::now() + delay;
+            notify(event(
+                name,
+                HealthStatus::Reconnecting,
+                state.attempts,
+                Some(delay),
+                Some(e),
+            ));
+        }
+    }
+}
+
+// 从连接池取一个连接并往返一次服务端
+// 连接都在使用中时跳过, 否则会等到超时而误判为断开
+// Take a connection from the pool and make one round trip to the server
+// Skipped when all connections are in use, otherwise it would wait until the timeout and be mistaken for down
+async fn ping(conn_name: &str) -> Result<(), DbError> {
+    let db_conn = match DbPool::global().get(conn_name).await {
+        Some(db_conn) => db_conn,
+        None => return Ok(()),
+    };
+    if db_conn.is_saturated() {
+        return Ok(());
+    }
+
+    let check = async {
+        let mut conn = db_conn.acquire().await?;
+        conn.ping().await?;
+        Ok::<(), DbError>(())
+    };
+    tokio::time::timeout(PING_TIMEOUT, check)
+        .await
+        .unwrap_or_else(|_| {
+            Err(DbError::new(
+                DbErrorCode::Timeout,
+                format!(
+                    "Health check timed out after {} ms",
+                    PING_TIMEOUT.as_millis()
+                ),
+            ))
+        })
+}
+
+fn backoff(attempts: u32) -> Duration {
+    BACKOFF_BASE
+        .saturating_mul(2u32.saturating_pow(attempts.saturating_sub(1)))
+        .min(BACKOFF_MAX)
+}
+
+fn event(
+    conn_name: &str,
+    status: HealthStatus,
+    attempts: u32,
+    retry_in: Option<Duration>,
+    error: Option<DbError>,
+) -> HealthEvent {
+    HealthEvent {
+        conn_name: conn_name.to_string(),
+        status,
+        attempts,
+        retry_in_ms: retry_in.map(|d| d.as_millis() as u64),
+        error,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_up_to_the_maximum() {
+        let secs: Vec<u64> = (0..=8)
+            .map(|attempts| backoff(attempts).as_secs())
+            .collect();
+        assert_eq!(secs, vec![1, 1, 2, 4, 8, 16, 32, 60, 60]);
+        assert_eq!(backoff(u32::MAX), BACKOFF_MAX);
+    }
+}
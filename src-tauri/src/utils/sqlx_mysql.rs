@@ -1,74 +1,227 @@
 use crate::types::QueryResult;
 use crate::utils::common::print_sql;
+use crate::utils::sqlx_common::{describe_columns, elapsed_ms, fetch_limited, resolve_columns};
+use crate::utils::sqlx_cursor::RowStream;
+use crate::utils::sqlx_error::DbError;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use bigdecimal::BigDecimal;
+use futures_util::{StreamExt, TryStreamExt};
 use serde_json::json;
+use sqlx::mysql::types::MySqlTime;
+use sqlx::mysql::{MySqlConnection, MySqlRow};
 use sqlx::types::chrono;
 use sqlx::Column;
 use sqlx::Row;
 use sqlx::TypeInfo;
-use sqlx::{Execute, MySqlPool};
+use sqlx::{Execute, Executor, MySqlPool};
+use std::sync::Arc;
+use std::time::Instant;
 
 /// 处理 MySQL 的查询 | Handling MySQL queries
 ///
+/// 分页查询使用 `sqlx_cursor`
+/// Use `sqlx_cursor` for paginated queries
+///
 /// # 参数
+/// - `conn`: 执行查询的连接, 来自会话或连接池 | Connection to run the query on, from a session or the pool
 /// - `sql`: 要执行的 sql | The SQL to be executed"
-/// - `streaming`: 是否启用流式分页 | Whether to enable streaming pagination
-/// - `page`: 当前页码（从1开始） | Current page number (starting from 1)
-/// - `page_size`: 每页的条目数 | Number of entries per page
+/// - `max_rows`: 最多返回的行数, None 表示全部返回 | Maximum number of rows to return, None returns all
+/// - `prepared`: 是否使用预处理语句 | Whether to use prepared statements
 ///
 pub async fn query_mysql(
-    pool: &MySqlPool,
+    conn: &mut MySqlConnection,
     sql: &str,
-    streaming: bool,
-    page: Option<usize>,
-    page_size: Option<usize>,
-) -> Result<QueryResult, Box<dyn std::error::Error>> {
+    max_rows: Option<usize>,
+    prepared: bool,
+) -> Result<QueryResult, DbError> {
     let query = sqlx::query(sql);
     #[cfg(debug_assertions)]
     {
         print_sql(query.sql(), 7);
     }
 
-    let rows = query.fetch_all(pool).await?;
-    let mut json_rows = Vec::new();
-    let mut column_names: Vec<String> = Vec::new();
-    let mut index = 0;
+    // 不使用预处理语句时直接执行 &str, 走文本协议, 也不能 describe
+    // Without prepared statements a plain &str is executed over the text protocol, describe isn't available either
+    let start = Instant::now();
+    let stream = if prepared {
+        query.fetch(&mut *conn)
+    } else {
+        conn.fetch(sql)
+    };
+    let (rows, truncated) = match max_rows {
+        Some(max) => fetch_limited(stream, max).await?,
+        None => (stream.try_collect().await?, false),
+    };
+    let elapsed_ms = elapsed_ms(start);
+
+    let described = if prepared {
+        describe_columns(conn.describe(sql).await)
+    } else {
+        None
+    };
+    let columns = resolve_columns(described, &rows);
+
+    Ok(QueryResult {
+        columns,
+        rows: process_rows(rows)?,
+        elapsed_ms,
+        truncated,
+    })
+}
+
+// 创建游标使用的行流
+// Create the row stream used by cursors
+pub fn row_stream(pool: MySqlPool, sql: Arc<str>, prepared: bool) -> RowStream<MySqlRow> {
+    Box::pin(async_stream::stream! {
+        let query = sqlx::query(&sql);
+        #[cfg(debug_assertions)]
+        {
+            print_sql(query.sql(), 7);
+        }
+
+        let mut stream = if prepared {
+            query.persistent(true).fetch(&pool)
+        } else {
+            pool.fetch(&*sql)
+        };
+
+        while let Some(row) = stream.next().await {
+            yield row;
+        }
+    })
+}
+
+// 把结果行转为按列顺序排列的 json 数组
+// Convert result rows to JSON arrays in column order
+pub fn process_rows(rows: Vec<MySqlRow>) -> Result<Vec<Vec<serde_json::Value>>, DbError> {
+    let mut json_rows = Vec::with_capacity(rows.len());
 
     for row in rows {
-        let mut json_row = json!({});
-        for column in row.columns() {
-            let col_name = column.name();
-            let value = match column.type_info().name() {
-                "TINYINT" => {
-                    let val: i8 = row.get(col_name);
-                    if column.type_info().name().starts_with("TINYINT(1)") {
-                        json!(val != 0)
-                    } else {
-                        json!(val)
-                    }
-                }
-                "INT" => json!(row.get::<i32, _>(col_name)),
-                "BIGINT" => json!(row.get::<i64, _>(col_name)),
-                "FLOAT" => json!(row.get::<f32, _>(col_name)),
-                "DOUBLE" => json!(row.get::<f64, _>(col_name)),
-                "VARCHAR" | "TEXT" => json!(row.get::<String, _>(col_name)),
-                "DATETIME" | "TIMESTAMP" => {
-                    let dt: chrono::NaiveDateTime = row.get(col_name);
-                    json!(dt.to_string())
-                }
-                _ => json!("unsupported_type"),
-            };
-            json_row[col_name] = value;
-
-            if index == 0 {
-                column_names.push(col_name.to_owned())
-            }
+        let mut json_row = Vec::with_capacity(row.len());
+        for idx in 0..row.len() {
+            json_row.push(convert_value_mysql(&row, idx)?);
         }
         json_rows.push(json_row);
-        index += 1;
     }
 
-    Ok(QueryResult {
-        column_name: serde_json::to_string(&column_names)?,
-        data: serde_json::to_string(&json_rows)?,
-    })
+    Ok(json_rows)
+}
+
+// 把数据库类型转为 json 类型
+// Convert database type to JSON type
+fn convert_value_mysql(row: &MySqlRow, idx: usize) -> Result<serde_json::Value, sqlx::Error> {
+    let column = row.columns().get(idx).unwrap();
+    let type_name = column.type_info().name();
+
+    // 参考: sqlx-mysql-0.8.3/src/protocol/text/column.rs
+    // 里的 pub(crate) fn name(self, flags: ColumnFlags, max_size: Option<u32>) -> &'static str { 部分
+    // TINYINT(1) 的名称是 BOOLEAN, 无符号整数的名称带有 UNSIGNED 后缀
+    //
+    // 特别注意: 必须使用 Option 处理, 用于针对 NULL 的值
+    //
+    match type_name {
+        // 空值, 例如 SELECT NULL
+        "NULL" => Ok(json!(null)),
+
+        // 布尔类型 (TINYINT(1))
+        "BOOLEAN" => Ok(json!(row.try_get::<Option<bool>, _>(idx)?)),
+
+        // 整数类型
+        "TINYINT" => Ok(json!(row.try_get::<Option<i8>, _>(idx)?)),
+        "SMALLINT" => Ok(json!(row.try_get::<Option<i16>, _>(idx)?)),
+        "MEDIUMINT" | "INT" => Ok(json!(row.try_get::<Option<i32>, _>(idx)?)),
+        "BIGINT" => Ok(json!(row.try_get::<Option<i64>, _>(idx)?)),
+        "TINYINT UNSIGNED" => Ok(json!(row.try_get::<Option<u8>, _>(idx)?)),
+        "SMALLINT UNSIGNED" => Ok(json!(row.try_get::<Option<u16>, _>(idx)?)),
+        "MEDIUMINT UNSIGNED" | "INT UNSIGNED" => Ok(json!(row.try_get::<Option<u32>, _>(idx)?)),
+        "BIGINT UNSIGNED" => Ok(json!(row.try_get::<Option<u64>, _>(idx)?)),
+
+        // 浮点数类型
+        "FLOAT" => Ok(json!(row.try_get::<Option<f32>, _>(idx)?)),
+        "DOUBLE" => Ok(json!(row.try_get::<Option<f64>, _>(idx)?)),
+
+        // 高精度数值
+        "DECIMAL" => {
+            let val: Option<BigDecimal> = row.try_get(idx)?;
+            match val {
+                Some(s) => Ok(json!(s.to_string())), // 转换为字符串避免精度丢失
+                None => Ok(json!(null)),
+            }
+        }
+
+        // 位类型, 按无符号整数读取
+        // 服务端不一定为 BIT 和 YEAR 设置 UNSIGNED 标志, 所以跳过类型兼容检查
+        "BIT" => Ok(json!(row.try_get_unchecked::<Option<u64>, _>(idx)?)),
+
+        // 年份类型
+        "YEAR" => Ok(json!(row.try_get_unchecked::<Option<u16>, _>(idx)?)),
+
+        // 字符串类型
+        "CHAR" | "VARCHAR" | "TINYTEXT" | "TEXT" | "MEDIUMTEXT" | "LONGTEXT" | "ENUM" => {
+            Ok(json!(row.try_get::<Option<String>, _>(idx)?))
+        }
+
+        // SET 的值是逗号分隔的文本, sqlx 的 String 不认为它兼容
+        "SET" => Ok(json!(row.try_get_unchecked::<Option<String>, _>(idx)?)),
+
+        // JSON 类型
+        "JSON" => {
+            let val: Option<serde_json::Value> = row.try_get(idx)?;
+            Ok(json!(val))
+        }
+
+        // 二进制数据
+        "BINARY" | "VARBINARY" | "TINYBLOB" | "BLOB" | "MEDIUMBLOB" | "LONGBLOB" | "GEOMETRY" => {
+            let val: Option<Vec<u8>> = row.try_get(idx)?;
+            match val {
+                Some(s) => Ok(json!(STANDARD.encode(&s))),
+                None => Ok(json!(null)),
+            }
+        }
+
+        // 时间日期类型
+        "DATE" => {
+            let val: Option<chrono::NaiveDate> = row.try_get(idx)?;
+            match val {
+                Some(s) => Ok(json!(s.format("%Y-%m-%d").to_string())),
+                None => Ok(json!(null)),
+            }
+        }
+        "TIME" => {
+            // TIME 可以是负数或超过 24 小时, 不能用 NaiveTime
+            let val: Option<MySqlTime> = row.try_get(idx)?;
+            match val {
+                Some(s) => Ok(json!(s.to_string())),
+                None => Ok(json!(null)),
+            }
+        }
+        "DATETIME" => {
+            let val: Option<chrono::NaiveDateTime> = row.try_get(idx)?;
+            match val {
+                Some(s) => Ok(json!(s.format("%Y-%m-%d %H:%M:%S%.f").to_string())),
+                None => Ok(json!(null)),
+            }
+        }
+        "TIMESTAMP" => {
+            let val: Option<chrono::DateTime<chrono::Utc>> = row.try_get(idx)?;
+            match val {
+                Some(s) => Ok(json!(s.to_rfc3339())),
+                None => Ok(json!(null)),
+            }
+        }
+
+        // 默认处理为字符串或标记不支持
+        _ => {
+            // 其它类型按照字符串处理
+            let val = row.try_get_unchecked::<Option<String>, _>(idx);
+            match val {
+                Ok(o) => match o {
+                    Some(s) => Ok(json!(s)),
+                    None => Ok(json!(null)),
+                },
+                Err(e) => Ok(json!(e.to_string())),
+            }
+        }
+    }
 }
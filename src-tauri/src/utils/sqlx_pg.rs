@@ -1,245 +1,253 @@
 use crate::types::QueryResult;
 use crate::utils::common::print_sql;
+use crate::utils::sqlx_common::{describe_columns, elapsed_ms, fetch_limited, resolve_columns};
+use crate::utils::sqlx_cursor::RowStream;
+use crate::utils::sqlx_error::DbError;
+use crate::utils::sqlx_pg_array::array_to_json;
+use crate::utils::sqlx_pg_custom::{
+    custom_to_json, invalidate_types, resolve_money_scale, resolve_types, FieldCountMismatch,
+    PgTypeMap,
+};
+use crate::utils::sqlx_pg_geometry::geometry_to_json;
+use crate::utils::sqlx_pg_range::{range_element, range_to_json};
+use crate::utils::sqlx_pg_value::{pg_type_name, raw_to_json, type_kind};
 use base64::engine::general_purpose::STANDARD;
 use base64::Engine;
 use bigdecimal::BigDecimal;
-use futures_util::StreamExt;
-use lazy_static::lazy_static;
+use futures_util::{StreamExt, TryStreamExt};
 use serde_json::json;
-use sqlx::postgres::{PgPool, PgRow};
+use sqlx::postgres::{PgConnection, PgPool, PgRow, PgTypeKind};
 use sqlx::types::chrono;
-use sqlx::Error as SqlxError;
 use sqlx::Row;
-use sqlx::TypeInfo;
-use sqlx::{Column, Execute};
-use std::collections::HashMap;
-use std::pin::Pin;
+use sqlx::ValueRef;
+use sqlx::{Column, Execute, Executor};
 use std::sync::Arc;
-use tokio::sync::Mutex as TokioMutex;
-
-lazy_static! {
-    static ref CURSOR_CACHE: TokioMutex<HashMap<Arc<str>, CursorState>> =
-        TokioMutex::new(HashMap::new());
-}
-
-struct CursorState {
-    offset: usize,
-    stream: TokioMutex<
-        Option<Pin<Box<dyn futures_util::Stream<Item = Result<PgRow, SqlxError>> + Send>>>,
-    >,
-}
+use std::time::Instant;
 
 /// 处理 PostgreSQL 的查询 | Handling PostgreSQL queries
 ///
+/// 分页查询使用 `sqlx_cursor`
+/// Use `sqlx_cursor` for paginated queries
+///
 /// # 参数
+/// - `conn_name`: 连接名, 用于缓存自定义类型 | Connection name, used to cache custom types
+/// - `conn`: 执行查询的连接, 来自会话或连接池 | Connection to run the query on, from a session or the pool
 /// - `sql`: 要执行的 sql | The SQL to be executed"
-/// - `streaming`: 是否启用流式分页 | Whether to enable streaming pagination
-/// - `page`: 当前页码（从1开始） | Current page number (starting from 1)
-/// - `page_size`: 每页的条目数 | Number of entries per page
+/// - `max_rows`: 最多返回的行数, None 表示全部返回 | Maximum number of rows to return, None returns all
+/// - `prepared`: 是否使用预处理语句 | Whether to use prepared statements
 ///
 pub async fn query_pg(
-    pool: &PgPool,
-    sql: &str,
-    streaming: bool,
-    page: Option<usize>,
-    page_size: Option<usize>,
-) -> Result<QueryResult, Box<dyn std::error::Error>> {
-    if streaming {
-        // 流式查询
-        // Streaming pagination
-        stream_pagination(pool, sql, page.unwrap_or(1), page_size.unwrap_or(200)).await
-    } else {
-        // 一次性返回所有数据
-        // Return all data at once
-        fetch_all_data(pool, sql).await
-    }
-}
-
-// 流式查询
-// Streaming pagination
-async fn stream_pagination(
-    pool: &PgPool,
+    conn_name: &str,
+    conn: &mut PgConnection,
     sql: &str,
-    page: usize,
-    page_size: usize,
-) -> Result<QueryResult, Box<dyn std::error::Error>> {
-    let sql_key = Arc::from(sql);
-    let pool = Arc::new(pool.clone());
-
-    // 异步获取缓存锁
-    // Asynchronous retrieval of cache lock
-    let mut cache = CURSOR_CACHE.lock().await;
-
-    // 清空缓存，如果当前 sql 参数与缓存中的不同
-    // Clear cache, if the current SQL parameters are different from those in the cache
-    if !cache.is_empty() && (!cache.contains_key(&sql_key)) {
-        cache.clear();
-    }
-
-    // 获取或创建游标状态
-    // Retrieve or create cursor state
-    let state = cache
-        .entry(Arc::clone(&sql_key))
-        .or_insert_with(|| CursorState {
-            offset: 0,
-            stream: TokioMutex::new(None),
-        });
-
-    let target_offset = (page - 1) * page_size;
-
-    // 获取流锁
-    // Get stream lock
-    let mut stream_guard = state.stream.lock().await;
-
-    // 判断是否需要重置流
-    // Determine whether to reset the stream
-    let needs_reset = target_offset < state.offset || stream_guard.is_none();
-
-    if needs_reset {
-        // 创建新流（包含所有权的安全传递）
-        // Create a new stream (including secure transfer of ownership)
-        let sql_clone = Arc::clone(&sql_key);
-        let pool_clone = Arc::clone(&pool);
-
-        *stream_guard = Some(Box::pin(async_stream::stream! {
-            let query = sqlx::query(&*sql_clone);
-            #[cfg(debug_assertions)]
-            {
-                print_sql(query.sql(),7);
-            }
-
-            let mut stream = query
-                .persistent(true)
-                .fetch(&*pool_clone);
-
-            while let Some(row) = stream.next().await {
-                yield row;
-            }
-        }));
-
-        state.offset = 0;
-    }
-
-    // 调整偏移量
-    // Adjust offset
-    let stream = stream_guard.as_mut().unwrap();
-    let skip = target_offset.saturating_sub(state.offset);
-    for _ in 0..skip {
-        if let Some(row) = stream.next().await {
-            row.map_err(|e| format!("Stream error: {}", e))?;
-            state.offset += 1;
-        } else {
-            break;
-        }
+    max_rows: Option<usize>,
+    prepared: bool,
+) -> Result<QueryResult, DbError> {
+    let query = sqlx::query(sql);
+    #[cfg(debug_assertions)]
+    {
+        print_sql(query.sql(), 7);
     }
 
-    // 收集当前页数据
-    // Collect current page data
-    let mut page_rows = Vec::with_capacity(page_size);
-    for _ in 0..page_size {
-        match stream.next().await {
-            Some(Ok(row)) => {
-                page_rows.push(row);
-                state.offset += 1;
-            }
-            Some(Err(e)) => return Err(Box::new(e)),
-            None => break,
+    // 不使用预处理语句时直接执行 &str, 走文本协议, 也不能 describe
+    // Without prepared statements a plain &str is executed over the text protocol, describe isn't available either
+    let start = Instant::now();
+    let stream = if prepared {
+        query.fetch(&mut *conn)
+    } else {
+        conn.fetch(sql)
+    };
+    let (rows, truncated) = match max_rows {
+        Some(max) => fetch_limited(stream, max).await?,
+        None => (stream.try_collect().await?, false),
+    };
+    let elapsed_ms = elapsed_ms(start);
+
+    let described = if prepared {
+        describe_columns(conn.describe(sql).await)
+    } else {
+        None
+    };
+    let columns = resolve_columns(described, &rows);
+    let types = resolve_types(conn_name, conn, &rows).await?;
+    let money_scale = resolve_money_scale(conn_name, conn, &rows).await?;
+    let json_rows = match process_rows(&rows, &types, money_scale) {
+        // 缓存的复合类型已被修改, 重新查询类型后再转换一次
+        // A cached composite was altered, query the types again and convert once more
+        Err(e) if is_stale(&e) => {
+            invalidate_types(conn_name);
+            let types = resolve_types(conn_name, conn, &rows).await?;
+            process_rows(&rows, &types, money_scale)?
         }
-    }
-
-    let (column_names, json_rows) = process_rows(page_rows)?;
+        res => res?,
+    };
 
     Ok(QueryResult {
-        column_name: serde_json::to_string(&column_names)?,
-        data: serde_json::to_string(&json_rows)?,
+        columns,
+        rows: json_rows,
+        elapsed_ms,
+        truncated,
     })
 }
 
-// 一次性返回所有数据
-// Return all data at once
-async fn fetch_all_data(
-    pool: &PgPool,
-    sql: &str,
-) -> Result<QueryResult, Box<dyn std::error::Error>> {
-    let query = sqlx::query(sql);
-    #[cfg(debug_assertions)]
-    {
-        print_sql(query.sql(), 7);
-    }
+// 创建游标使用的行流
+// Create the row stream used by cursors
+pub fn row_stream(pool: PgPool, sql: Arc<str>, prepared: bool) -> RowStream<PgRow> {
+    Box::pin(async_stream::stream! {
+        let query = sqlx::query(&sql);
+        #[cfg(debug_assertions)]
+        {
+            print_sql(query.sql(), 7);
+        }
 
-    let rows = query.fetch_all(pool).await?;
-    let (column_names, json_rows) = process_rows(rows)?;
+        let mut stream = if prepared {
+            query.persistent(true).fetch(&pool)
+        } else {
+            pool.fetch(&*sql)
+        };
 
-    Ok(QueryResult {
-        column_name: serde_json::to_string(&column_names)?,
-        data: serde_json::to_string(&json_rows)?,
+        while let Some(row) = stream.next().await {
+            yield row;
+        }
     })
 }
 
-fn process_rows(
-    rows: Vec<PgRow>,
-) -> Result<(Vec<String>, Vec<serde_json::Value>), Box<dyn std::error::Error>> {
+// 把结果行转为按列顺序排列的 json 数组, sqlx 没有解析的类型按 resolve_types 查到的类型转换
+// Convert result rows to JSON arrays in column order, types sqlx hasn't resolved are converted by those from resolve_types
+pub fn process_rows(
+    rows: &[PgRow],
+    types: &PgTypeMap,
+    money_scale: u32,
+) -> Result<Vec<Vec<serde_json::Value>>, sqlx::Error> {
     let mut json_rows = Vec::with_capacity(rows.len());
-    let mut column_names = Vec::new();
-    let mut is_first_row = true;
 
     for row in rows {
-        let mut json_row = json!({});
-        for (idx, column) in row.columns().iter().enumerate() {
-            let col_name = column.name();
-            json_row[col_name] = convert_value_pg(&row, idx)?;
-
-            if is_first_row {
-                column_names.push(col_name.to_owned());
-            }
+        let mut json_row = Vec::with_capacity(row.len());
+        for idx in 0..row.len() {
+            json_row.push(convert_value_pg(row, idx, types, money_scale)?);
         }
         json_rows.push(json_row);
-        is_first_row = false;
     }
 
-    Ok((column_names, json_rows))
+    Ok(json_rows)
+}
+
+/// 转换失败是否因为缓存的类型已经过期, 是时用 invalidate_types 丢弃缓存后重新查询类型
+/// Whether the conversion failed because the cached types are stale,
+/// if so drop the cache with invalidate_types and query the types again
+pub fn is_stale(e: &sqlx::Error) -> bool {
+    matches!(e, sqlx::Error::Decode(source) if source.is::<FieldCountMismatch>())
 }
 
 // 把数据库类型转为 json 类型
 // Convert database type to JSON type
-fn convert_value_pg(row: &PgRow, idx: usize) -> Result<serde_json::Value, sqlx::Error> {
+fn convert_value_pg(
+    row: &PgRow,
+    idx: usize,
+    types: &PgTypeMap,
+    money_scale: u32,
+) -> Result<serde_json::Value, sqlx::Error> {
     let column = row.columns().get(idx).unwrap();
-    let col_name = column.name();
-    let type_name = column.type_info().name();
+    let type_name = pg_type_name(column.type_info());
 
     // 参考: /xxx/cargo/registry/src/mirrors.tuna.tsinghua.edu.cn-e791a3f93f26854f/sqlx-postgres-0.8.3/src/type_info.rs
     // 里的 pub(crate) fn display_name(&self) -> &str { 部分
     //
     // 特别注意: 必须使用 Option 处理, 用于针对 NULL 的值
     //
+
+    // 范围和多范围类型, 多范围类型通过文本协议查询时没有类型名, 所以按类型信息判断, 错误放进单元格
+    // Range and multirange types, multiranges have no type name over the text protocol so the type info is checked,
+    // errors go into the cell
+    if let Some((element, multirange)) = range_element(column.type_info()) {
+        let value = row.try_get_raw(idx)?;
+        if value.is_null() {
+            return Ok(json!(null));
+        }
+        let format = value.format();
+        let res = value
+            .as_bytes()
+            .and_then(|bytes| range_to_json(element, multirange, format, bytes));
+        return Ok(cell_value(idx, res));
+    }
+
+    // 自定义类型: 枚举转为标签, 域按基础类型转换, 复合类型转为对象, 错误和数组一样放进单元格
+    // 通过文本协议查询时类型只有 oid, 按 pg_type 中查到的类型转换, 查到的类型过期时返回错误, 由调用方重新查询
+    // Custom types: enums become labels, domains are converted by their base type, composites become objects,
+    // errors go into the cell as for arrays
+    // Over the text protocol the type is just an oid, converted by the type looked up in pg_type,
+    // stale looked up types return an error so the caller queries them again
+    let info = column.type_info();
+    let looked_up = match (type_kind(info), info.oid()) {
+        (None, Some(oid)) if types.contains_key(&oid.0) => Some(oid.0),
+        _ => None,
+    };
+    let custom = matches!(
+        type_kind(info),
+        Some(PgTypeKind::Enum(_) | PgTypeKind::Domain(_) | PgTypeKind::Composite(_))
+    );
+    if looked_up.is_some() || custom {
+        let value = row.try_get_raw(idx)?;
+        if value.is_null() {
+            return Ok(json!(null));
+        }
+        let format = value.format();
+        let res = value.as_bytes().and_then(|bytes| match looked_up {
+            Some(oid) => custom_to_json(types, oid, format, bytes, money_scale),
+            None => raw_to_json(info, format, bytes, money_scale),
+        });
+        return match res {
+            Err(e) if looked_up.is_some() && e.is::<FieldCountMismatch>() => {
+                Err(sqlx::Error::Decode(e))
+            }
+            res => Ok(cell_value(idx, res)),
+        };
+    }
+
+    // 数组类型, 元素按原始字节转换, 不支持的元素类型和其它类型一样把错误放进单元格
+    // Array types, the elements are converted from their raw bytes,
+    // unsupported element types put the error into the cell like other types
+    if let Some(PgTypeKind::Array(element)) = type_kind(column.type_info()) {
+        let value = row.try_get_raw(idx)?;
+        if value.is_null() {
+            return Ok(json!(null));
+        }
+        let format = value.format();
+        let res = value
+            .as_bytes()
+            .and_then(|bytes| array_to_json(element, format, bytes, money_scale));
+        return Ok(cell_value(idx, res));
+    }
+
     match type_name {
         // TODO: 补充单一类型:
         // Char 类型是 "\"CHAR\"" ???
         // Bpchar 的是 CHAR
         // BIT INTERVAL JSONPATH MONEY OID RECORD TIMETZ UNKNOWN VARBIT VARCHAR VOID
-        // ========== 几何类型: POINT LINE LSEG BOX PATH POLYGON CIRCLE
-        // POINT
+        // BIT INTERVAL MONEY OID TIMETZ VARBIT 已添加, XML TSVECTOR TSQUERY 也已添加, 见 sqlx_pg_value
+        // ========== 几何类型: 已添加, 见 sqlx_pg_geometry
+        // POINT 已添加
         // 表示平面上的一个点，存储为 (x, y) 坐标。
         // 示例：(1.0, 2.0)。
-        // LINE
+        // LINE 已添加
         // 表示一条无限长的直线，存储为 Ax + By + C = 0 的系数。
         // 示例：{A: 1, B: -1, C: 0}。
-        // LSEG
+        // LSEG 已添加
         // 表示一条线段，存储为两个端点 (x1, y1) 和 (x2, y2)。
         // 示例：[(1.0, 2.0), (3.0, 4.0)]。
-        // BOX
+        // BOX 已添加
         // 表示一个矩形框，存储为两个对角点 (x1, y1) 和 (x2, y2)。
         // 示例：(1.0, 2.0), (3.0, 4.0)。
-        // PATH
+        // PATH 已添加
         // 表示一个路径，可以是闭合的（多边形）或开放的（折线）。
         // 示例：[(1.0, 2.0), (3.0, 4.0), (5.0, 6.0)]。
-        // POLYGON
+        // POLYGON 已添加
         // 表示一个多边形，存储为一系列点。
         // 示例：((1.0, 2.0), (3.0, 4.0), (5.0, 6.0))。
-        // CIRCLE
+        // CIRCLE 已添加
         // 表示一个圆，存储为中心点和半径 (x, y, r)。
         // 示例：<(1.0, 2.0), 3.0>。
-        // ========== 网络地址类型: MACADDR8
+        // ========== 网络地址类型: 已添加
         // INET 已添加
         // 表示 IPv4 或 IPv6 地址。
         // 示例：192.168.1.1 或 ::1。
@@ -249,29 +257,29 @@ fn convert_value_pg(row: &PgRow, idx: usize) -> Result<serde_json::Value, sqlx::
         // MACADDR 已添加
         // 表示 MAC 地址（硬件地址）。
         // 示例：08:00:2b:01:02:03。
-        // MACADDR8
+        // MACADDR8 已添加
         // 表示扩展的 MAC 地址（EUI-64 格式）。
         // 示例：08:00:2b:01:02:03:04:05。
-        // ========== 范围类型: DATERANGE INT4RANGE INT8RANGE NUMRANGE TSRANGE TSTZRANGE
-        // INT4RANGE
+        // ========== 范围类型: 已添加, 多范围类型也已添加, 见 sqlx_pg_range
+        // INT4RANGE 已添加
         // 表示一个整数范围（int4）。
         // 示例：[1, 10)。
-        // INT8RANGE
+        // INT8RANGE 已添加
         // 表示一个大整数范围（int8）。
         // 示例：[100, 1000]。
-        // NUMRANGE
+        // NUMRANGE 已添加
         // 表示一个数值范围（numeric）。
         // 示例：[1.5, 10.5)。
-        // TSRANGE
+        // TSRANGE 已添加
         // 表示一个时间戳范围（不带时区）。
         // 示例：['2023-01-01 00:00:00', '2023-12-31 23:59:59']。
-        // TSTZRANGE
+        // TSTZRANGE 已添加
         // 表示一个带时区的时间戳范围。
         // 示例：['2023-01-01 00:00:00 UTC', '2023-12-31 23:59:59 UTC']。
-        // DATERANGE
+        // DATERANGE 已添加
         // 表示一个日期范围。
         // 示例：['2023-01-01', '2023-12-31']。
-        // TODO: 补充数组类型
+        // 数组类型: 已添加, 见 sqlx_pg_array
         // BIT[] BOOL[] BOX[] BYTEA[] CIDR[] CIRCLE[] DATE[] DATERANGE[] FLOAT4[] FLOAT8[] INET[] INT2[] INT4[] INT4RANGE[] INT8[]
         // INT8RANGE[] INTERVAL[] JSON[] JSONB[] JSONPATH[] LINE[] LSEG[] MACADDR[] MACADDR8[] MONEY[] NAME[] NUMERIC[] NUMRANGE[]
         // OID[] PATH[] POINT[] POLYGON[] RECORD[] TIME[] TIMESTAMP[] TIMESTAMPTZ[] TIMETZ[] TSRANGE[] TSTZRANGE[] UUID[] VARBIT[] VARCHAR[]
@@ -279,20 +287,20 @@ fn convert_value_pg(row: &PgRow, idx: usize) -> Result<serde_json::Value, sqlx::
         // BpcharArray 的是 CHAR[]
 
         // 布尔类型
-        "BOOL" => Ok(json!(row.get::<Option<bool>, _>(col_name))),
+        "BOOL" => Ok(json!(row.get::<Option<bool>, _>(idx))),
 
         // 整数类型
-        "INT2" => Ok(json!(row.get::<Option<i16>, _>(col_name))),
-        "INT4" => Ok(json!(row.get::<Option<i32>, _>(col_name))),
-        "INT8" => Ok(json!(row.get::<Option<i64>, _>(col_name))),
+        "INT2" => Ok(json!(row.get::<Option<i16>, _>(idx))),
+        "INT4" => Ok(json!(row.get::<Option<i32>, _>(idx))),
+        "INT8" => Ok(json!(row.get::<Option<i64>, _>(idx))),
 
         // 浮点数类型
-        "FLOAT4" => Ok(json!(row.get::<Option<f32>, _>(col_name))),
-        "FLOAT8" => Ok(json!(row.get::<Option<f64>, _>(col_name))),
+        "FLOAT4" => Ok(json!(row.get::<Option<f32>, _>(idx))),
+        "FLOAT8" => Ok(json!(row.get::<Option<f64>, _>(idx))),
 
         // 高精度数值
         "NUMERIC" => {
-            let val: Option<BigDecimal> = row.get(col_name);
+            let val: Option<BigDecimal> = row.get(idx);
             match val {
                 Some(s) => Ok(json!(s.to_string())), // 转换为字符串避免精度丢失
                 None => Ok(json!(null)),
@@ -300,17 +308,17 @@ fn convert_value_pg(row: &PgRow, idx: usize) -> Result<serde_json::Value, sqlx::
         }
 
         // 字符串类型
-        "TEXT" | "VARCHAR" | "BPCHAR" | "NAME" => Ok(json!(row.get::<Option<String>, _>(col_name))),
+        "TEXT" | "VARCHAR" | "BPCHAR" | "NAME" => Ok(json!(row.get::<Option<String>, _>(idx))),
 
         // JSON 类型
         "JSON" | "JSONB" => {
-            let val: Option<serde_json::Value> = row.get(col_name);
+            let val: Option<serde_json::Value> = row.get(idx);
             Ok(json!(val))
         }
 
         // 二进制数据
         "BYTEA" => {
-            let val: Option<Vec<u8>> = row.get(col_name);
+            let val: Option<Vec<u8>> = row.get(idx);
             match val {
                 Some(s) => Ok(json!(STANDARD.encode(&s))),
                 None => Ok(json!(null)),
@@ -319,28 +327,21 @@ fn convert_value_pg(row: &PgRow, idx: usize) -> Result<serde_json::Value, sqlx::
 
         // 时间日期类型
         "DATE" => {
-            let val: Option<chrono::NaiveDate> = row.get(col_name);
+            let val: Option<chrono::NaiveDate> = row.get(idx);
             match val {
                 Some(s) => Ok(json!(s.format("%Y-%m-%d").to_string())),
                 None => Ok(json!(null)),
             }
         }
-        "TIME" => {
-            let val: Option<chrono::NaiveTime> = row.get(col_name);
-            match val {
-                Some(s) => Ok(json!(s.format("%H:%M:%S").to_string())),
-                None => Ok(json!(null)),
-            }
-        }
         "TIMESTAMP" => {
-            let val: Option<chrono::NaiveDateTime> = row.get(col_name);
+            let val: Option<chrono::NaiveDateTime> = row.get(idx);
             match val {
                 Some(s) => Ok(json!(s.format("%Y-%m-%d %H:%M:%S").to_string())),
                 None => Ok(json!(null)),
             }
         }
         "TIMESTAMPTZ" => {
-            let val: Option<chrono::DateTime<chrono::Utc>> = row.get(col_name);
+            let val: Option<chrono::DateTime<chrono::Utc>> = row.get(idx);
             match val {
                 Some(s) => Ok(json!(s.to_rfc3339())),
                 None => Ok(json!(null)),
@@ -349,7 +350,7 @@ fn convert_value_pg(row: &PgRow, idx: usize) -> Result<serde_json::Value, sqlx::
 
         // UUID 类型
         "UUID" => {
-            let val: Option<uuid::Uuid> = row.get(col_name);
+            let val: Option<uuid::Uuid> = row.get(idx);
             match val {
                 Some(s) => Ok(json!(s.to_string())),
                 None => Ok(json!(null)),
@@ -357,27 +358,44 @@ fn convert_value_pg(row: &PgRow, idx: usize) -> Result<serde_json::Value, sqlx::
         }
 
         // 网络类型
-        "INET" | "CIDR" | "MACADDR" => Ok(json!(row.get::<Option<String>, _>(col_name))),
-
-        // 数组类型
-        "INT2[]" => Ok(json!(row.get::<Option<Vec<i16>>, _>(col_name))),
-        "INT4[]" => Ok(json!(row.get::<Option<Vec<i32>>, _>(col_name))),
-        "INT8[]" => Ok(json!(row.get::<Option<Vec<i64>>, _>(col_name))),
+        "INET" | "CIDR" | "MACADDR" => Ok(json!(row.get::<Option<String>, _>(idx))),
 
-        // 浮点数类型
-        "FLOAT4[]" => Ok(json!(row.get::<Option<Vec<f32>>, _>(col_name))),
-        "FLOAT8[]" => Ok(json!(row.get::<Option<Vec<f64>>, _>(col_name))),
+        // 几何类型
+        "POINT" | "LINE" | "LSEG" | "BOX" | "PATH" | "POLYGON" | "CIRCLE" => {
+            let value = row.try_get_raw(idx)?;
+            if value.is_null() {
+                return Ok(json!(null));
+            }
+            let format = value.format();
+            let res = value
+                .as_bytes()
+                .and_then(|bytes| geometry_to_json(type_name, format, bytes));
+            Ok(cell_value(idx, res))
+        }
 
-        // 文本类型
-        "TEXT[]" | "VARCHAR[]" | "BPCHAR[]" | "NAME[]" => {
-            Ok(json!(row.get::<Option<Vec<Option<String>>>, _>(col_name)))
+        // sqlx 没有对应 Rust 类型或者解码结果不适合直接展示的类型, 按原始字节转换, 错误放进单元格
+        // TIME 也在这里, NaiveTime 不能表示 24:00:00
+        // Types sqlx has no Rust type for, or whose decoded value doesn't display well, converted from raw bytes,
+        // errors go into the cell
+        // TIME is here as well, NaiveTime can't represent 24:00:00
+        "TIME" | "INTERVAL" | "MONEY" | "TIMETZ" | "BIT" | "VARBIT" | "MACADDR8" | "OID"
+        | "XML" | "TSVECTOR" | "TSQUERY" => {
+            let value = row.try_get_raw(idx)?;
+            if value.is_null() {
+                return Ok(json!(null));
+            }
+            let format = value.format();
+            let res = value
+                .as_bytes()
+                .and_then(|bytes| raw_to_json(column.type_info(), format, bytes, money_scale));
+            Ok(cell_value(idx, res))
         }
 
         // 默认处理为字符串或标记不支持
         _ => {
             // log::warn!("Unsupported PostgreSQL type: {}", type_name);
             // 其它类型按照字符串处理
-            let val = row.try_get::<Option<String>, _>(col_name);
+            let val = row.try_get::<Option<String>, _>(idx);
             match val {
                 Ok(o) => match o {
                     Some(s) => Ok(json!(s)),
@@ -388,3 +406,18 @@ fn convert_value_pg(row: &PgRow, idx: usize) -> Result<serde_json::Value, sqlx::
         }
     }
 }
+
+// 按原始字节转换失败时把错误放进单元格, 不让整个查询失败
+// When converting from raw bytes fails the error goes into the cell instead of failing the whole query
+fn cell_value(
+    idx: usize,
+    res: Result<serde_json::Value, sqlx::error::BoxDynError>,
+) -> serde_json::Value {
+    res.unwrap_or_else(|source| {
+        let err = sqlx::Error::ColumnDecode {
+            index: idx.to_string(),
+            source,
+        };
+        json!(err.to_string())
+    })
+}
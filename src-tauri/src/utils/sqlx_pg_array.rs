@@ -0,0 +1,288 @@
+use crate::utils::sqlx_pg_value::{raw_to_json, read_bytes, read_i32};
+use serde_json::{json, Value};
+use sqlx::error::BoxDynError;
+use sqlx::postgres::{PgTypeInfo, PgValueFormat};
+use sqlx::TypeInfo;
+use std::iter::Peekable;
+use std::str::Chars;
+
+type ElementFn<'a> = dyn Fn(PgValueFormat, &[u8]) -> Result<Value, BoxDynError> + 'a;
+
+/// 把任意元素类型的数组转为 json | Convert an array of any element type to JSON
+///
+/// 多维数组转为嵌套的数组, NULL 元素为 null, 元素按 raw_to_json 转换
+/// 下标不从 1 开始时转为 `{"lowerBounds": [0], "elements": [...]}`, 每一维一个下界
+///
+/// Multidimensional arrays become nested arrays, NULL elements are null, elements are converted by raw_to_json
+/// When the subscripts don't start at 1 it becomes `{"lowerBounds": [0], "elements": [...]}`, one lower bound per dimension
+///
+/// # 参数
+/// - `element`: 元素类型 | Element type
+/// - `format`: 值的格式 | Format of the value
+/// - `bytes`: 值的原始字节, 不能为 NULL | Raw bytes of the value, must not be NULL
+/// - `money_scale`: 二进制格式的 MONEY 的小数位数 | Scale of MONEY in the binary format
+///
+pub fn array_to_json(
+    element: &PgTypeInfo,
+    format: PgValueFormat,
+    bytes: &[u8],
+    money_scale: u32,
+) -> Result<Value, BoxDynError> {
+    // BOX 的文本中含有逗号, 所以用分号分隔 | The text of a BOX contains commas, so semicolons separate them
+    let delimiter = if element.name() == "BOX" { ';' } else { ',' };
+    array_to_json_with(delimiter, format, bytes, &|format, bytes| {
+        raw_to_json(element, format, bytes, money_scale)
+    })
+}
+
+/// 把数组转为 json, 元素由 `element` 转换, 用于 sqlx 没有解析的元素类型
+/// Convert an array to JSON with the elements converted by `element`, for element types sqlx hasn't resolved
+///
+/// # 参数
+/// - `delimiter`: 文本格式中元素的分隔符 | Element delimiter in the text format
+/// - `format`: 值的格式 | Format of the value
+/// - `bytes`: 值的原始字节, 不能为 NULL | Raw bytes of the value, must not be NULL
+/// - `element`: 按格式和原始字节转换一个元素 | Converts one element from its format and raw bytes
+///
+pub fn array_to_json_with(
+    delimiter: char,
+    format: PgValueFormat,
+    bytes: &[u8],
+    element: &ElementFn,
+) -> Result<Value, BoxDynError> {
+    let (elements, lower_bounds) = match format {
+        PgValueFormat::Binary => binary_array(element, bytes)?,
+        PgValueFormat::Text => text_array(element, delimiter, std::str::from_utf8(bytes)?)?,
+    };
+
+    if lower_bounds.iter().all(|&lower| lower == 1) {
+        Ok(elements)
+    } else {
+        Ok(json!({ "lowerBounds": lower_bounds, "elements": elements }))
+    }
+}
+
+// 二进制格式: 维数, 标记, 元素 oid, 每一维的长度和下界, 然后是所有元素, 每个元素是 4 字节长度 (-1 为 NULL) 加值
+// Binary format: dimension count, flags, element oid, the length and lower bound of each dimension,
+// then all elements, each a 4 byte length (-1 for NULL) and the value
+fn binary_array(element: &ElementFn, bytes: &[u8]) -> Result<(Value, Vec<i32>), BoxDynError> {
+    let mut reader = bytes;
+    let ndim = read_i32(&mut reader)?;
+    read_bytes(&mut reader, 8)?;
+
+    let mut lengths = Vec::new();
+    let mut lower_bounds = Vec::new();
+    for _ in 0..ndim {
+        lengths.push(usize::try_from(read_i32(&mut reader)?)?);
+        lower_bounds.push(read_i32(&mut reader)?);
+    }
+
+    let elements = binary_elements(element, &lengths, &mut reader)?;
+    if !reader.is_empty() {
+        return Err("unexpected trailing data in array value".into());
+    }
+    Ok((elements, lower_bounds))
+}
+
+fn binary_elements(
+    element: &ElementFn,
+    lengths: &[usize],
+    reader: &mut &[u8],
+) -> Result<Value, BoxDynError> {
+    let values = match lengths {
+        [] => Vec::new(),
+        [len] => (0..*len)
+            .map(|_| match read_i32(reader)? {
+                -1 => Ok(Value::Null),
+                size => element(PgValueFormat::Binary, read_bytes(reader, size)?),
+            })
+            .collect::<Result<_, BoxDynError>>()?,
+        [len, inner @ ..] => (0..*len)
+            .map(|_| binary_elements(element, inner, reader))
+            .collect::<Result<_, _>>()?,
+    };
+    Ok(Value::Array(values))
+}
+
+// 文本格式: 可选的下标范围前缀例如 [0:1]={1,2}, 然后是花括号嵌套的元素
+// 元素可能带双引号, 引号内用反斜杠转义, 不带引号的 NULL 表示 NULL
+// Text format: an optional subscript prefix such as [0:1]={1,2}, then the elements nested in braces
+// Elements may be double quoted with backslash escapes inside the quotes, an unquoted NULL means NULL
+fn text_array(
+    element: &ElementFn,
+    delimiter: char,
+    text: &str,
+) -> Result<(Value, Vec<i32>), BoxDynError> {
+    let text = text.trim();
+    let (lower_bounds, body) = match text.split_once('=') {
+        Some((dims, body)) if dims.starts_with('[') => {
+            let lower_bounds = dims
+                .trim_start_matches('[')
+                .trim_end_matches(']')
+                .split("][")
+                .map(|dim| {
+                    let lower = dim.split(':').next().unwrap_or_default();
+                    lower
+                        .trim()
+                        .parse::<i32>()
+                        .map_err(|_| format!("invalid array dimensions '{}'", dims))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            (lower_bounds, body)
+        }
+        _ => (Vec::new(), text),
+    };
+
+    let mut chars = body.trim().chars().peekable();
+    let elements = text_elements(element, delimiter, &mut chars)?;
+    if chars.any(|c| !c.is_whitespace()) {
+        return Err(format!("unexpected trailing data in array '{}'", text).into());
+    }
+    Ok((elements, lower_bounds))
+}
+
+fn text_elements(
+    element: &ElementFn,
+    delimiter: char,
+    chars: &mut Peekable<Chars>,
+) -> Result<Value, BoxDynError> {
+    if chars.next() != Some('{') {
+        return Err("array value must start with '{'".into());
+    }
+
+    let mut values = Vec::new();
+    loop {
+        skip_whitespace(chars);
+        match chars.peek() {
+            Some('}') if values.is_empty() => {
+                chars.next();
+                break;
+            }
+            Some('{') => values.push(text_elements(element, delimiter, chars)?),
+            Some(_) => {
+                let (text, quoted) = text_element(delimiter, chars)?;
+                if !quoted && text.eq_ignore_ascii_case("NULL") {
+                    values.push(Value::Null);
+                } else {
+                    values.push(element(PgValueFormat::Text, text.as_bytes())?);
+                }
+            }
+            None => return Err("unterminated array value".into()),
+        }
+
+        skip_whitespace(chars);
+        match chars.next() {
+            Some(c) if c == delimiter => {}
+            Some('}') => break,
+            other => return Err(format!("unexpected {:?} in array value", other).into()),
+        }
+    }
+
+    Ok(Value::Array(values))
+}
+
+// 读取一个元素, 直到分隔符或 }, 不消耗结束符, 同时返回是否带引号
+// Read one element up to the delimiter or }, without consuming the terminator, also returns whether it was quoted
+fn text_element(
+    delimiter: char,
+    chars: &mut Peekable<Chars>,
+) -> Result<(String, bool), BoxDynError> {
+    let mut text = String::new();
+    let quoted = chars.peek() == Some(&'"');
+    let mut in_quotes = false;
+
+    while let Some(&c) = chars.peek() {
+        if !in_quotes && (c == delimiter || c == '}') {
+            break;
+        }
+        chars.next();
+        match c {
+            '"' => in_quotes = !in_quotes,
+            '\\' => text.extend(chars.next()),
+            _ => text.push(c),
+        }
+    }
+
+    if in_quotes {
+        return Err("unterminated quoted array element".into());
+    }
+    if !quoted {
+        text.truncate(text.trim_end().len());
+    }
+    Ok((text, quoted))
+}
+
+fn skip_whitespace(chars: &mut Peekable<Chars>) {
+    while chars.next_if(|c| c.is_whitespace()).is_some() {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::sqlx_pg_value::named_to_json;
+
+    fn array(type_name: &str, delimiter: char, format: PgValueFormat, bytes: &[u8]) -> Value {
+        array_to_json_with(delimiter, format, bytes, &|format, bytes| {
+            named_to_json(type_name, format, bytes, 2)
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn binary_arrays() {
+        // array_send('{{1,NULL},{3,4}}'::int4[])
+        let bytes = b"\x00\x00\x00\x02\x00\x00\x00\x01\x00\x00\x00\x17\x00\x00\x00\x02\x00\x00\x00\x01\x00\x00\x00\x02\x00\x00\x00\x01\x00\x00\x00\x04\x00\x00\x00\x01\xff\xff\xff\xff\x00\x00\x00\x04\x00\x00\x00\x03\x00\x00\x00\x04\x00\x00\x00\x04";
+        assert_eq!(
+            array("INT4", ',', PgValueFormat::Binary, bytes),
+            json!([[1, null], [3, 4]])
+        );
+
+        // array_send('[0:1]={1,2}'::int4[])
+        let bytes = b"\x00\x00\x00\x01\x00\x00\x00\x00\x00\x00\x00\x17\x00\x00\x00\x02\x00\x00\x00\x00\x00\x00\x00\x04\x00\x00\x00\x01\x00\x00\x00\x04\x00\x00\x00\x02";
+        assert_eq!(
+            array("INT4", ',', PgValueFormat::Binary, bytes),
+            json!({ "lowerBounds": [0], "elements": [1, 2] })
+        );
+
+        // 空数组没有维度 | An empty array has no dimensions
+        let bytes = b"\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x17";
+        assert_eq!(array("INT4", ',', PgValueFormat::Binary, bytes), json!([]));
+    }
+
+    #[test]
+    fn text_arrays() {
+        assert_eq!(
+            array(
+                "TEXT",
+                ',',
+                PgValueFormat::Text,
+                br#"{"a,b",NULL,"NULL","c\"d", e f }"#
+            ),
+            json!(["a,b", null, "NULL", "c\"d", "e f"])
+        );
+        assert_eq!(
+            array("INT4", ',', PgValueFormat::Text, b"[0:1][1:1]={{1},{2}}"),
+            json!({ "lowerBounds": [0, 1], "elements": [[1], [2]] })
+        );
+        assert_eq!(
+            array(
+                "BOX",
+                ';',
+                PgValueFormat::Text,
+                b"{(3,4),(1,2);(5,6),(0,0)}"
+            ),
+            json!([
+                { "high": { "x": 3.0, "y": 4.0 }, "low": { "x": 1.0, "y": 2.0 } },
+                { "high": { "x": 5.0, "y": 6.0 }, "low": { "x": 0.0, "y": 0.0 } }
+            ])
+        );
+        assert_eq!(array("INT4", ',', PgValueFormat::Text, b"{}"), json!([]));
+
+        for text in ["1,2", "{1,2", r#"{"a}"#, "{1,2}x"] {
+            let res = array_to_json_with(',', PgValueFormat::Text, text.as_bytes(), &|f, b| {
+                named_to_json("TEXT", f, b, 2)
+            });
+            assert!(res.is_err(), "{}", text);
+        }
+    }
+}
@@ -0,0 +1,618 @@
+use crate::utils::sqlx_error::DbError;
+use crate::utils::sqlx_pg_array::array_to_json_with;
+use crate::utils::sqlx_pg_range::range_element;
+use crate::utils::sqlx_pg_value::{
+    money_scale, named_to_json, pg_type_name, read_bytes, read_i32, type_kind,
+};
+use once_cell::sync::Lazy;
+use serde_json::Value;
+use sqlx::error::BoxDynError;
+use sqlx::postgres::{PgConnection, PgPool, PgRow, PgTypeInfo, PgTypeKind, PgValueFormat};
+use sqlx::{Column, Executor, Row};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+/// pg_type 中查到的类型 | A type looked up in pg_type
+#[derive(Debug, Clone)]
+pub enum PgCustomType {
+    Enum,
+    // 基础类型的 oid | oid of the base type
+    Domain(u32),
+    // 字段名和字段类型的 oid | Field names and the oids of the field types
+    Composite(Vec<(String, u32)>),
+    // 元素类型的 oid 和文本格式中的分隔符 | oid of the element type and the delimiter in the text format
+    Array(u32, char),
+    // 其它类型, 保存大写的类型名, 例如 INT4 | Other types, keeps the upper case type name, e.g. INT4
+    Base(String),
+}
+
+/// oid 到类型的映射 | Map from oid to type
+pub type PgTypeMap = HashMap<u32, PgCustomType>;
+
+/// 复合类型的值和类型的字段数不一致, 通常是类型在缓存后被修改
+/// A composite value doesn't have as many fields as its type, usually the type was altered after it was cached
+#[derive(Debug)]
+pub struct FieldCountMismatch {
+    pub expected: usize,
+    pub actual: usize,
+}
+
+impl fmt::Display for FieldCountMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "composite value has {} fields, the type has {}",
+            self.actual, self.expected
+        )
+    }
+}
+
+impl std::error::Error for FieldCountMismatch {}
+
+// 按连接名缓存的类型, 查到新的类型时整体替换, 转换结果时不用持有锁
+// Types cached by connection name, replaced as a whole when new types are found,
+// so the lock isn't held while converting results
+static PG_TYPES: Lazy<Mutex<HashMap<String, Arc<PgTypeMap>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+// 按连接名缓存的 MONEY 小数位数, 由连接的 lc_monetary 决定
+// Scale of MONEY cached by connection name, decided by the lc_monetary of the connection
+static MONEY_SCALES: Lazy<Mutex<HashMap<String, u32>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+// 还没有查到小数位数时使用的常见值 | The common scale used until the real one is known
+const DEFAULT_MONEY_SCALE: u32 = 2;
+
+/// 查找结果中 sqlx 没有解析的类型, 优先使用缓存, 缺少的从 pg_type 查询后加入连接的缓存
+/// Look up the types of the result sqlx hasn't resolved, the cache is used first,
+/// missing types are queried from pg_type and added to the cache of the connection
+///
+/// 使用预处理语句时 sqlx 会自己解析自定义类型, 只有通过文本协议查询时列的类型只有 oid
+/// 域, 复合类型的字段和数组的元素引用的类型也会一起查询
+///
+/// With prepared statements sqlx resolves custom types itself, only over the text protocol the columns have just an oid
+/// The types referenced by domains, composite fields and array elements are queried as well
+///
+/// # 参数
+/// - `conn_name`: 连接名, 缓存按连接名保存 | Connection name, the cache is kept by connection name
+/// - `conn`: 查询 pg_type 使用的连接 | Connection used to query pg_type
+/// - `rows`: 查询结果 | The query result
+///
+pub async fn resolve_types(
+    conn_name: &str,
+    conn: &mut PgConnection,
+    rows: &[PgRow],
+) -> Result<Arc<PgTypeMap>, DbError> {
+    let types = cached_types(conn_name);
+    let missing = missing_oids(&types, rows);
+    if missing.is_empty() {
+        return Ok(types);
+    }
+
+    let found = fetch_types(conn, &types, missing).await?;
+    Ok(store_types(conn_name, found))
+}
+
+/// 和 resolve_types 相同, 但从连接池中获取连接, 用于游标
+/// Same as resolve_types, but takes the connection from the pool, used by cursors
+///
+/// 游标的行流占用着一个连接, 只在有空闲连接时查询 pg_type, 否则只使用缓存, 避免连接池满时一直等待
+/// The row stream of the cursor holds a connection, pg_type is only queried when a connection is idle,
+/// otherwise only the cache is used, so a full pool doesn't block
+///
+pub async fn resolve_types_in_pool(
+    conn_name: &str,
+    pool: &PgPool,
+    rows: &[PgRow],
+) -> Result<Arc<PgTypeMap>, DbError> {
+    let types = cached_types(conn_name);
+    let missing = missing_oids(&types, rows);
+    if missing.is_empty() {
+        return Ok(types);
+    }
+
+    match pool.try_acquire() {
+        Some(mut conn) => {
+            let found = fetch_types(&mut conn, &types, missing).await?;
+            Ok(store_types(conn_name, found))
+        }
+        None => Ok(types),
+    }
+}
+
+/// 二进制格式的 MONEY 的小数位数, 优先使用缓存, 没有缓存时用 `'1'::money` 的文本推算
+/// Scale of MONEY in the binary format, the cache is used first, otherwise it is derived from the text of `'1'::money`
+///
+/// 只有结果中有二进制格式且可能含有 MONEY 的列时才查询
+/// Only queried when the result has a column in the binary format that may contain MONEY
+///
+/// # 参数
+/// - `conn_name`: 连接名, 缓存按连接名保存 | Connection name, the cache is kept by connection name
+/// - `conn`: 查询使用的连接 | Connection used for the query
+/// - `rows`: 查询结果 | The query result
+///
+pub async fn resolve_money_scale(
+    conn_name: &str,
+    conn: &mut PgConnection,
+    rows: &[PgRow],
+) -> Result<u32, DbError> {
+    if let Some(scale) = MONEY_SCALES.lock().unwrap().get(conn_name) {
+        return Ok(*scale);
+    }
+    if !needs_money_scale(rows) {
+        return Ok(DEFAULT_MONEY_SCALE);
+    }
+
+    let scale = fetch_money_scale(conn).await?;
+    MONEY_SCALES
+        .lock()
+        .unwrap()
+        .insert(conn_name.to_string(), scale);
+    Ok(scale)
+}
+
+/// 和 resolve_money_scale 相同, 但从连接池中获取连接, 没有空闲连接时先按 2 位处理
+/// Same as resolve_money_scale, but takes the connection from the pool, a scale of 2 is used while no connection is idle
+pub async fn resolve_money_scale_in_pool(
+    conn_name: &str,
+    pool: &PgPool,
+    rows: &[PgRow],
+) -> Result<u32, DbError> {
+    if let Some(scale) = MONEY_SCALES.lock().unwrap().get(conn_name) {
+        return Ok(*scale);
+    }
+    if !needs_money_scale(rows) {
+        return Ok(DEFAULT_MONEY_SCALE);
+    }
+
+    match pool.try_acquire() {
+        Some(mut conn) => resolve_money_scale(conn_name, &mut conn, rows).await,
+        None => Ok(DEFAULT_MONEY_SCALE),
+    }
+}
+
+// 缓存的类型过期时丢弃, 下次 resolve_types 重新查询
+// Drop the cached types when they are stale, the next resolve_types queries them again
+pub fn invalidate_types(conn_name: &str) {
+    PG_TYPES.lock().unwrap().remove(conn_name);
+}
+
+// 断开连接时清除缓存 | Clear the cache when the connection is closed
+pub fn clear_types(conn_name: &str) {
+    PG_TYPES.lock().unwrap().remove(conn_name);
+    MONEY_SCALES.lock().unwrap().remove(conn_name);
+}
+
+// 重命名连接时把缓存移到新名称下
+// Move the cache to the new name when the connection is renamed
+pub fn rename_types(conn_name: &str, new_name: &str) {
+    let mut cache = PG_TYPES.lock().unwrap();
+    if let Some(types) = cache.remove(conn_name) {
+        cache.insert(new_name.to_string(), types);
+    }
+    let mut scales = MONEY_SCALES.lock().unwrap();
+    if let Some(scale) = scales.remove(conn_name) {
+        scales.insert(new_name.to_string(), scale);
+    }
+}
+
+// 文本协议查询时 lc_monetary 已经体现在文本中, 只有二进制格式需要小数位数
+// Over the text protocol lc_monetary is already reflected in the text, only the binary format needs the scale
+fn needs_money_scale(rows: &[PgRow]) -> bool {
+    let Some(row) = rows.first() else {
+        return false;
+    };
+
+    row.columns().iter().any(|column| {
+        let binary = row
+            .try_get_raw(column.ordinal())
+            .is_ok_and(|value| value.format() == PgValueFormat::Binary);
+        binary && may_contain_money(column.type_info())
+    })
+}
+
+fn may_contain_money(type_info: &PgTypeInfo) -> bool {
+    match type_kind(type_info) {
+        Some(PgTypeKind::Array(element)) => may_contain_money(element),
+        Some(PgTypeKind::Domain(base)) => may_contain_money(base),
+        Some(PgTypeKind::Composite(fields)) => fields.iter().any(|(_, ty)| may_contain_money(ty)),
+        _ => pg_type_name(type_info) == "MONEY",
+    }
+}
+
+async fn fetch_money_scale(conn: &mut PgConnection) -> Result<u32, DbError> {
+    let text: String = conn
+        .fetch_one("SELECT '1'::money::text")
+        .await?
+        .try_get(0)?;
+    Ok(money_scale(&text).unwrap_or(DEFAULT_MONEY_SCALE))
+}
+
+fn cached_types(conn_name: &str) -> Arc<PgTypeMap> {
+    PG_TYPES
+        .lock()
+        .unwrap()
+        .get(conn_name)
+        .cloned()
+        .unwrap_or_default()
+}
+
+fn store_types(conn_name: &str, found: PgTypeMap) -> Arc<PgTypeMap> {
+    let mut cache = PG_TYPES.lock().unwrap();
+    let entry = cache.entry(conn_name.to_string()).or_default();
+    let mut types = PgTypeMap::clone(entry);
+    types.extend(found);
+    *entry = Arc::new(types);
+    Arc::clone(entry)
+}
+
+// 类型名为 ? 的列是 sqlx 不认识的类型, 多范围和 pg_type_name 认识的类型已经按 oid 处理
+// Columns named ? have types sqlx doesn't know, multiranges and the types pg_type_name knows are already handled by oid
+fn missing_oids(types: &PgTypeMap, rows: &[PgRow]) -> Vec<u32> {
+    let Some(row) = rows.first() else {
+        return Vec::new();
+    };
+
+    let mut oids: Vec<u32> = row
+        .columns()
+        .iter()
+        .map(|column| column.type_info())
+        .filter(|info| pg_type_name(info) == "?" && range_element(info).is_none())
+        .filter_map(|info| info.oid().map(|oid| oid.0))
+        .filter(|oid| !types.contains_key(oid))
+        .collect();
+    oids.sort_unstable();
+    oids.dedup();
+    oids
+}
+
+// 逐层查询类型和它们引用的类型, 直到没有新的类型
+// Query the types and the types they reference level by level, until there are no new ones
+async fn fetch_types(
+    conn: &mut PgConnection,
+    known: &PgTypeMap,
+    mut missing: Vec<u32>,
+) -> Result<PgTypeMap, DbError> {
+    let mut found = PgTypeMap::new();
+    let mut queried = HashSet::new();
+
+    while !missing.is_empty() {
+        queried.extend(missing.iter().copied());
+        // oid 是整数, 直接拼进 SQL, 不使用预处理语句时也能执行
+        // The oids are integers and are inlined, so this also runs without prepared statements
+        let oids: Vec<String> = missing.iter().map(u32::to_string).collect();
+        let sql = format!(
+            "SELECT t.oid::int8, t.typname::text, t.typtype::text, t.typcategory::text, \
+             t.typbasetype::int8, t.typelem::int8, e.typdelim::text, a.attname::text, a.atttypid::int8 \
+             FROM pg_catalog.pg_type t \
+             LEFT JOIN pg_catalog.pg_type e ON e.oid = t.typelem \
+             LEFT JOIN pg_catalog.pg_attribute a ON t.typtype = 'c' AND a.attrelid = t.typrelid \
+             AND a.attnum > 0 AND NOT a.attisdropped \
+             WHERE t.oid IN ({}) ORDER BY t.oid, a.attnum",
+            oids.join(",")
+        );
+
+        for row in conn.fetch_all(&*sql).await? {
+            let oid = u32::try_from(row.try_get::<i64, _>(0)?).unwrap_or_default();
+            if let (Some(PgCustomType::Composite(fields)), Some(name)) =
+                (found.get_mut(&oid), row.try_get::<Option<String>, _>(7)?)
+            {
+                let field = row.try_get::<i64, _>(8)?;
+                fields.push((name, u32::try_from(field).unwrap_or_default()));
+                continue;
+            }
+            found.insert(oid, custom_type(&row)?);
+        }
+
+        missing = found
+            .values()
+            .flat_map(referenced_oids)
+            .filter(|oid| !known.contains_key(oid) && !queried.contains(oid))
+            .collect();
+        missing.sort_unstable();
+        missing.dedup();
+    }
+
+    Ok(found)
+}
+
+fn custom_type(row: &PgRow) -> Result<PgCustomType, sqlx::Error> {
+    let typtype: String = row.try_get(2)?;
+    let category: String = row.try_get(3)?;
+    let oid_at = |idx: usize| -> Result<u32, sqlx::Error> {
+        Ok(u32::try_from(row.try_get::<i64, _>(idx)?).unwrap_or_default())
+    };
+
+    Ok(match (typtype.as_str(), category.as_str()) {
+        ("e", _) => PgCustomType::Enum,
+        ("d", _) => PgCustomType::Domain(oid_at(4)?),
+        ("c", _) => match row.try_get::<Option<String>, _>(7)? {
+            Some(name) => PgCustomType::Composite(vec![(name, oid_at(8)?)]),
+            None => PgCustomType::Composite(Vec::new()),
+        },
+        (_, "A") if oid_at(5)? != 0 => {
+            let delimiter = row.try_get::<Option<String>, _>(6)?;
+            let delimiter = delimiter.and_then(|d| d.chars().next()).unwrap_or(',');
+            PgCustomType::Array(oid_at(5)?, delimiter)
+        }
+        _ => PgCustomType::Base(row.try_get::<String, _>(1)?.to_uppercase()),
+    })
+}
+
+fn referenced_oids(ty: &PgCustomType) -> Vec<u32> {
+    match ty {
+        PgCustomType::Domain(base) => vec![*base],
+        PgCustomType::Composite(fields) => fields.iter().map(|(_, oid)| *oid).collect(),
+        PgCustomType::Array(element, _) => vec![*element],
+        PgCustomType::Enum | PgCustomType::Base(_) => Vec::new(),
+    }
+}
+
+/// 按 pg_type 中查到的类型把值转为 json | Convert a value to JSON by the type looked up in pg_type
+///
+/// 枚举转为标签, 域按基础类型转换, 复合类型转为以字段名为键的对象, 数组的元素同样按查到的类型转换
+/// Enums become their labels, domains are converted by their base type,
+/// composites become objects keyed by field name, array elements are converted by their looked up type as well
+///
+/// # 参数
+/// - `types`: resolve_types 返回的类型 | The types returned by resolve_types
+/// - `oid`: 值的类型 | Type of the value
+/// - `format`: 值的格式 | Format of the value
+/// - `bytes`: 值的原始字节, 不能为 NULL | Raw bytes of the value, must not be NULL
+/// - `money_scale`: 二进制格式的 MONEY 的小数位数 | Scale of MONEY in the binary format
+///
+pub fn custom_to_json(
+    types: &PgTypeMap,
+    oid: u32,
+    format: PgValueFormat,
+    bytes: &[u8],
+    money_scale: u32,
+) -> Result<Value, BoxDynError> {
+    match types.get(&oid) {
+        Some(PgCustomType::Enum) => Ok(Value::String(std::str::from_utf8(bytes)?.to_string())),
+        Some(PgCustomType::Domain(base)) => {
+            custom_to_json(types, *base, format, bytes, money_scale)
+        }
+        Some(PgCustomType::Composite(fields)) => {
+            composite_to_json(fields, format, bytes, |oid, format, bytes| {
+                custom_to_json(types, *oid, format, bytes, money_scale)
+            })
+        }
+        Some(PgCustomType::Array(element, delimiter)) => {
+            array_to_json_with(*delimiter, format, bytes, &|format, bytes| {
+                custom_to_json(types, *element, format, bytes, money_scale)
+            })
+        }
+        Some(PgCustomType::Base(name)) => named_to_json(name, format, bytes, money_scale),
+        None => Err(format!("unknown PostgreSQL type oid {}", oid).into()),
+    }
+}
+
+/// 把复合类型转为以字段名为键的对象, NULL 字段为 null
+/// Convert a composite to an object keyed by field name, NULL fields are null
+///
+/// # 参数
+/// - `fields`: 字段名和字段类型 | Field names and field types
+/// - `format`: 值的格式 | Format of the value
+/// - `bytes`: 值的原始字节, 不能为 NULL | Raw bytes of the value, must not be NULL
+/// - `field`: 按字段类型, 格式和原始字节转换一个字段 | Converts one field from its type, format and raw bytes
+///
+pub fn composite_to_json<T>(
+    fields: &[(String, T)],
+    format: PgValueFormat,
+    bytes: &[u8],
+    field: impl Fn(&T, PgValueFormat, &[u8]) -> Result<Value, BoxDynError>,
+) -> Result<Value, BoxDynError> {
+    let values = match format {
+        // 二进制格式: 字段数, 每个字段是 oid, 4 字节长度 (-1 为 NULL) 加值
+        // Binary format: the field count, each field is an oid, a 4 byte length (-1 for NULL) and the value
+        PgValueFormat::Binary => {
+            let mut reader = bytes;
+            check_field_count(fields.len(), usize::try_from(read_i32(&mut reader)?)?)?;
+            let values = fields
+                .iter()
+                .map(|(_, ty)| {
+                    read_bytes(&mut reader, 4)?;
+                    match read_i32(&mut reader)? {
+                        -1 => Ok(Value::Null),
+                        len => field(ty, PgValueFormat::Binary, read_bytes(&mut reader, len)?),
+                    }
+                })
+                .collect::<Result<Vec<_>, BoxDynError>>()?;
+            if !reader.is_empty() {
+                return Err("unexpected trailing data in composite value".into());
+            }
+            values
+        }
+        PgValueFormat::Text => {
+            let texts = text_fields(std::str::from_utf8(bytes)?)?;
+            // 没有字段和只有一个 NULL 字段的文本都是 () | No fields and a single NULL field are both ()
+            let texts = if fields.is_empty() { Vec::new() } else { texts };
+            check_field_count(fields.len(), texts.len())?;
+            fields
+                .iter()
+                .zip(texts)
+                .map(|((_, ty), text)| match text {
+                    Some(text) => field(ty, PgValueFormat::Text, text.as_bytes()),
+                    None => Ok(Value::Null),
+                })
+                .collect::<Result<Vec<_>, _>>()?
+        }
+    };
+
+    Ok(Value::Object(
+        fields
+            .iter()
+            .map(|(name, _)| name.clone())
+            .zip(values)
+            .collect(),
+    ))
+}
+
+// 缓存的字段在类型修改后可能过期 | Cached fields may be stale after the type was altered
+fn check_field_count(expected: usize, actual: usize) -> Result<(), BoxDynError> {
+    if expected != actual {
+        return Err(Box::new(FieldCountMismatch { expected, actual }));
+    }
+    Ok(())
+}
+
+// 文本格式: 括号内逗号分隔的字段, 空字段为 NULL, 字段可能带双引号, 引号内的 "" 表示 ", 反斜杠转义
+// Text format: comma separated fields in parentheses, an empty field is NULL,
+// fields may be double quoted, "" inside the quotes is a ", backslashes escape
+fn text_fields(text: &str) -> Result<Vec<Option<String>>, BoxDynError> {
+    let inner = text
+        .trim()
+        .strip_prefix('(')
+        .and_then(|s| s.strip_suffix(')'))
+        .ok_or_else(|| format!("invalid composite value '{}'", text))?;
+
+    let mut fields = Vec::new();
+    let mut value = String::new();
+    let mut quoted = false;
+    let mut in_quotes = false;
+    let mut chars = inner.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if in_quotes && chars.peek() == Some(&'"') => {
+                chars.next();
+                value.push('"');
+            }
+            '"' => {
+                quoted = true;
+                in_quotes = !in_quotes;
+            }
+            '\\' => value.extend(chars.next()),
+            ',' if !in_quotes => {
+                fields.push((quoted || !value.is_empty()).then(|| std::mem::take(&mut value)));
+                quoted = false;
+            }
+            _ => value.push(c),
+        }
+    }
+
+    if in_quotes {
+        return Err("unterminated quoted composite field".into());
+    }
+    fields.push((quoted || !value.is_empty()).then_some(value));
+    Ok(fields)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn fields(names: &[(&str, &str)]) -> Vec<(String, String)> {
+        names
+            .iter()
+            .map(|(name, ty)| (name.to_string(), ty.to_string()))
+            .collect()
+    }
+
+    fn composite(
+        fields: &[(String, String)],
+        format: PgValueFormat,
+        bytes: &[u8],
+    ) -> Result<Value, BoxDynError> {
+        composite_to_json(fields, format, bytes, |ty, format, bytes| {
+            named_to_json(ty, format, bytes, 2)
+        })
+    }
+
+    #[test]
+    fn composites_become_objects() {
+        let fields = fields(&[("a", "INT4"), ("b", "TEXT"), ("c", "INT4")]);
+
+        // record_send(row(1,'x',null))
+        let bytes = b"\x00\x00\x00\x03\x00\x00\x00\x17\x00\x00\x00\x04\x00\x00\x00\x01\x00\x00\x02\xc1\x00\x00\x00\x01x\x00\x00\x02\xc1\xff\xff\xff\xff";
+        let expected = json!({ "a": 1, "b": "x", "c": null });
+        assert_eq!(
+            composite(&fields, PgValueFormat::Binary, bytes).unwrap(),
+            expected
+        );
+        assert_eq!(
+            composite(&fields, PgValueFormat::Text, b"(1,x,)").unwrap(),
+            expected
+        );
+        assert_eq!(
+            composite(&fields, PgValueFormat::Text, br#"(2,"a ""q"", \\b",)"#).unwrap(),
+            json!({ "a": 2, "b": "a \"q\", \\b", "c": null })
+        );
+        assert_eq!(
+            composite(&fields, PgValueFormat::Text, br#"(3,"",)"#).unwrap()["b"],
+            ""
+        );
+        assert_eq!(
+            composite(&[], PgValueFormat::Text, b"()").unwrap(),
+            json!({})
+        );
+        assert!(composite(&fields, PgValueFormat::Text, br#"(1,"x,)"#).is_err());
+    }
+
+    #[test]
+    fn field_count_mismatch_is_reported_as_stale() {
+        let fields = fields(&[("a", "INT4"), ("b", "TEXT")]);
+        // 类型增加了字段后的文本值, 删除了字段后的二进制值
+        // A text value after a field was added to the type, a binary value after one was dropped
+        let cases: [(PgValueFormat, &[u8], usize); 2] = [
+            (PgValueFormat::Text, b"(1,x,)", 3),
+            (
+                PgValueFormat::Binary,
+                b"\x00\x00\x00\x01\x00\x00\x00\x17\x00\x00\x00\x04\x00\x00\x00\x01",
+                1,
+            ),
+        ];
+        for (format, bytes, actual) in cases {
+            let err = composite(&fields, format, bytes).unwrap_err();
+            let mismatch = err.downcast_ref::<FieldCountMismatch>().unwrap();
+            assert_eq!((mismatch.expected, mismatch.actual), (2, actual));
+        }
+    }
+
+    #[test]
+    fn looked_up_types_are_followed() {
+        let types = PgTypeMap::from([
+            (100, PgCustomType::Enum),
+            (101, PgCustomType::Domain(102)),
+            (102, PgCustomType::Base("INT4".to_string())),
+            (103, PgCustomType::Array(100, ',')),
+            (
+                104,
+                PgCustomType::Composite(vec![("mood".to_string(), 100), ("n".to_string(), 101)]),
+            ),
+            (105, PgCustomType::Array(104, ',')),
+        ]);
+        let convert = |oid: u32, text: &str| {
+            custom_to_json(&types, oid, PgValueFormat::Text, text.as_bytes(), 2)
+        };
+
+        assert_eq!(convert(100, "happy").unwrap(), "happy");
+        assert_eq!(convert(101, "42").unwrap(), 42);
+        assert_eq!(
+            convert(103, "{happy,NULL}").unwrap(),
+            json!(["happy", null])
+        );
+        assert_eq!(
+            convert(105, r#"{"(sad,1)","(,)"}"#).unwrap(),
+            json!([{ "mood": "sad", "n": 1 }, { "mood": null, "n": null }])
+        );
+        assert!(convert(101, "x").is_err());
+        assert!(convert(999, "1").is_err());
+    }
+
+    #[test]
+    fn types_referenced_by_others_are_listed() {
+        assert_eq!(referenced_oids(&PgCustomType::Domain(23)), vec![23]);
+        assert_eq!(referenced_oids(&PgCustomType::Array(25, ',')), vec![25]);
+        assert_eq!(
+            referenced_oids(&PgCustomType::Composite(vec![
+                ("a".to_string(), 23),
+                ("b".to_string(), 25)
+            ])),
+            vec![23, 25]
+        );
+        assert!(referenced_oids(&PgCustomType::Enum).is_empty());
+    }
+}
@@ -0,0 +1,207 @@
+use serde_json::{json, Value};
+use sqlx::error::BoxDynError;
+use sqlx::postgres::PgValueFormat;
+
+/// 把 PostgreSQL 的几何类型转为结构化的 json | Convert PostgreSQL geometric types to structured JSON
+///
+/// - POINT: `{"x": 1, "y": 2}`
+/// - LINE: `{"a": 1, "b": -1, "c": 0}`, 即 Ax + By + C = 0 | i.e. Ax + By + C = 0
+/// - LSEG: `{"start": POINT, "end": POINT}`
+/// - BOX: `{"high": POINT, "low": POINT}`, 右上角和左下角 | The upper right and lower left corners
+/// - PATH: `{"closed": true, "points": [POINT, ...]}`
+/// - POLYGON: `{"points": [POINT, ...]}`
+/// - CIRCLE: `{"center": POINT, "radius": 3}`
+///
+/// 使用预处理语句时是二进制格式, 通过文本协议查询时是文本格式
+/// The binary format is used with prepared statements, the text format when queried over the text protocol
+///
+/// # 参数
+/// - `type_name`: 类型名, 例如 POINT | Type name, e.g. POINT
+/// - `format`: 值的格式 | Format of the value
+/// - `bytes`: 值的原始字节, 不能为 NULL | Raw bytes of the value, must not be NULL
+///
+pub fn geometry_to_json(
+    type_name: &str,
+    format: PgValueFormat,
+    bytes: &[u8],
+) -> Result<Value, BoxDynError> {
+    let mut reader = match format {
+        PgValueFormat::Binary => Reader::Binary(bytes),
+        PgValueFormat::Text => Reader::text(std::str::from_utf8(bytes)?)?,
+    };
+
+    let value = match type_name {
+        "POINT" => reader.point()?,
+        "LINE" => json!({
+            "a": reader.float()?,
+            "b": reader.float()?,
+            "c": reader.float()?,
+        }),
+        "LSEG" => json!({
+            "start": reader.point()?,
+            "end": reader.point()?,
+        }),
+        "BOX" => json!({
+            "high": reader.point()?,
+            "low": reader.point()?,
+        }),
+        "PATH" => {
+            let closed = reader.closed()?;
+            json!({
+                "closed": closed,
+                "points": reader.points()?,
+            })
+        }
+        "POLYGON" => json!({ "points": reader.points()? }),
+        "CIRCLE" => json!({
+            "center": reader.point()?,
+            "radius": reader.float()?,
+        }),
+        _ => return Err(format!("{} is not a geometric type", type_name).into()),
+    };
+
+    reader.finish(type_name)?;
+    Ok(value)
+}
+
+// 二进制格式是连续的大端 float8, PATH 前面有闭合标记和点数, POLYGON 前面有点数
+// 文本格式例如 ((1,2),(3,4)), 去掉括号后只剩逗号分隔的数字, PATH 以 ( 开头表示闭合, [ 开头表示开放
+// The binary format is consecutive big-endian float8s, PATH is prefixed with a closed flag and the point count,
+// POLYGON with the point count
+// The text format is e.g. ((1,2),(3,4)), only comma separated numbers remain once the brackets are removed,
+// a PATH starting with ( is closed, with [ it is open
+enum Reader<'a> {
+    Binary(&'a [u8]),
+    Text {
+        closed: bool,
+        numbers: std::vec::IntoIter<f64>,
+    },
+}
+
+impl<'a> Reader<'a> {
+    fn text(s: &'a str) -> Result<Self, BoxDynError> {
+        let s = s.trim();
+        let numbers = s
+            .split(|c: char| c == ',' || "()[]<>{}".contains(c))
+            .map(str::trim)
+            .filter(|n| !n.is_empty())
+            .map(|n| {
+                n.parse::<f64>()
+                    .map_err(|_| format!("invalid number '{}' in geometric value '{}'", n, s))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Reader::Text {
+            closed: !s.starts_with('['),
+            numbers: numbers.into_iter(),
+        })
+    }
+
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], BoxDynError> {
+        match self {
+            Reader::Binary(bytes) if bytes.len() >= N => {
+                let (head, rest) = bytes.split_at(N);
+                *bytes = rest;
+                Ok(head.try_into()?)
+            }
+            _ => Err("unexpected end of geometric value".into()),
+        }
+    }
+
+    fn float(&mut self) -> Result<f64, BoxDynError> {
+        match self {
+            Reader::Binary(_) => Ok(f64::from_be_bytes(self.take()?)),
+            Reader::Text { numbers, .. } => numbers
+                .next()
+                .ok_or_else(|| "unexpected end of geometric value".into()),
+        }
+    }
+
+    fn point(&mut self) -> Result<Value, BoxDynError> {
+        Ok(json!({ "x": self.float()?, "y": self.float()? }))
+    }
+
+    fn closed(&mut self) -> Result<bool, BoxDynError> {
+        match self {
+            Reader::Binary(_) => Ok(self.take::<1>()?[0] != 0),
+            Reader::Text { closed, .. } => Ok(*closed),
+        }
+    }
+
+    fn points(&mut self) -> Result<Vec<Value>, BoxDynError> {
+        let count = match self {
+            Reader::Binary(_) => i32::from_be_bytes(self.take()?).max(0) as usize,
+            Reader::Text { numbers, .. } => numbers.len() / 2,
+        };
+        (0..count).map(|_| self.point()).collect()
+    }
+
+    // 所有数据都应已读完 | All data should have been read
+    fn finish(&self, type_name: &str) -> Result<(), BoxDynError> {
+        let remaining = match self {
+            Reader::Binary(bytes) => bytes.len(),
+            Reader::Text { numbers, .. } => numbers.len(),
+        };
+        if remaining > 0 {
+            return Err(format!("unexpected trailing data in {} value", type_name).into());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn binary_geometry() {
+        // path_send('[(1,2),(3,4)]'::path)
+        let bytes = b"\x00\x00\x00\x00\x02?\xf0\x00\x00\x00\x00\x00\x00@\x00\x00\x00\x00\x00\x00\x00@\x08\x00\x00\x00\x00\x00\x00@\x10\x00\x00\x00\x00\x00\x00";
+        assert_eq!(
+            geometry_to_json("PATH", PgValueFormat::Binary, bytes).unwrap(),
+            json!({ "closed": false, "points": [{ "x": 1.0, "y": 2.0 }, { "x": 3.0, "y": 4.0 }] })
+        );
+
+        // circle_send('<(1,2),3>'::circle)
+        let bytes = b"?\xf0\x00\x00\x00\x00\x00\x00@\x00\x00\x00\x00\x00\x00\x00@\x08\x00\x00\x00\x00\x00\x00";
+        assert_eq!(
+            geometry_to_json("CIRCLE", PgValueFormat::Binary, bytes).unwrap(),
+            json!({ "center": { "x": 1.0, "y": 2.0 }, "radius": 3.0 })
+        );
+
+        assert!(geometry_to_json("POINT", PgValueFormat::Binary, &bytes[..8]).is_err());
+        assert!(geometry_to_json("POINT", PgValueFormat::Binary, bytes).is_err());
+    }
+
+    #[test]
+    fn text_geometry() {
+        let parse = |type_name: &str, text: &str| {
+            geometry_to_json(type_name, PgValueFormat::Text, text.as_bytes())
+        };
+
+        assert_eq!(
+            parse("LINE", "{1,-1,0}").unwrap(),
+            json!({ "a": 1.0, "b": -1.0, "c": 0.0 })
+        );
+        assert_eq!(
+            parse("LSEG", "[(1,2),(3,4)]").unwrap(),
+            json!({ "start": { "x": 1.0, "y": 2.0 }, "end": { "x": 3.0, "y": 4.0 } })
+        );
+        assert_eq!(parse("PATH", "((0,0),(1,1))").unwrap()["closed"], true);
+        assert_eq!(
+            parse("POLYGON", "((0,0),(1,1),(1,0))").unwrap()["points"]
+                .as_array()
+                .map(Vec::len),
+            Some(3)
+        );
+        assert_eq!(
+            parse("POINT", "(1e+20,-0.5)").unwrap(),
+            json!({ "x": 1e20, "y": -0.5 })
+        );
+
+        assert!(parse("POINT", "(1,2,3)").is_err());
+        assert!(parse("POINT", "(1,x)").is_err());
+        assert!(parse("CIRCLE", "<(1,2)>").is_err());
+        assert!(parse("INT4", "1").is_err());
+    }
+}
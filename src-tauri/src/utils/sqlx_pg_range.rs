@@ -0,0 +1,357 @@
+use crate::utils::sqlx_pg_value::{
+    binary_numeric, pg_date, pg_timestamp, read_bytes, read_i32, read_i64,
+};
+use serde_json::{json, Value};
+use sqlx::error::BoxDynError;
+use sqlx::postgres::{PgTypeInfo, PgValueFormat};
+use sqlx::TypeInfo;
+use std::iter::Peekable;
+use std::str::Chars;
+
+// 二进制格式中范围的标记位 | Flag bits of a range in the binary format
+const RANGE_EMPTY: u8 = 0x01;
+const RANGE_LB_INC: u8 = 0x02;
+const RANGE_UB_INC: u8 = 0x04;
+const RANGE_LB_INF: u8 = 0x08;
+const RANGE_UB_INF: u8 = 0x10;
+
+/// 范围和多范围类型的元素类型, 以及是否是多范围, 不是范围类型时返回 None
+/// Element type of a range or multirange type and whether it is a multirange, None when it isn't a range type
+///
+/// 多范围类型 sqlx 不认识, 通过预处理语句查询时是 pg_type 中的小写名称, 通过文本协议查询时只有 oid
+/// Multiranges aren't known to sqlx, queried with prepared statements they have their lower case pg_type name,
+/// over the text protocol only the oid
+pub fn range_element(type_info: &PgTypeInfo) -> Option<(&'static str, bool)> {
+    let oid = type_info.oid().map(|oid| oid.0);
+    match (type_info.name(), oid) {
+        ("INT4RANGE", _) => Some(("INT4", false)),
+        ("INT8RANGE", _) => Some(("INT8", false)),
+        ("NUMRANGE", _) => Some(("NUMERIC", false)),
+        ("TSRANGE", _) => Some(("TIMESTAMP", false)),
+        ("TSTZRANGE", _) => Some(("TIMESTAMPTZ", false)),
+        ("DATERANGE", _) => Some(("DATE", false)),
+        ("int4multirange", _) | (_, Some(4451)) => Some(("INT4", true)),
+        ("int8multirange", _) | (_, Some(4536)) => Some(("INT8", true)),
+        ("nummultirange", _) | (_, Some(4532)) => Some(("NUMERIC", true)),
+        ("tsmultirange", _) | (_, Some(4533)) => Some(("TIMESTAMP", true)),
+        ("tstzmultirange", _) | (_, Some(4534)) => Some(("TIMESTAMPTZ", true)),
+        ("datemultirange", _) | (_, Some(4535)) => Some(("DATE", true)),
+        _ => None,
+    }
+}
+
+/// 把范围或多范围类型转为 json | Convert a range or multirange type to JSON
+///
+/// 范围转为 `{"empty": false, "lower": 1, "upper": 10, "lowerInclusive": true, "upperInclusive": false}`,
+/// 没有下界或上界时为 null, 多范围转为范围的数组
+/// 整数边界是数字, 其它边界是 PostgreSQL 能直接解析的字符串, 加上双引号就能拼回字面量, 例如 `["2024-01-01","2024-02-01")`
+///
+/// A range becomes `{"empty": false, "lower": 1, "upper": 10, "lowerInclusive": true, "upperInclusive": false}`,
+/// a missing lower or upper bound is null, a multirange becomes an array of ranges
+/// Integer bounds are numbers, other bounds are strings PostgreSQL parses as is, so they can be double quoted back
+/// into a literal, e.g. `["2024-01-01","2024-02-01")`
+///
+/// # 参数
+/// - `element`: 元素类型, 来自 range_element | Element type, from range_element
+/// - `multirange`: 是否是多范围 | Whether it is a multirange
+/// - `format`: 值的格式 | Format of the value
+/// - `bytes`: 值的原始字节, 不能为 NULL | Raw bytes of the value, must not be NULL
+///
+pub fn range_to_json(
+    element: &str,
+    multirange: bool,
+    format: PgValueFormat,
+    bytes: &[u8],
+) -> Result<Value, BoxDynError> {
+    match (format, multirange) {
+        (PgValueFormat::Binary, false) => binary_range(element, bytes),
+        (PgValueFormat::Binary, true) => {
+            let mut reader = bytes;
+            let count = read_i32(&mut reader)?;
+            let ranges = (0..count)
+                .map(|_| {
+                    let len = read_i32(&mut reader)?;
+                    binary_range(element, read_bytes(&mut reader, len)?)
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(Value::Array(ranges))
+        }
+        (PgValueFormat::Text, false) => {
+            let mut chars = std::str::from_utf8(bytes)?.trim().chars().peekable();
+            text_range(element, &mut chars)
+        }
+        (PgValueFormat::Text, true) => {
+            let text = std::str::from_utf8(bytes)?.trim();
+            let inner = text
+                .strip_prefix('{')
+                .and_then(|s| s.strip_suffix('}'))
+                .ok_or_else(|| format!("invalid multirange '{}'", text))?;
+
+            let mut chars = inner.chars().peekable();
+            let mut ranges = Vec::new();
+            loop {
+                skip_whitespace(&mut chars);
+                if chars.peek().is_none() {
+                    break;
+                }
+                ranges.push(text_range(element, &mut chars)?);
+                skip_whitespace(&mut chars);
+                if chars.peek() == Some(&',') {
+                    chars.next();
+                }
+            }
+            Ok(Value::Array(ranges))
+        }
+    }
+}
+
+fn range_json(
+    lower: Option<Value>,
+    upper: Option<Value>,
+    lower_inclusive: bool,
+    upper_inclusive: bool,
+) -> Value {
+    json!({
+        "empty": false,
+        "lower": lower,
+        "upper": upper,
+        // 没有边界时不可能包含边界 | An infinite bound is never inclusive
+        "lowerInclusive": lower_inclusive && lower.is_some(),
+        "upperInclusive": upper_inclusive && upper.is_some(),
+    })
+}
+
+fn empty_range() -> Value {
+    json!({
+        "empty": true,
+        "lower": null,
+        "upper": null,
+        "lowerInclusive": false,
+        "upperInclusive": false,
+    })
+}
+
+// 二进制格式: 1 字节标记, 然后是存在的边界, 每个边界是 4 字节长度加元素的二进制值
+// Binary format: a 1 byte flag, then the present bounds, each a 4 byte length and the binary value of the element
+fn binary_range(element: &str, mut bytes: &[u8]) -> Result<Value, BoxDynError> {
+    let flags = read_bytes(&mut bytes, 1)?[0];
+    if flags & RANGE_EMPTY != 0 {
+        return Ok(empty_range());
+    }
+
+    let mut bound = |infinite: u8| -> Result<Option<Value>, BoxDynError> {
+        if flags & infinite != 0 {
+            return Ok(None);
+        }
+        let len = read_i32(&mut bytes)?;
+        binary_element(element, read_bytes(&mut bytes, len)?).map(Some)
+    };
+    let lower = bound(RANGE_LB_INF)?;
+    let upper = bound(RANGE_UB_INF)?;
+
+    Ok(range_json(
+        lower,
+        upper,
+        flags & RANGE_LB_INC != 0,
+        flags & RANGE_UB_INC != 0,
+    ))
+}
+
+// 和列的格式保持一致, 但保留秒的小数部分, 以便拼回字面量时不丢精度
+// Consistent with the column formats, but keeping fractional seconds so nothing is lost in the literal
+fn binary_element(element: &str, bytes: &[u8]) -> Result<Value, BoxDynError> {
+    let mut reader = bytes;
+    let value = match element {
+        "INT4" => json!(read_i32(&mut reader)?),
+        "INT8" => json!(read_i64(&mut reader)?),
+        "NUMERIC" => json!(binary_numeric(&mut reader)?),
+        "DATE" => match read_i32(&mut reader)? {
+            i32::MAX => json!("infinity"),
+            i32::MIN => json!("-infinity"),
+            days => json!(pg_date(days)?.format("%Y-%m-%d").to_string()),
+        },
+        "TIMESTAMP" | "TIMESTAMPTZ" => match read_i64(&mut reader)? {
+            i64::MAX => json!("infinity"),
+            i64::MIN => json!("-infinity"),
+            micros if element == "TIMESTAMPTZ" => json!(pg_timestamp(micros)?.to_rfc3339()),
+            micros => json!(pg_timestamp(micros)?
+                .naive_utc()
+                .format("%Y-%m-%d %H:%M:%S%.f")
+                .to_string()),
+        },
+        _ => return Err(format!("unsupported range element type {}", element).into()),
+    };
+
+    if !reader.is_empty() {
+        return Err(format!("unexpected trailing data in {} range bound", element).into());
+    }
+    Ok(value)
+}
+
+// 文本格式: empty, 或者 [ 或 ( 开头, 逗号分隔的两个边界, ] 或 ) 结尾
+// 边界为空表示没有边界, 可能带双引号, 引号内用反斜杠转义
+// Text format: empty, or [ or ( followed by two comma separated bounds and ] or )
+// An empty bound means no bound, bounds may be double quoted, with backslash escapes inside the quotes
+fn text_range(element: &str, chars: &mut Peekable<Chars>) -> Result<Value, BoxDynError> {
+    let lower_inclusive = match chars.next() {
+        Some('[') => true,
+        Some('(') => false,
+        Some(c) if c.eq_ignore_ascii_case(&'e') => {
+            let rest: String = chars.by_ref().take(4).collect();
+            if !rest.eq_ignore_ascii_case("mpty") {
+                return Err(format!("invalid range 'e{}'", rest).into());
+            }
+            return Ok(empty_range());
+        }
+        other => return Err(format!("invalid range start {:?}", other).into()),
+    };
+
+    let lower = text_bound(chars, ',')?;
+    chars.next();
+    let upper = text_bound(chars, ']')?;
+    let upper_inclusive = match chars.next() {
+        Some(']') => true,
+        Some(')') => false,
+        other => return Err(format!("invalid range end {:?}", other).into()),
+    };
+
+    Ok(range_json(
+        lower.map(|s| text_element(element, s)).transpose()?,
+        upper.map(|s| text_element(element, s)).transpose()?,
+        lower_inclusive,
+        upper_inclusive,
+    ))
+}
+
+// 读取一个边界, 直到遇到 end (上界时还有 ")"), 不消耗结束符
+// Read one bound up to `end` (or ")" for the upper bound), without consuming the terminator
+fn text_bound(chars: &mut Peekable<Chars>, end: char) -> Result<Option<String>, BoxDynError> {
+    let mut bound = String::new();
+    let mut quoted = false;
+    let mut in_quotes = false;
+
+    while let Some(&c) = chars.peek() {
+        if !in_quotes && (c == end || (end == ']' && c == ')')) {
+            break;
+        }
+        chars.next();
+        match c {
+            '"' if in_quotes && chars.peek() == Some(&'"') => {
+                chars.next();
+                bound.push('"');
+            }
+            '"' => {
+                quoted = true;
+                in_quotes = !in_quotes;
+            }
+            '\\' => bound.extend(chars.next()),
+            _ => bound.push(c),
+        }
+    }
+
+    if in_quotes {
+        return Err("unterminated quoted range bound".into());
+    }
+    Ok((quoted || !bound.is_empty()).then_some(bound))
+}
+
+fn text_element(element: &str, text: String) -> Result<Value, BoxDynError> {
+    Ok(match element {
+        "INT4" | "INT8" => json!(text.trim().parse::<i64>()?),
+        _ => json!(text),
+    })
+}
+
+fn skip_whitespace(chars: &mut Peekable<Chars>) {
+    while chars.next_if(|c| c.is_whitespace()).is_some() {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(lower: Value, upper: Value, lower_inclusive: bool, upper_inclusive: bool) -> Value {
+        json!({
+            "empty": false,
+            "lower": lower,
+            "upper": upper,
+            "lowerInclusive": lower_inclusive,
+            "upperInclusive": upper_inclusive,
+        })
+    }
+
+    #[test]
+    fn binary_ranges() {
+        // range_send('[1,5)'::int4range)
+        let bytes = b"\x02\x00\x00\x00\x04\x00\x00\x00\x01\x00\x00\x00\x04\x00\x00\x00\x05";
+        assert_eq!(
+            range_to_json("INT4", false, PgValueFormat::Binary, bytes).unwrap(),
+            range(json!(1), json!(5), true, false)
+        );
+
+        // range_send('(,2024-01-01]'::daterange), the upper bound is normalized to 2024-01-02)
+        let bytes = b"\x08\x00\x00\x00\x04\x00\x00\x22?";
+        assert_eq!(
+            range_to_json("DATE", false, PgValueFormat::Binary, bytes).unwrap(),
+            range(json!(null), json!("2024-01-02"), false, false)
+        );
+
+        assert_eq!(
+            range_to_json("INT4", false, PgValueFormat::Binary, &[RANGE_EMPTY]).unwrap(),
+            empty_range()
+        );
+
+        // multirange_send('{[1,3),[5,7)}'::int4multirange)
+        let bytes = b"\x00\x00\x00\x02\x00\x00\x00\x11\x02\x00\x00\x00\x04\x00\x00\x00\x01\x00\x00\x00\x04\x00\x00\x00\x03\x00\x00\x00\x11\x02\x00\x00\x00\x04\x00\x00\x00\x05\x00\x00\x00\x04\x00\x00\x00\x07";
+        assert_eq!(
+            range_to_json("INT4", true, PgValueFormat::Binary, bytes).unwrap(),
+            json!([
+                range(json!(1), json!(3), true, false),
+                range(json!(5), json!(7), true, false)
+            ])
+        );
+    }
+
+    #[test]
+    fn text_ranges() {
+        let parse = |element: &str, multirange: bool, text: &str| {
+            range_to_json(element, multirange, PgValueFormat::Text, text.as_bytes())
+        };
+
+        assert_eq!(
+            parse("INT8", false, "[1,10)").unwrap(),
+            range(json!(1), json!(10), true, false)
+        );
+        assert_eq!(
+            parse(
+                "TIMESTAMP",
+                false,
+                r#"["2024-01-01 00:00:00","2024-02-01 00:00:00.5"]"#
+            )
+            .unwrap(),
+            range(
+                json!("2024-01-01 00:00:00"),
+                json!("2024-02-01 00:00:00.5"),
+                true,
+                true
+            )
+        );
+        assert_eq!(
+            parse("NUMERIC", false, "(,1.5]").unwrap(),
+            range(json!(null), json!("1.5"), false, true)
+        );
+        assert_eq!(parse("INT4", false, "empty").unwrap(), empty_range());
+        assert_eq!(
+            parse("INT4", true, "{[1,3), [5,7)}").unwrap(),
+            json!([
+                range(json!(1), json!(3), true, false),
+                range(json!(5), json!(7), true, false)
+            ])
+        );
+        assert_eq!(parse("INT4", true, "{}").unwrap(), json!([]));
+
+        assert!(parse("INT4", false, "[1,5").is_err());
+        assert!(parse("INT4", false, "1,5").is_err());
+        assert!(parse("DATE", false, r#"["2024-01-01,)"#).is_err());
+    }
+}
@@ -0,0 +1,224 @@
+use crate::utils::sqlx_pg_value::{read_bytes, read_i32};
+use sqlx::error::BoxDynError;
+
+// TSQUERY 二进制格式中的节点类型和运算符 | Item types and operators in the binary format of TSQUERY
+const QI_VAL: u8 = 1;
+const QI_OPR: u8 = 2;
+const OP_NOT: u8 = 1;
+const OP_AND: u8 = 2;
+const OP_OR: u8 = 3;
+const OP_PHRASE: u8 = 4;
+
+/// 把二进制格式的 TSVECTOR 转为文本形式, 和 PostgreSQL 的输出一致, 例如 `'cat':3 'fat':2A`
+/// Convert a binary TSVECTOR to its textual form, same as PostgreSQL's output, e.g. `'cat':3 'fat':2A`
+///
+/// 二进制格式: 词条数, 每个词条是以 0 结尾的文本, 2 字节位置数, 然后是每个 2 字节的位置
+/// 位置的高 2 位是权重, 3 为 A, 2 为 B, 1 为 C, 0 为默认的 D 不输出
+///
+/// Binary format: the lexeme count, each lexeme is a nul terminated text, a 2 byte position count,
+/// then 2 bytes per position, whose top 2 bits are the weight, 3 for A, 2 for B, 1 for C, 0 for the default D
+///
+/// # 参数
+/// - `reader`: 值的原始字节, 读取后前移 | Raw bytes of the value, advanced past what is read
+///
+pub fn binary_tsvector(reader: &mut &[u8]) -> Result<String, BoxDynError> {
+    let count = read_i32(reader)?;
+    let mut lexemes = Vec::new();
+
+    for _ in 0..count {
+        let mut lexeme = quote(&read_cstring(reader)?);
+        let positions = read_u16(reader)?;
+        for idx in 0..positions {
+            let position = read_u16(reader)?;
+            lexeme.push(if idx == 0 { ':' } else { ',' });
+            lexeme.push_str(&(position & 0x3fff).to_string());
+            match position >> 14 {
+                3 => lexeme.push('A'),
+                2 => lexeme.push('B'),
+                1 => lexeme.push('C'),
+                _ => {}
+            }
+        }
+        lexemes.push(lexeme);
+    }
+
+    Ok(lexemes.join(" "))
+}
+
+/// 把二进制格式的 TSQUERY 转为文本形式, 和 PostgreSQL 的输出一致, 例如 `'fat' & !( 'rat' | 'cat' )`
+/// Convert a binary TSQUERY to its textual form, same as PostgreSQL's output, e.g. `'fat' & !( 'rat' | 'cat' )`
+///
+/// 二进制格式: 节点数, 然后是前缀顺序的节点, 二元运算符先是右操作数再是左操作数
+/// 操作数节点: 类型, 权重, 前缀标记, 以 0 结尾的文本
+/// 运算符节点: 类型, 运算符, PHRASE 还有 2 字节的距离
+///
+/// Binary format: the item count, then the items in prefix order, binary operators have the right operand first
+/// Operand items: type, weight, prefix flag, nul terminated text
+/// Operator items: type, operator, and for PHRASE a 2 byte distance
+///
+/// # 参数
+/// - `reader`: 值的原始字节, 读取后前移 | Raw bytes of the value, advanced past what is read
+///
+pub fn binary_tsquery(reader: &mut &[u8]) -> Result<String, BoxDynError> {
+    let count = read_i32(reader)?;
+    if count == 0 {
+        return Ok(String::new());
+    }
+
+    let mut items = Vec::new();
+    for _ in 0..count {
+        items.push(match read_bytes(reader, 1)?[0] {
+            QI_VAL => {
+                let weight = read_bytes(reader, 1)?[0];
+                let prefix = read_bytes(reader, 1)?[0] != 0;
+                Item::Operand {
+                    text: read_cstring(reader)?,
+                    weight,
+                    prefix,
+                }
+            }
+            QI_OPR => match read_bytes(reader, 1)?[0] {
+                OP_PHRASE => Item::Operator(OP_PHRASE, read_u16(reader)?),
+                oper @ (OP_NOT | OP_AND | OP_OR) => Item::Operator(oper, 0),
+                oper => return Err(format!("invalid tsquery operator {}", oper).into()),
+            },
+            other => return Err(format!("invalid tsquery item type {}", other).into()),
+        });
+    }
+
+    let mut items = items.into_iter();
+    let (text, _) = query_text(&mut items)?;
+    if items.next().is_some() {
+        return Err("unexpected trailing items in tsquery value".into());
+    }
+    Ok(text)
+}
+
+enum Item {
+    Operand {
+        text: String,
+        weight: u8,
+        prefix: bool,
+    },
+    // 运算符和 PHRASE 的距离 | The operator and the PHRASE distance
+    Operator(u8, u16),
+}
+
+// 按前缀顺序还原表达式, 同时返回优先级, 用于决定是否需要括号
+// Rebuild the expression from prefix order, also returning its priority to decide on parentheses
+fn query_text(items: &mut impl Iterator<Item = Item>) -> Result<(String, u8), BoxDynError> {
+    let item = items.next().ok_or("unexpected end of tsquery value")?;
+    let (oper, distance) = match item {
+        Item::Operand {
+            text,
+            weight,
+            prefix,
+        } => {
+            let mut operand = quote(&text);
+            if prefix || weight != 0 {
+                operand.push(':');
+            }
+            if prefix {
+                operand.push('*');
+            }
+            for (bit, name) in [(8, 'A'), (4, 'B'), (2, 'C'), (1, 'D')] {
+                if weight & bit != 0 {
+                    operand.push(name);
+                }
+            }
+            return Ok((operand, u8::MAX));
+        }
+        Item::Operator(oper, distance) => (oper, distance),
+    };
+
+    let priority = match oper {
+        OP_OR => 1,
+        OP_AND => 2,
+        OP_PHRASE => 3,
+        _ => 4,
+    };
+    let wrap = |(text, child): (String, u8), right: bool| {
+        if child < priority || (right && child == priority && oper == OP_PHRASE) {
+            format!("( {} )", text)
+        } else {
+            text
+        }
+    };
+
+    if oper == OP_NOT {
+        let operand = wrap(query_text(items)?, false);
+        return Ok((format!("!{}", operand), priority));
+    }
+
+    let right = wrap(query_text(items)?, true);
+    let left = wrap(query_text(items)?, false);
+    let symbol = match (oper, distance) {
+        (OP_OR, _) => " | ".to_string(),
+        (OP_AND, _) => " & ".to_string(),
+        (_, 1) => " <-> ".to_string(),
+        (_, distance) => format!(" <{}> ", distance),
+    };
+    Ok((format!("{}{}{}", left, symbol, right), priority))
+}
+
+// 单引号包裹, 单引号和反斜杠需要加倍 | Wrapped in single quotes, single quotes and backslashes are doubled
+fn quote(text: &str) -> String {
+    format!("'{}'", text.replace('\\', "\\\\").replace('\'', "''"))
+}
+
+fn read_cstring(reader: &mut &[u8]) -> Result<String, BoxDynError> {
+    let end = reader
+        .iter()
+        .position(|&b| b == 0)
+        .ok_or("unterminated string in text search value")?;
+    let text = std::str::from_utf8(&reader[..end])?.to_string();
+    *reader = &reader[end + 1..];
+    Ok(text)
+}
+
+fn read_u16(reader: &mut &[u8]) -> Result<u16, BoxDynError> {
+    Ok(u16::from_be_bytes(read_bytes(reader, 2)?.try_into()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tsvector_matches_the_server_output() {
+        // tsvectorsend('fat:2A cat:3 rat''s:1,5B')
+        let mut reader: &[u8] =
+            b"\x00\x00\x00\x03cat\x00\x00\x01\x00\x03fat\x00\x00\x01\xc0\x02rat's\x00\x00\x02\x00\x01\x80\x05";
+        assert_eq!(
+            binary_tsvector(&mut reader).unwrap(),
+            "'cat':3 'fat':2A 'rat''s':1,5B"
+        );
+        assert!(reader.is_empty());
+
+        let mut reader: &[u8] = b"\x00\x00\x00\x01cat";
+        assert!(binary_tsvector(&mut reader).is_err());
+    }
+
+    #[test]
+    fn tsquery_matches_the_server_output() {
+        // tsquerysend('fat & !(rat | cat:*A)')
+        let mut reader: &[u8] = b"\x00\x00\x00\x06\x02\x02\x02\x01\x02\x03\x01\x08\x01cat\x00\x01\x00\x00rat\x00\x01\x00\x00fat\x00";
+        assert_eq!(
+            binary_tsquery(&mut reader).unwrap(),
+            "'fat' & !( 'rat' | 'cat':*A )"
+        );
+
+        // tsquerysend('a <2> b <-> c')
+        let mut reader: &[u8] = b"\x00\x00\x00\x05\x02\x04\x00\x01\x01\x00\x00c\x00\x02\x04\x00\x02\x01\x00\x00b\x00\x01\x00\x00a\x00";
+        assert_eq!(binary_tsquery(&mut reader).unwrap(), "'a' <2> 'b' <-> 'c'");
+
+        let mut reader: &[u8] = b"\x00\x00\x00\x00";
+        assert_eq!(binary_tsquery(&mut reader).unwrap(), "");
+
+        // 缺少右操作数 | The right operand is missing
+        let mut reader: &[u8] = b"\x00\x00\x00\x02\x02\x02\x01\x00\x00a\x00";
+        assert!(binary_tsquery(&mut reader).is_err());
+        let mut reader: &[u8] = b"\x00\x00\x00\x01\x02\x09";
+        assert!(binary_tsquery(&mut reader).is_err());
+    }
+}
@@ -0,0 +1,661 @@
+use crate::utils::sqlx_pg_array::array_to_json;
+use crate::utils::sqlx_pg_custom::composite_to_json;
+use crate::utils::sqlx_pg_geometry::geometry_to_json;
+use crate::utils::sqlx_pg_range::{range_element, range_to_json};
+use crate::utils::sqlx_pg_tsearch::{binary_tsquery, binary_tsvector};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use bigdecimal::BigDecimal;
+use serde_json::{json, Value};
+use sqlx::error::BoxDynError;
+use sqlx::postgres::{PgTypeInfo, PgTypeKind, PgValueFormat};
+use sqlx::types::chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use sqlx::TypeInfo;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+
+// PostgreSQL 的日期和时间从 2000-01-01 开始计算
+// PostgreSQL dates and timestamps count from 2000-01-01
+const PG_EPOCH_DAYS: i32 = 730_120;
+const PG_EPOCH_MICROS: i64 = 946_684_800_000_000;
+// 一天的微秒数, TIME 的最大值 24:00:00 | Microseconds in a day, the largest TIME 24:00:00
+const MICROS_PER_DAY: i64 = 86_400_000_000;
+
+/// 类型的种类, 通过文本协议查询的自定义类型只有 oid, 返回 None
+/// Kind of the type, None for custom types queried over the text protocol, which only have an oid
+///
+/// sqlx 对没有解析的类型调用 kind() 会 panic
+/// sqlx panics when kind() is called on an unresolved type
+pub fn type_kind(type_info: &PgTypeInfo) -> Option<&PgTypeKind> {
+    match type_info.name() {
+        "?" => None,
+        _ => Some(type_info.kind()),
+    }
+}
+
+/// 类型名, sqlx 不认识的内置类型统一为大写的名称
+/// Type name, built-in types sqlx doesn't know get an upper case name
+///
+/// 这些类型通过预处理语句查询时是 pg_type 中的小写名称, 通过文本协议查询时只有 oid
+/// Queried with prepared statements these types have their lower case pg_type name, over the text protocol only the oid
+pub fn pg_type_name(type_info: &PgTypeInfo) -> &str {
+    match (type_info.name(), type_info.oid().map(|oid| oid.0)) {
+        ("xml", _) | (_, Some(142)) => "XML",
+        ("tsvector", _) | (_, Some(3614)) => "TSVECTOR",
+        ("tsquery", _) | (_, Some(3615)) => "TSQUERY",
+        (name, _) => name,
+    }
+}
+
+/// 按原始字节把值转为 json, 用于数组的元素这类无法通过 sqlx 单独解码的值
+/// Convert a value to JSON from its raw bytes, for values sqlx can't decode on their own such as array elements
+///
+/// 转换结果和 convert_value_pg 中对应的列类型一致
+/// The results are the same as for the corresponding column types in convert_value_pg
+///
+/// # 参数
+/// - `type_info`: 值的类型 | Type of the value
+/// - `format`: 值的格式 | Format of the value
+/// - `bytes`: 值的原始字节, 不能为 NULL | Raw bytes of the value, must not be NULL
+/// - `money_scale`: 二进制格式的 MONEY 的小数位数, 来自 resolve_money_scale
+///   | Scale of MONEY in the binary format, from resolve_money_scale
+///
+pub fn raw_to_json(
+    type_info: &PgTypeInfo,
+    format: PgValueFormat,
+    bytes: &[u8],
+    money_scale: u32,
+) -> Result<Value, BoxDynError> {
+    if let Some((element, multirange)) = range_element(type_info) {
+        return range_to_json(element, multirange, format, bytes);
+    }
+    match type_kind(type_info) {
+        Some(PgTypeKind::Array(element)) => {
+            return array_to_json(element, format, bytes, money_scale)
+        }
+        Some(PgTypeKind::Enum(_)) => return Ok(json!(std::str::from_utf8(bytes)?)),
+        Some(PgTypeKind::Domain(base)) => return raw_to_json(base, format, bytes, money_scale),
+        Some(PgTypeKind::Composite(fields)) => {
+            return composite_to_json(fields, format, bytes, |ty, format, bytes| {
+                raw_to_json(ty, format, bytes, money_scale)
+            })
+        }
+        _ => {}
+    }
+
+    named_to_json(pg_type_name(type_info), format, bytes, money_scale)
+}
+
+/// 按类型名把值转为 json, 用于没有 PgTypeInfo 的内置类型, 不处理范围和数组
+/// Convert a value to JSON by its type name, for built-in types without a PgTypeInfo, ranges and arrays aren't handled
+///
+/// # 参数
+/// - `type_name`: 大写的类型名, 例如 INT4 | Upper case type name, e.g. INT4
+/// - `format`: 值的格式 | Format of the value
+/// - `bytes`: 值的原始字节, 不能为 NULL | Raw bytes of the value, must not be NULL
+/// - `money_scale`: 二进制格式的 MONEY 的小数位数 | Scale of MONEY in the binary format
+///
+pub fn named_to_json(
+    type_name: &str,
+    format: PgValueFormat,
+    bytes: &[u8],
+    money_scale: u32,
+) -> Result<Value, BoxDynError> {
+    match type_name {
+        "POINT" | "LINE" | "LSEG" | "BOX" | "PATH" | "POLYGON" | "CIRCLE" => {
+            geometry_to_json(type_name, format, bytes)
+        }
+        _ => match format {
+            PgValueFormat::Binary => binary_to_json(type_name, bytes, money_scale),
+            PgValueFormat::Text => Ok(text_to_json(type_name, std::str::from_utf8(bytes)?)?),
+        },
+    }
+}
+
+fn binary_to_json(type_name: &str, bytes: &[u8], money_scale: u32) -> Result<Value, BoxDynError> {
+    let mut reader = bytes;
+    let value = match type_name {
+        "BOOL" => json!(read_bytes(&mut reader, 1)?[0] != 0),
+        "INT2" => json!(i16::from_be_bytes(read_bytes(&mut reader, 2)?.try_into()?)),
+        "INT4" => json!(read_i32(&mut reader)?),
+        "INT8" => json!(read_i64(&mut reader)?),
+        "FLOAT4" => json!(f32::from_be_bytes(read_bytes(&mut reader, 4)?.try_into()?)),
+        "FLOAT8" => json!(f64::from_be_bytes(read_bytes(&mut reader, 8)?.try_into()?)),
+        "NUMERIC" => json!(binary_numeric(&mut reader)?),
+        "TEXT" | "VARCHAR" | "CHAR" | "BPCHAR" | "NAME" | "\"CHAR\"" => {
+            json!(std::str::from_utf8(std::mem::take(&mut reader))?)
+        }
+        "JSON" => serde_json::from_slice(std::mem::take(&mut reader))?,
+        // JSONB 的二进制格式以 1 字节版本号开头 | The binary format of JSONB starts with a 1 byte version
+        "JSONB" => {
+            read_bytes(&mut reader, 1)?;
+            serde_json::from_slice(std::mem::take(&mut reader))?
+        }
+        "BYTEA" => json!(STANDARD.encode(std::mem::take(&mut reader))),
+        "DATE" => match read_i32(&mut reader)? {
+            i32::MAX => json!("infinity"),
+            i32::MIN => json!("-infinity"),
+            days => json!(pg_date(days)?.format("%Y-%m-%d").to_string()),
+        },
+        "TIME" => json!(pg_time(read_i64(&mut reader)?, "%H:%M:%S")?),
+        "TIMESTAMP" | "TIMESTAMPTZ" => match read_i64(&mut reader)? {
+            i64::MAX => json!("infinity"),
+            i64::MIN => json!("-infinity"),
+            micros if type_name == "TIMESTAMPTZ" => json!(pg_timestamp(micros)?.to_rfc3339()),
+            micros => json!(pg_timestamp(micros)?
+                .naive_utc()
+                .format("%Y-%m-%d %H:%M:%S")
+                .to_string()),
+        },
+        "UUID" => json!(uuid::Uuid::from_slice(std::mem::take(&mut reader))?.to_string()),
+        "INET" | "CIDR" => json!(binary_inet(type_name, &mut reader)?),
+        "MACADDR" => json!(mac_address(read_bytes(&mut reader, 6)?)),
+        "MACADDR8" => json!(mac_address(read_bytes(&mut reader, 8)?)),
+        "OID" => json!(u32::from_be_bytes(read_bytes(&mut reader, 4)?.try_into()?)),
+        // 二进制格式: 微秒数, 天数, 月数 | Binary format: microseconds, days, months
+        "INTERVAL" => {
+            let micros = read_i64(&mut reader)?;
+            let days = read_i32(&mut reader)?;
+            let months = read_i32(&mut reader)?;
+            interval_json(months, days, micros)
+        }
+        // 以最小货币单位存储, 小数位数取决于连接的 lc_monetary
+        // Stored in the smallest currency unit, the scale depends on the lc_monetary of the connection
+        "MONEY" => json!(money_text(read_i64(&mut reader)?, money_scale)),
+        // 二进制格式: 微秒数, 以秒为单位的时区偏移, 向西为正
+        // Binary format: microseconds, the zone offset in seconds, positive west of UTC
+        "TIMETZ" => {
+            let time = pg_time(read_i64(&mut reader)?, "%H:%M:%S%.f")?;
+            // 和文本格式一样去掉小数末尾的 0 | Trailing zeros of the fraction are trimmed as in the text format
+            let time = if time.contains('.') {
+                time.trim_end_matches('0').trim_end_matches('.')
+            } else {
+                &time
+            };
+            let offset = read_i32(&mut reader)?;
+            json!(format!("{}{}", time, offset_text(-offset)))
+        }
+        // 二进制格式: 位数, 然后是按字节存储的位 | Binary format: the bit count, then the bits packed into bytes
+        "BIT" | "VARBIT" => {
+            let len = usize::try_from(read_i32(&mut reader)?)?;
+            let bits = std::mem::take(&mut reader);
+            if bits.len() != len.div_ceil(8) {
+                return Err(format!("invalid {} length {}", type_name, len).into());
+            }
+            json!((0..len)
+                .map(|i| if bits[i / 8] & (0x80 >> (i % 8)) != 0 {
+                    '1'
+                } else {
+                    '0'
+                })
+                .collect::<String>())
+        }
+        "XML" => json!(std::str::from_utf8(std::mem::take(&mut reader))?),
+        "TSVECTOR" => json!(binary_tsvector(&mut reader)?),
+        "TSQUERY" => json!(binary_tsquery(&mut reader)?),
+        _ => return Err(format!("unsupported PostgreSQL type {}", type_name).into()),
+    };
+
+    if !reader.is_empty() {
+        return Err(format!("unexpected trailing data in {} value", type_name).into());
+    }
+    Ok(value)
+}
+
+// 文本格式的值本身就是字符串, 只转换和列类型的结果不同的类型
+// A value in the text format already is a string, only types whose column results differ are converted
+fn text_to_json(type_name: &str, text: &str) -> Result<Value, BoxDynError> {
+    Ok(match type_name {
+        "BOOL" => json!(text == "t"),
+        "INT2" | "INT4" | "INT8" => json!(text.parse::<i64>()?),
+        "FLOAT4" | "FLOAT8" => json!(text.parse::<f64>()?),
+        "JSON" | "JSONB" => serde_json::from_str(text)?,
+        "BYTEA" => {
+            let hex = text
+                .strip_prefix("\\x")
+                .ok_or_else(|| format!("unsupported bytea format '{}'", text))?;
+            let bytes = (0..hex.len())
+                .step_by(2)
+                .map(|i| u8::from_str_radix(hex.get(i..i + 2).unwrap_or_default(), 16))
+                .collect::<Result<Vec<_>, _>>()?;
+            json!(STANDARD.encode(bytes))
+        }
+        // 无法解析的值 (例如 24:00:00) 原样返回 | Values that can't be parsed (e.g. 24:00:00) are returned as is
+        "TIME" => match NaiveTime::parse_from_str(text, "%H:%M:%S%.f") {
+            Ok(time) => json!(time.format("%H:%M:%S").to_string()),
+            Err(_) => json!(text),
+        },
+        "TIMESTAMP" => match NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S%.f") {
+            Ok(ts) => json!(ts.format("%Y-%m-%d %H:%M:%S").to_string()),
+            Err(_) => json!(text),
+        },
+        "TIMESTAMPTZ" => match DateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S%.f%#z") {
+            Ok(ts) => json!(ts.with_timezone(&Utc).to_rfc3339()),
+            Err(_) => json!(text),
+        },
+        "OID" => json!(text.parse::<u32>()?),
+        "INTERVAL" => match parse_interval(text) {
+            Some((months, days, micros)) => interval_json(months, days, micros),
+            None => json!(text),
+        },
+        "MONEY" => json!(parse_money(text).ok_or_else(|| format!("invalid money '{}'", text))?),
+        // 偏移统一为 +08:00 的形式, 和二进制格式一致 | The offset is normalized to +08:00, same as the binary format
+        "TIMETZ" => {
+            let split = text
+                .rfind(['+', '-'])
+                .ok_or_else(|| format!("invalid timetz '{}'", text))?;
+            let (time, offset) = text.split_at(split);
+            let mut parts = offset[1..].split(':').map(str::parse::<i32>);
+            let seconds = parts.next().transpose()?.unwrap_or(0) * 3600
+                + parts.next().transpose()?.unwrap_or(0) * 60
+                + parts.next().transpose()?.unwrap_or(0);
+            let sign = if offset.starts_with('-') { -1 } else { 1 };
+            json!(format!("{}{}", time, offset_text(sign * seconds)))
+        }
+        _ => json!(text),
+    })
+}
+
+// 区间转为 ISO 8601 的时长和各部分, 例如 {"iso": "P1Y2M3DT4H5M6.5S", "years": 1, "months": 2, "days": 3,
+// "hours": 4, "minutes": 5, "seconds": 6, "microseconds": 500000}
+// 和 PostgreSQL 一样, 月数按 12 折算为年, 微秒折算为时分秒, 天数不折算, 各部分可以有不同的符号
+// An interval becomes an ISO 8601 duration and its parts, e.g. {"iso": "P1Y2M3DT4H5M6.5S", "years": 1, "months": 2,
+// "days": 3, "hours": 4, "minutes": 5, "seconds": 6, "microseconds": 500000}
+// As in PostgreSQL, months are folded into years by 12, microseconds into hours, minutes and seconds,
+// days are not folded, and the parts may have different signs
+fn interval_json(months: i32, days: i32, micros: i64) -> Value {
+    let (years, months) = (months / 12, months % 12);
+    let (hours, minutes) = (micros / 3_600_000_000, micros / 60_000_000 % 60);
+    let (seconds, fraction) = (micros / 1_000_000 % 60, micros % 1_000_000);
+
+    let mut iso = String::from("P");
+    for (value, unit) in [(years, 'Y'), (months, 'M'), (days, 'D')] {
+        if value != 0 {
+            iso.push_str(&format!("{}{}", value, unit));
+        }
+    }
+    if hours != 0 || minutes != 0 || seconds != 0 || fraction != 0 {
+        iso.push('T');
+        for (value, unit) in [(hours, 'H'), (minutes, 'M')] {
+            if value != 0 {
+                iso.push_str(&format!("{}{}", value, unit));
+            }
+        }
+        if seconds != 0 || fraction != 0 {
+            let sign = if seconds < 0 || fraction < 0 { "-" } else { "" };
+            let fraction = format!(".{:06}", fraction.abs());
+            let fraction = fraction.trim_end_matches('0').trim_end_matches('.');
+            iso.push_str(&format!("{}{}{}S", sign, seconds.abs(), fraction));
+        }
+    }
+    if iso == "P" {
+        iso.push_str("T0S");
+    }
+
+    json!({
+        "iso": iso,
+        "years": years,
+        "months": months,
+        "days": days,
+        "hours": hours,
+        "minutes": minutes,
+        "seconds": seconds,
+        "microseconds": fraction,
+    })
+}
+
+// 解析默认的 postgres 输出格式, 例如 "1 year 2 mons -3 days +04:05:06.5", 其它格式返回 None
+// Parse the default postgres output style, e.g. "1 year 2 mons -3 days +04:05:06.5", None for other styles
+fn parse_interval(text: &str) -> Option<(i32, i32, i64)> {
+    let (mut months, mut days, mut micros) = (0i32, 0i32, 0i64);
+    let mut tokens = text.split_whitespace();
+
+    while let Some(token) = tokens.next() {
+        if token.contains(':') {
+            let (sign, time) = match token.strip_prefix('-') {
+                Some(time) => (-1, time),
+                None => (1, token.trim_start_matches('+')),
+            };
+            let mut parts = time.split(':');
+            let hours: i64 = parts.next()?.parse().ok()?;
+            let minutes: i64 = parts.next()?.parse().ok()?;
+            let seconds: f64 = parts.next().unwrap_or("0").parse().ok()?;
+            let total = hours * 3_600_000_000 + minutes * 60_000_000;
+            micros += sign * (total + (seconds * 1_000_000.0).round() as i64);
+            continue;
+        }
+
+        let value: i32 = token.parse().ok()?;
+        match tokens.next()?.trim_end_matches('s') {
+            "year" => months += value * 12,
+            "mon" => months += value,
+            "day" => days += value,
+            _ => return None,
+        }
+    }
+
+    Some((months, days, micros))
+}
+
+// 按小数位数把最小货币单位转为数字文本 | Turn the smallest currency unit into number text with the scale
+fn money_text(cents: i64, scale: u32) -> String {
+    if scale == 0 {
+        return cents.to_string();
+    }
+    let unit = 10u64.pow(scale);
+    let sign = if cents < 0 { "-" } else { "" };
+    let abs = cents.unsigned_abs();
+    format!(
+        "{}{}.{:0width$}",
+        sign,
+        abs / unit,
+        abs % unit,
+        width = scale as usize
+    )
+}
+
+/// 由 `'1'::money` 的文本得到小数位数, 1 后面的数字都是小数, 例如 $1.00 是 2, ￥1 是 0, BD 1.000 是 3
+/// The scale from the text of `'1'::money`, all digits after the 1 are the fraction,
+/// e.g. 2 for $1.00, 0 for ￥1 and 3 for BD 1.000
+///
+/// 值是 1 时没有千位分隔符, 不需要像 parse_money 那样猜测小数点
+/// A value of 1 has no thousands separators, so the decimal point doesn't have to be guessed as in parse_money
+pub fn money_scale(one: &str) -> Option<u32> {
+    let digits = one.chars().filter(char::is_ascii_digit).count();
+    u32::try_from(digits.checked_sub(1)?).ok()
+}
+
+// 文本格式按 lc_monetary 格式化, 例如 $1,234.56 或 -$1,234.56
+// 只保留数字和小数点, 最后一个 . 或 , 后面不是 3 位数字时当作小数点
+// The text format is formatted by lc_monetary, e.g. $1,234.56 or -$1,234.56
+// Only digits and the decimal point are kept, the last . or , is the decimal point unless 3 digits follow it
+fn parse_money(text: &str) -> Option<String> {
+    let negative = text.contains('-') || text.starts_with('(');
+    let separator = text.rfind(['.', ',']).filter(|&idx| {
+        let rest = &text[idx + 1..];
+        let digits = rest.chars().take_while(char::is_ascii_digit).count();
+        let same = text[..idx].contains(&text[idx..idx + 1]);
+        digits != 3 || (!same && text[..idx].contains(['.', ',']))
+    });
+
+    let digits = |s: &str| s.chars().filter(char::is_ascii_digit).collect::<String>();
+    let (whole, fraction) = match separator {
+        Some(idx) => (digits(&text[..idx]), digits(&text[idx + 1..])),
+        None => (digits(text), String::new()),
+    };
+    if whole.is_empty() && fraction.is_empty() {
+        return None;
+    }
+
+    let mut money = String::new();
+    if negative {
+        money.push('-');
+    }
+    money.push_str(if whole.is_empty() { "0" } else { &whole });
+    if !fraction.is_empty() {
+        money.push('.');
+        money.push_str(&fraction);
+    }
+    Some(money)
+}
+
+// 时区偏移, 单位秒, 向东为正, 例如 +08:00 | Zone offset in seconds, positive east of UTC, e.g. +08:00
+fn offset_text(seconds: i32) -> String {
+    let sign = if seconds < 0 { '-' } else { '+' };
+    let abs = seconds.unsigned_abs();
+    let text = format!("{}{:02}:{:02}", sign, abs / 3600, abs / 60 % 60);
+    match abs % 60 {
+        0 => text,
+        secs => format!("{}:{:02}", text, secs),
+    }
+}
+
+fn mac_address(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+// INET/CIDR 的二进制格式: 地址族, 前缀长度, 是否是 CIDR, 地址长度, 地址
+// 和文本格式一致, INET 的前缀长度是完整长度时省略
+// Binary format of INET/CIDR: address family, prefix length, whether it is a CIDR, address length, address
+// Same as the text format, the prefix length of an INET is omitted when it is the full length
+fn binary_inet(type_name: &str, reader: &mut &[u8]) -> Result<String, BoxDynError> {
+    let header = read_bytes(reader, 4)?;
+    let (bits, len) = (header[1], usize::from(header[3]));
+    let addr = read_bytes(reader, len)?;
+
+    let (addr, max_bits) = match len {
+        4 => (Ipv4Addr::from(<[u8; 4]>::try_from(addr)?).to_string(), 32),
+        16 => (Ipv6Addr::from(<[u8; 16]>::try_from(addr)?).to_string(), 128),
+        _ => return Err(format!("invalid {} address length {}", type_name, len).into()),
+    };
+
+    if type_name == "INET" && bits == max_bits {
+        Ok(addr)
+    } else {
+        Ok(format!("{}/{}", addr, bits))
+    }
+}
+
+// NUMERIC 的二进制格式: 位数, 权重, 符号, 小数位数, 然后是以 10000 为基数的各位
+// 转为字符串避免精度丢失, 和 NUMERIC 列一致
+// Binary format of NUMERIC: digit count, weight, sign, display scale, then the base 10000 digits
+// Converted to a string to avoid losing precision, same as NUMERIC columns
+pub fn binary_numeric(reader: &mut &[u8]) -> Result<String, BoxDynError> {
+    let mut read_i16 = || -> Result<i16, BoxDynError> {
+        Ok(i16::from_be_bytes(read_bytes(reader, 2)?.try_into()?))
+    };
+    let ndigits = read_i16()?;
+    let weight = read_i16()?;
+    let sign = read_i16()? as u16;
+    let scale = read_i16()?.max(0) as usize;
+    let digits = (0..ndigits)
+        .map(|_| read_i16())
+        .collect::<Result<Vec<_>, _>>()?;
+
+    match sign {
+        0xC000 => return Ok("NaN".to_string()),
+        0xD000 => return Ok("Infinity".to_string()),
+        0xF000 => return Ok("-Infinity".to_string()),
+        _ => {}
+    }
+
+    let digit = |idx: i32| -> i16 {
+        usize::try_from(idx)
+            .ok()
+            .and_then(|idx| digits.get(idx))
+            .copied()
+            .unwrap_or(0)
+    };
+
+    let mut text = String::new();
+    if sign == 0x4000 {
+        text.push('-');
+    }
+    if weight < 0 {
+        text.push('0');
+    } else {
+        text.push_str(&digit(0).to_string());
+        for idx in 1..=i32::from(weight) {
+            text.push_str(&format!("{:04}", digit(idx)));
+        }
+    }
+
+    if scale > 0 {
+        let mut fraction = String::new();
+        let mut idx = i32::from(weight) + 1;
+        while fraction.len() < scale {
+            fraction.push_str(&format!("{:04}", digit(idx)));
+            idx += 1;
+        }
+        fraction.truncate(scale);
+        text.push('.');
+        text.push_str(&fraction);
+    }
+
+    // 检查结果是合法的数字 | Check that the result is a valid number
+    BigDecimal::from_str(&text)?;
+    Ok(text)
+}
+
+// 从午夜开始的微秒数, 按 format 格式化, NaiveTime 不能表示 24:00:00, 直接返回文本
+// Microseconds since midnight formatted with format, NaiveTime can't represent 24:00:00 so its text is returned
+fn pg_time(micros: i64, format: &str) -> Result<String, BoxDynError> {
+    if micros == MICROS_PER_DAY {
+        return Ok("24:00:00".to_string());
+    }
+    let time = NaiveTime::from_num_seconds_from_midnight_opt(
+        u32::try_from(micros.div_euclid(1_000_000))?,
+        u32::try_from(micros.rem_euclid(1_000_000) * 1000)?,
+    )
+    .ok_or("time out of range")?;
+    Ok(time.format(format).to_string())
+}
+
+// 从 2000-01-01 开始的天数, 不处理 infinity
+// Days since 2000-01-01, infinity is not handled
+pub fn pg_date(days: i32) -> Result<NaiveDate, BoxDynError> {
+    days.checked_add(PG_EPOCH_DAYS)
+        .and_then(NaiveDate::from_num_days_from_ce_opt)
+        .ok_or_else(|| "date out of range".into())
+}
+
+// 从 2000-01-01 开始的微秒数, 不处理 infinity
+// Microseconds since 2000-01-01, infinity is not handled
+pub fn pg_timestamp(micros: i64) -> Result<DateTime<Utc>, BoxDynError> {
+    micros
+        .checked_add(PG_EPOCH_MICROS)
+        .and_then(DateTime::<Utc>::from_timestamp_micros)
+        .ok_or_else(|| "timestamp out of range".into())
+}
+
+pub fn read_bytes<'a>(
+    reader: &mut &'a [u8],
+    len: impl TryInto<usize>,
+) -> Result<&'a [u8], BoxDynError> {
+    let len = len.try_into().map_err(|_| "invalid length in value")?;
+    if reader.len() < len {
+        return Err("unexpected end of value".into());
+    }
+    let (head, rest) = reader.split_at(len);
+    *reader = rest;
+    Ok(head)
+}
+
+pub fn read_i32(reader: &mut &[u8]) -> Result<i32, BoxDynError> {
+    Ok(i32::from_be_bytes(read_bytes(reader, 4)?.try_into()?))
+}
+
+pub fn read_i64(reader: &mut &[u8]) -> Result<i64, BoxDynError> {
+    Ok(i64::from_be_bytes(read_bytes(reader, 8)?.try_into()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn binary(type_name: &str, bytes: &[u8]) -> Value {
+        named_to_json(type_name, PgValueFormat::Binary, bytes, 2).unwrap()
+    }
+
+    fn text(type_name: &str, text: &str) -> Value {
+        named_to_json(type_name, PgValueFormat::Text, text.as_bytes(), 2).unwrap()
+    }
+
+    #[test]
+    fn time_accepts_the_end_of_the_day() {
+        // time_send('24:00:00')
+        assert_eq!(binary("TIME", b"\x00\x00\x00\x14\x1d\xd7`\x00"), "24:00:00");
+        assert_eq!(text("TIME", "24:00:00"), "24:00:00");
+        assert_eq!(text("TIME", "12:34:56.5"), "12:34:56");
+        assert!(named_to_json("TIME", PgValueFormat::Binary, &(-1i64).to_be_bytes(), 2).is_err());
+
+        // timetz_send('24:00:00+02'), timetz_send('12:34:56.789-05:30')
+        assert_eq!(
+            binary("TIMETZ", b"\x00\x00\x00\x14\x1d\xd7`\x00\xff\xff\xe3\xe0"),
+            "24:00:00+02:00"
+        );
+        assert_eq!(
+            binary("TIMETZ", b"\x00\x00\x00\x0a\x8b\xe6&\x08\x00\x00MX"),
+            "12:34:56.789-05:30"
+        );
+        assert_eq!(text("TIMETZ", "12:34:56.789-05:30"), "12:34:56.789-05:30");
+        assert_eq!(text("TIMETZ", "24:00:00+02"), "24:00:00+02:00");
+    }
+
+    #[test]
+    fn money_uses_the_scale_of_the_connection() {
+        let cents = (-123456i64).to_be_bytes();
+        for (scale, expected) in [(2, "-1234.56"), (0, "-123456"), (3, "-123.456")] {
+            let value = named_to_json("MONEY", PgValueFormat::Binary, &cents, scale).unwrap();
+            assert_eq!(value, expected);
+        }
+
+        assert_eq!(money_scale("$1.00"), Some(2));
+        assert_eq!(money_scale("￥1"), Some(0));
+        assert_eq!(money_scale("BD 1.000"), Some(3));
+        assert_eq!(money_scale("1,00 €"), Some(2));
+        assert_eq!(money_scale("$"), None);
+
+        assert_eq!(text("MONEY", "-$1,234.56"), "-1234.56");
+        assert_eq!(text("MONEY", "($1,234.56)"), "-1234.56");
+        assert_eq!(text("MONEY", "1.234,56 €"), "1234.56");
+        assert_eq!(text("MONEY", "$1,234"), "1234");
+    }
+
+    #[test]
+    fn interval_becomes_iso_and_parts() {
+        // interval_send('1 year 2 mons 3 days 04:05:06.5')
+        let value = binary(
+            "INTERVAL",
+            b"\x00\x00\x00\x03l\x93a\xa0\x00\x00\x00\x03\x00\x00\x00\x0e",
+        );
+        assert_eq!(value["iso"], "P1Y2M3DT4H5M6.5S");
+        assert_eq!(value["months"], 2);
+        assert_eq!(value["microseconds"], 500_000);
+        assert_eq!(text("INTERVAL", "1 year 2 mons 3 days 04:05:06.5"), value);
+
+        assert_eq!(text("INTERVAL", "-00:00:01.25")["iso"], "PT-1.25S");
+        assert_eq!(text("INTERVAL", "00:00:00")["iso"], "PT0S");
+        assert_eq!(text("INTERVAL", "P1Y"), "P1Y");
+    }
+
+    #[test]
+    fn binary_scalars_match_the_column_results() {
+        // numeric_send(-1234.5678), numeric_send(0.0001)
+        assert_eq!(
+            binary("NUMERIC", b"\x00\x02\x00\x00@\x00\x00\x04\x04\xd2\x16."),
+            "-1234.5678"
+        );
+        assert_eq!(
+            binary("NUMERIC", b"\x00\x01\xff\xff\x00\x00\x00\x04\x00\x01"),
+            "0.0001"
+        );
+        // bit_send(B'10110')
+        assert_eq!(binary("VARBIT", b"\x00\x00\x00\x05\xb0"), "10110");
+        assert_eq!(binary("DATE", &i32::MAX.to_be_bytes()), "infinity");
+        assert_eq!(binary("DATE", &0i32.to_be_bytes()), "2000-01-01");
+        assert_eq!(
+            binary("MACADDR8", &[8, 0, 0x2b, 1, 2, 3, 4, 5]),
+            "08:00:2b:01:02:03:04:05"
+        );
+        assert_eq!(binary("OID", &u32::MAX.to_be_bytes()), u32::MAX);
+        assert_eq!(text("BYTEA", "\\x0102"), "AQI=");
+        assert_eq!(text("BOOL", "t"), true);
+    }
+
+    #[test]
+    fn malformed_values_are_errors() {
+        let decode = |type_name: &str, bytes: &[u8]| {
+            named_to_json(type_name, PgValueFormat::Binary, bytes, 2)
+        };
+        assert!(decode("INT4", &[0, 0, 0, 1, 0]).is_err());
+        assert!(decode("INT8", &[0, 0, 0, 1]).is_err());
+        assert!(decode("VARBIT", b"\x00\x00\x00\x09\xb0").is_err());
+        assert!(decode("NO_SUCH_TYPE", &[]).is_err());
+        assert!(named_to_json("MONEY", PgValueFormat::Text, b"$", 2).is_err());
+    }
+}
@@ -1,19 +1,37 @@
 use super::{
-    sqlx_common::{DbConnection, DbPool},
+    sqlx_cancel::{rename_running, track},
+    sqlx_common::{elapsed_ms, DbConnection, DbPool, PooledConn},
+    sqlx_config::{build_options, tunnel_target, DbConnectOptions},
+    sqlx_cursor::{close_cursors, rename_cursors},
+    sqlx_error::{DbError, DbErrorCode},
+    sqlx_guard::{check_destructive, check_read_only},
     sqlx_mysql::query_mysql,
     sqlx_pg::query_pg,
+    sqlx_pg_custom::{clear_types, rename_types},
+    sqlx_session::{close_sessions, detach_sessions, rename_sessions, SessionConn, TxControl},
     sqlx_sqlite::query_sqlite,
+    sqlx_tunnel::{close_tunnel, open_tunnel, rename_tunnel, replace_tunnel, LocalAddr, Tunnel},
 };
 use crate::{
-    types::{ExecResult, QueryResult},
+    types::{
+        ConnConfig, ConnSettings, ConnSummary, ConnectInfo, ExecResult, PoolSettings, QueryResult,
+        ServerInfo, TlsState,
+    },
     utils::{common::print_sql, sqlx_common::DbType},
 };
 use sqlparser::{
+    ast::Statement,
     dialect::{Dialect, MySqlDialect, PostgreSqlDialect, SQLiteDialect},
     parser::{Parser, ParserError},
     tokenizer::Token,
 };
-use sqlx::{Error, Execute};
+use sqlx::{Executor, Row};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+
+// execute_many 在已有事务中执行时使用的保存点名称
+// Savepoint name used by execute_many when running inside an open transaction
+const BATCH_SAVEPOINT: &str = "dibim_exec_many";
 
 // TODO: 判断 SQL 语句的光标位置
 pub fn get_cursor_context(sql: &str, cursor_pos: usize) -> String {
@@ -30,7 +48,7 @@ pub fn get_cursor_context(sql: &str, cursor_pos: usize) -> String {
     }
 }
 
-fn split_sql_statements(sql: &str, dialect: &dyn Dialect) -> Result<Vec<String>, ParserError> {
+fn split_sql_statements(sql: &str, dialect: &dyn Dialect) -> Result<Vec<Statement>, ParserError> {
     let mut parser = Parser::new(dialect).try_with_sql(sql)?;
     let mut statements = Vec::new();
 
@@ -48,7 +66,7 @@ fn split_sql_statements(sql: &str, dialect: &dyn Dialect) -> Result<Vec<String>,
             }
         };
 
-        statements.push(stmt.to_string());
+        statements.push(stmt);
 
         // 显式跳过语句终止符（分号）
         if parser.peek_token().token == Token::SemiColon {
@@ -64,216 +82,845 @@ fn split_sql_statements(sql: &str, dialect: &dyn Dialect) -> Result<Vec<String>,
     Ok(statements)
 }
 
-pub fn get_db_type(url: &str) -> Result<DbType, Error> {
-    let url_lower = url.to_lowercase();
+pub fn dialect(db_type: DbType) -> Box<dyn Dialect> {
+    match db_type {
+        DbType::Postgres => Box::new(PostgreSqlDialect {}),
+        DbType::MySql => Box::new(MySqlDialect {}),
+        DbType::Sqlite => Box::new(SQLiteDialect {}),
+    }
+}
 
-    if url_lower.starts_with("postgres://") {
-        Ok(DbType::Postgres)
-    } else if url_lower.starts_with("mysql://") {
-        Ok(DbType::MySql)
-    } else if url_lower.starts_with("sqlite:") {
-        Ok(DbType::Sqlite)
-    } else {
-        Err(Error::Configuration(
-            format!("Unsupported database scheme in URL: {}", url).into(),
-        ))
+// 判断单条 SQL 是否为事务控制语句, 无法解析时当作普通语句交给数据库
+// Check whether a single SQL is a transaction control statement, SQL that can't be parsed is left to the database
+fn parse_tx_control(sql: &str, db_type: DbType) -> Option<TxControl> {
+    let statements = Parser::parse_sql(&*dialect(db_type), sql).ok()?;
+    match statements.as_slice() {
+        [stmt] => TxControl::from_statement(stmt),
+        _ => None,
     }
 }
 
-pub async fn connect(conn_name: &str, url: &str) -> Result<(), sqlx::Error> {
-    let db_type = get_db_type(url)?;
-    DbPool::global().connect(db_type, conn_name, url).await
+// 连接池里的连接用完就放回, 在它上面开始的事务没有意义, 还会把未结束的事务带回连接池
+// A pooled connection goes back right after use, a transaction started on it is meaningless
+// and would bring an unfinished transaction back into the pool
+fn session_required() -> DbError {
+    DbError::new(
+        DbErrorCode::InvalidState,
+        "Transaction statements must be run in a session",
+    )
 }
 
-pub async fn disconnect(conn_name: &str) -> Result<bool, sqlx::Error> {
-    Ok(DbPool::global().disconnect(conn_name).await)
+pub async fn connect(
+    conn_name: &str,
+    config: &ConnConfig,
+    settings: ConnSettings,
+) -> Result<ConnectInfo, DbError> {
+    let tunnel_addr = match &config.ssh {
+        Some(ssh) => {
+            let (host, port) = tunnel_target(config)?;
+            Some(open_tunnel(conn_name, ssh, &host, port).await?)
+        }
+        None => None,
+    };
+
+    let res = connect_pool(conn_name, config, tunnel_addr.as_ref(), settings).await;
+    if res.is_err() && tunnel_addr.is_some() {
+        close_tunnel(conn_name).await;
+    }
+    res
 }
 
-// 执行查询语句并返回 JSON 数组
-// Execute query statement and return JSON array
-pub async fn query(
+async fn connect_pool(
     conn_name: &str,
-    sql: &str,
-    streaming: bool,
-    page: Option<usize>,
-    page_size: Option<usize>,
-) -> Result<QueryResult, Box<dyn std::error::Error>> {
+    config: &ConnConfig,
+    tunnel_addr: Option<&LocalAddr>,
+    settings: ConnSettings,
+) -> Result<ConnectInfo, DbError> {
+    let options = connect_options(config, tunnel_addr)?;
     let db_conn = DbPool::global()
-        .get(conn_name)
+        .connect(conn_name, options, config.clone(), settings)
+        .await?;
+    // 连接已经登记, 拿不到连接时撤销登记, 以免留下一个用不了的连接
+    // The connection is already registered, unregister it when no connection can be acquired
+    // so an unusable connection isn't left behind
+    let (tls, version) = match probe(&db_conn).await {
+        Ok(probed) => probed,
+        Err(e) => {
+            DbPool::global().disconnect(conn_name).await;
+            return Err(e);
+        }
+    };
+    if let Some(version) = version {
+        DbPool::global()
+            .set_server_version(conn_name, version)
+            .await;
+    }
+
+    Ok(ConnectInfo { tls })
+}
+
+// 从连接池取一个连接, 读取 TLS 状态和服务端版本
+// 读取 TLS 状态和版本失败不影响连接
+// Acquire a connection from the pool and read the TLS state and the server version
+// Failing to read the TLS state or the version doesn't fail the connection
+async fn probe(db_conn: &DbConnection) -> Result<(Option<TlsState>, Option<String>), DbError> {
+    let mut conn = db_conn.acquire().await?;
+    let tls = tls_state(&mut conn)
+        .await
+        .inspect_err(|e| eprintln!("Failed to read the TLS state: {:?}", e))
+        .unwrap_or_default();
+    let version = server_version(&mut conn)
         .await
-        .ok_or_else(|| format!("Connection '{}' not found", conn_name))?;
+        .inspect_err(|e| eprintln!("Failed to read the server version: {:?}", e))
+        .ok();
+    Ok((tls, version))
+}
 
-    match db_conn {
-        DbConnection::Postgres(pool) => query_pg(&pool, sql, streaming, page, page_size).await,
-        DbConnection::MySql(pool) => query_mysql(&pool, sql, streaming, page, page_size).await,
-        DbConnection::Sqlite(pool) => query_sqlite(&pool, sql, streaming, page, page_size).await,
+// 建立新的隧道 (不登记) 和连接池, 确认能连上之后才替换旧的, 失败时旧连接不受影响
+// Open a new tunnel (unregistered) and pool, the old ones are only replaced once this works,
+// so a failure leaves the old connection untouched
+async fn open_replacement(
+    config: &ConnConfig,
+    settings: &ConnSettings,
+) -> Result<(DbConnection, Option<Tunnel>), DbError> {
+    let tunnel = match &config.ssh {
+        Some(ssh) => {
+            let (host, port) = tunnel_target(config)?;
+            Some(Tunnel::start(ssh, &host, port).await?)
+        }
+        None => None,
+    };
+
+    let opened = match connect_options(config, tunnel.as_ref().map(Tunnel::local_addr)) {
+        Ok(options) => DbConnection::open(options, settings).await,
+        Err(e) => Err(e),
+    };
+    match opened {
+        Ok(db_conn) => Ok((db_conn, tunnel)),
+        Err(e) => {
+            if let Some(tunnel) = tunnel {
+                tunnel.close().await;
+            }
+            Err(e)
+        }
     }
 }
 
-// 执行单条非查询语句
-// Execute a single non query statement
-pub async fn exec(conn_name: &str, sql: &str) -> Result<ExecResult, Box<dyn std::error::Error>> {
-    let db_conn = DbPool::global()
-        .get(conn_name)
+async fn close_replacement(db_conn: DbConnection, tunnel: Option<Tunnel>) {
+    db_conn.close().await;
+    // 隧道在连接池关闭后才能关闭 | The tunnel can only be closed after the pool
+    if let Some(tunnel) = tunnel {
+        tunnel.close().await;
+    }
+}
+
+/// 重新连接, 会话, 游标和隧道都会关闭 | Reconnect, sessions, cursors and the tunnel are closed
+///
+/// 先用新的参数建立连接池和隧道, 成功后才替换旧的, 连接失败时旧连接继续可用
+/// The new pool and tunnel are opened first and only then swapped in, the old connection keeps working
+/// when the new one fails
+///
+/// # 参数
+/// - `conn_name`: 连接名 | Connection name
+/// - `config`: 新的连接参数, 为空时使用上次连接的参数 | New connection parameters, the previous ones when empty
+/// - `settings`: 新的连接设置, 为空时使用上次连接的设置 | New connection settings, the previous ones when empty
+///
+pub async fn reconnect(
+    conn_name: &str,
+    config: Option<ConnConfig>,
+    settings: Option<ConnSettings>,
+) -> Result<ConnectInfo, DbError> {
+    let (prev_config, prev_settings) = DbPool::global()
+        .config(conn_name)
         .await
-        .ok_or_else(|| format!("Connection '{}' not found", conn_name))?;
+        .ok_or_else(|| DbError::connection_not_found(conn_name))?;
+    let config = config.unwrap_or(prev_config);
+    let settings = settings.unwrap_or(prev_settings);
+
+    let (db_conn, tunnel) = open_replacement(&config, &settings).await?;
+    let (tls, version) = match probe(&db_conn).await {
+        Ok(probed) => probed,
+        Err(e) => {
+            close_replacement(db_conn, tunnel).await;
+            return Err(e);
+        }
+    };
 
-    match db_conn {
-        DbConnection::Postgres(pool) => {
-            let query = sqlx::query(sql);
+    let old = match DbPool::global()
+        .replace_config(conn_name, db_conn.clone(), config, settings)
+        .await
+    {
+        Some(old) => old,
+        None => {
+            // 重连期间连接被断开 | The connection was disconnected while reconnecting
+            close_replacement(db_conn, tunnel).await;
+            return Err(DbError::connection_not_found(conn_name));
+        }
+    };
+    let old_tunnel = replace_tunnel(conn_name, tunnel);
+    if let Some(version) = version {
+        DbPool::global()
+            .set_server_version(conn_name, version)
+            .await;
+    }
 
-            #[cfg(debug_assertions)]
-            {
-                print_sql(query.sql(), 7);
-            }
+    // 会话和游标还占着旧连接池的连接, 先关闭它们, 旧连接池才能关闭, 旧隧道最后关闭
+    // Sessions and cursors still hold connections of the old pool, so they are closed first,
+    // then the old pool, and the old tunnel last
+    close_cursors(conn_name).await;
+    close_sessions(conn_name).await;
+    close_replacement(old, old_tunnel).await;
+    clear_types(conn_name);
 
-            let result = query.execute(&pool).await?;
-            Ok(ExecResult {
-                affected_rows: result.rows_affected(),
-                last_insert_id: 0, // PostgreSQL 需要 RETURNING 子句
-            })
+    Ok(ConnectInfo { tls })
+}
+
+// 在原来的名称下重新建立连接池和隧道, 连接一直保持登记, 用于自动重连
+// 和 reconnect 一样关闭会话和游标, 之后使用它们会得到 session_not_found 和 cursor_not_found
+// Rebuild the pool and the tunnel under the same name, the connection stays registered, used for automatic reconnecting
+// Sessions and cursors are closed like on reconnect, using them afterwards gives session_not_found and cursor_not_found
+pub async fn reopen(conn_name: &str) -> Result<(), DbError> {
+    let (config, settings) = DbPool::global()
+        .config(conn_name)
+        .await
+        .ok_or_else(|| DbError::connection_not_found(conn_name))?;
+
+    // 旧的隧道多半已经断开, 但新的连接池连上之前不动它, 重连失败时连接保持原样
+    // The old tunnel is most likely broken, but it's left alone until the new pool connects,
+    // so a failed attempt leaves the connection as it was
+    let (db_conn, tunnel) = open_replacement(&config, &settings).await?;
+    match DbPool::global().replace(conn_name, db_conn.clone()).await {
+        Some(old) => {
+            let old_tunnel = replace_tunnel(conn_name, tunnel);
+            // 服务端可能已重建或切换, 缓存的类型 oid 不再可靠
+            // The server may have been rebuilt or failed over, so the cached type oids can't be trusted
+            clear_types(conn_name);
+            // 会话和游标立即移除, 但在旧连接上回滚可能要等到超时, 所以不在这里等待
+            // 会话关闭后旧连接池才能关闭, 旧隧道最后关闭
+            // Sessions and cursors are removed right away, but rolling back on the old connections may
+            // only end with a timeout, so don't wait here
+            // The old pool can only close once the sessions are closed, the old tunnel is closed last
+            close_cursors(conn_name).await;
+            let sessions = detach_sessions(conn_name).await;
+            tokio::spawn(async move {
+                sessions.await;
+                close_replacement(old, old_tunnel).await;
+            });
+            Ok(())
+        }
+        None => {
+            // 重连期间连接被断开 | The connection was disconnected while reconnecting
+            close_replacement(db_conn, tunnel).await;
+            Err(DbError::connection_not_found(conn_name))
         }
-        DbConnection::MySql(pool) => {
-            let query = sqlx::query(sql);
+    }
+}
 
-            #[cfg(debug_assertions)]
-            {
-                print_sql(query.sql(), 7);
-            }
+// 重命名连接, 会话, 游标和隧道随之移到新名称下
+// Rename the connection, its sessions, cursors and tunnel move to the new name
+pub async fn rename(conn_name: &str, new_name: &str) -> Result<(), DbError> {
+    if new_name.is_empty() {
+        return Err(DbError::new(
+            DbErrorCode::InvalidArgument,
+            "The connection name can't be empty",
+        ));
+    }
+    if conn_name == new_name {
+        return Ok(());
+    }
+
+    // 新名称下已有隧道 (同名连接正在建立) 时拒绝, 不能覆盖它
+    // Refuse when a tunnel already exists under the new name (a connection with that name is being opened),
+    // it must not be overwritten
+    rename_tunnel(conn_name, new_name)?;
+    if let Err(e) = DbPool::global().rename(conn_name, new_name).await {
+        let _ = rename_tunnel(new_name, conn_name);
+        return Err(e);
+    }
+    rename_sessions(conn_name, new_name).await;
+    rename_cursors(conn_name, new_name).await;
+    rename_running(conn_name, new_name);
+    rename_types(conn_name, new_name);
+    Ok(())
+}
 
-            let result = query.execute(&pool).await?;
-            Ok(ExecResult {
-                affected_rows: result.rows_affected(),
-                last_insert_id: result.last_insert_id(),
+pub async fn list_connections() -> Vec<ConnSummary> {
+    DbPool::global().list().await
+}
+
+// 服务端版本, 用文本协议查询
+// Server version, queried over the text protocol
+async fn server_version(conn: &mut PooledConn) -> Result<String, DbError> {
+    Ok(match conn {
+        PooledConn::Postgres(conn) => conn.fetch_one("SHOW server_version").await?.try_get(0)?,
+        PooledConn::MySql(conn) => conn.fetch_one("SELECT VERSION()").await?.try_get(0)?,
+        PooledConn::Sqlite(conn) => conn
+            .fetch_one("SELECT sqlite_version()")
+            .await?
+            .try_get(0)?,
+    })
+}
+
+fn connect_options(
+    config: &ConnConfig,
+    tunnel_addr: Option<&LocalAddr>,
+) -> Result<DbConnectOptions, DbError> {
+    match tunnel_addr {
+        // 通过隧道的套接字连接, 主机名不变, TLS 的 SNI 和证书校验仍然针对数据库的主机名
+        // PostgreSQL 的 socket 是目录, 在其中找 .s.PGSQL.<端口>; MySQL 的 socket 是文件本身
+        // Connect through the socket of the tunnel, the host is unchanged so TLS SNI and certificate
+        // verification still use the database host name
+        // The PostgreSQL socket is the directory it looks up .s.PGSQL.<port> in, for MySQL it's the file itself
+        #[cfg(unix)]
+        Some(LocalAddr::Socket(path)) => {
+            let (_, port) = tunnel_target(config)?;
+            let socket = match config.engine {
+                DbType::Postgres => path.parent().unwrap_or(path),
+                _ => path.as_path(),
+            };
+            build_options(&ConnConfig {
+                port: Some(port),
+                socket: Some(socket.to_string_lossy().into_owned()),
+                ..config.clone()
             })
         }
-        DbConnection::Sqlite(pool) => {
-            let query = sqlx::query(sql);
+        // 通过隧道的本地端口连接, 使用 verify-full 时证书的主机名需要包含 127.0.0.1
+        // Connect through the local port of the tunnel, with verify-full the certificate must cover 127.0.0.1
+        #[cfg(not(unix))]
+        Some(LocalAddr::Port(port)) => build_options(&ConnConfig {
+            host: Some("127.0.0.1".to_string()),
+            port: Some(*port),
+            ..config.clone()
+        }),
+        None => build_options(config),
+    }
+}
 
-            #[cfg(debug_assertions)]
-            {
-                print_sql(query.sql(), 7);
+/// 测试连接并返回服务端信息, 不登记到连接列表, 用完即关闭
+/// Test the connection and return the server information, nothing is registered and everything is closed afterwards
+///
+/// # 参数
+/// - `config`: 连接参数 | Connection parameters
+/// - `settings`: 连接的设置, 连接池大小会被忽略 | Connection settings, the pool size is ignored
+///
+pub async fn test_connection(
+    config: &ConnConfig,
+    settings: ConnSettings,
+) -> Result<ServerInfo, DbError> {
+    let tunnel = match &config.ssh {
+        Some(ssh) => {
+            let (host, port) = tunnel_target(config)?;
+            Some(Tunnel::start(ssh, &host, port).await?)
+        }
+        None => None,
+    };
+
+    let res = test_pool(config, tunnel.as_ref().map(Tunnel::local_addr), settings).await;
+    if let Some(tunnel) = tunnel {
+        tunnel.close().await;
+    }
+    res
+}
+
+async fn test_pool(
+    config: &ConnConfig,
+    tunnel_addr: Option<&LocalAddr>,
+    settings: ConnSettings,
+) -> Result<ServerInfo, DbError> {
+    // 只需要一个连接 | Only one connection is needed
+    let settings = ConnSettings {
+        pool: PoolSettings {
+            min_connections: Some(0),
+            max_connections: Some(1),
+            ..settings.pool
+        },
+        ..settings
+    };
+    let db_conn = DbConnection::open(connect_options(config, tunnel_addr)?, &settings).await?;
+
+    let res = async {
+        let mut conn = db_conn.acquire().await?;
+        server_info(&mut conn, config).await
+    }
+    .await;
+
+    db_conn.close().await;
+    res
+}
+
+// 查询服务端信息, 用文本协议以便关闭预处理语句时也能使用
+// Query the server information over the text protocol, so it also works with prepared statements disabled
+async fn server_info(conn: &mut PooledConn, config: &ConnConfig) -> Result<ServerInfo, DbError> {
+    let start = Instant::now();
+    conn.ping().await?;
+    let latency_ms = elapsed_ms(start);
+
+    let mut info = match conn {
+        PooledConn::Postgres(conn) => {
+            let row = conn
+                .fetch_one(
+                    "SELECT version(), current_user, current_database(), \
+                     current_setting('server_encoding'), current_setting('TimeZone')",
+                )
+                .await?;
+            ServerInfo {
+                latency_ms,
+                version: row.try_get(0)?,
+                user: row.try_get(1)?,
+                database: row.try_get(2)?,
+                encoding: row.try_get(3)?,
+                time_zone: row.try_get(4)?,
+                tls: None,
+            }
+        }
+        PooledConn::MySql(conn) => {
+            // time_zone 为 SYSTEM 时使用系统时区 | The system time zone is used when time_zone is SYSTEM
+            let row = conn
+                .fetch_one(
+                    "SELECT VERSION(), CURRENT_USER(), DATABASE(), @@character_set_server, \
+                     IF(@@time_zone = 'SYSTEM', @@system_time_zone, @@time_zone)",
+                )
+                .await?;
+            ServerInfo {
+                latency_ms,
+                version: row.try_get(0)?,
+                user: row.try_get(1)?,
+                database: row.try_get(2)?,
+                encoding: row.try_get(3)?,
+                time_zone: row.try_get(4)?,
+                tls: None,
             }
+        }
+        PooledConn::Sqlite(conn) => {
+            let version = conn.fetch_one("SELECT sqlite_version()").await?;
+            let encoding = conn.fetch_one("PRAGMA encoding").await?;
+            // SQLite 没有用户和时区 | SQLite has no users or time zones
+            ServerInfo {
+                latency_ms,
+                version: version.try_get(0)?,
+                user: None,
+                database: config.database.clone(),
+                encoding: encoding.try_get(0)?,
+                time_zone: None,
+                tls: None,
+            }
+        }
+    };
 
-            let result = query.execute(&pool).await?;
-            Ok(ExecResult {
-                affected_rows: result.rows_affected(),
-                last_insert_id: result.last_insert_rowid() as u64,
-            })
+    info.tls = tls_state(conn)
+        .await
+        .inspect_err(|e| eprintln!("Failed to read the TLS state: {:?}", e))
+        .unwrap_or_default();
+    Ok(info)
+}
+
+// 查询连接实际协商的 TLS 状态, 用文本协议以便关闭预处理语句时也能使用
+// Query the TLS state the connection actually negotiated, over the text protocol
+// so it also works with prepared statements disabled
+async fn tls_state(conn: &mut PooledConn) -> Result<Option<TlsState>, DbError> {
+    match conn {
+        PooledConn::Postgres(conn) => {
+            let row = conn
+                .fetch_optional(
+                    "SELECT ssl, version, cipher FROM pg_stat_ssl WHERE pid = pg_backend_pid()",
+                )
+                .await?;
+            Ok(row
+                .map(|row| -> Result<TlsState, sqlx::Error> {
+                    Ok(TlsState {
+                        enabled: row.try_get(0)?,
+                        version: row.try_get(1)?,
+                        cipher: row.try_get(2)?,
+                    })
+                })
+                .transpose()?)
+        }
+        PooledConn::MySql(conn) => {
+            let rows = conn
+                .fetch_all(
+                    "SHOW SESSION STATUS WHERE Variable_name IN ('Ssl_version', 'Ssl_cipher')",
+                )
+                .await?;
+            let mut state = TlsState::default();
+            for row in rows {
+                let name: String = row.try_get(0)?;
+                // 未加密时值为空字符串 | The value is an empty string when not encrypted
+                let value = Some(row.try_get::<String, _>(1)?).filter(|v| !v.is_empty());
+                match name.as_str() {
+                    "Ssl_version" => state.version = value,
+                    "Ssl_cipher" => state.cipher = value,
+                    _ => {}
+                }
+            }
+            state.enabled = state.version.is_some();
+            Ok(Some(state))
         }
+        PooledConn::Sqlite(_) => Ok(None),
     }
 }
 
-// 执行多条非查询语句
-// Execute multiple non query statements
-pub async fn execute_many(
+// 获取连接的设置
+// Get the settings of the connection
+async fn conn_settings(conn_name: &str) -> Result<ConnSettings, DbError> {
+    DbPool::global()
+        .settings(conn_name)
+        .await
+        .ok_or_else(|| DbError::connection_not_found(conn_name))
+}
+
+pub async fn disconnect(conn_name: &str) -> Result<bool, DbError> {
+    close_cursors(conn_name).await;
+    // 关闭连接池会等待所有连接放回, 所以先关闭会话
+    // Closing the pool waits for all connections to be returned, so close the sessions first
+    close_sessions(conn_name).await;
+    let disconnected = DbPool::global().disconnect(conn_name).await;
+    // 隧道在连接池关闭后才能关闭 | The tunnel can only be closed after the pool
+    close_tunnel(conn_name).await;
+    clear_types(conn_name);
+    Ok(disconnected)
+}
+
+/// 关闭所有连接, 应用退出时调用 | Close all connections, called when the application exits
+///
+/// 丢弃所有游标, 回滚会话中未提交的事务, 关闭所有连接池和 SSH 隧道
+/// 顺序和 disconnect 相同, 连接池并行关闭
+///
+/// Drops all cursors, rolls back the open transactions of the sessions, closes all pools and SSH tunnels
+/// Same order as disconnect, the pools are closed in parallel
+///
+pub async fn close_all() -> usize {
+    let names = DbPool::global().names().await;
+    for name in &names {
+        close_cursors(name).await;
+        close_sessions(name).await;
+    }
+    let closed = DbPool::global().close_all().await;
+    for name in &names {
+        close_tunnel(name).await;
+        clear_types(name);
+    }
+    closed
+}
+
+// 执行查询语句并返回 JSON 数组
+// 查询中也可能有破坏性的语句, 和 exec 一样需要带上确认令牌, 见 check_destructive
+// Execute query statement and return JSON array
+// Queries may contain destructive statements too, they need a confirmation token like exec, see check_destructive
+pub async fn query(
     conn_name: &str,
     sql: &str,
-) -> Result<ExecResult, Box<dyn std::error::Error>> {
-    let db_conn = DbPool::global()
-        .get(conn_name)
-        .await
-        .ok_or_else(|| format!("Connection '{}' not found", conn_name))?;
+    max_rows: Option<usize>,
+    session_id: Option<&str>,
+    statement_id: Option<&str>,
+    timeout_ms: Option<u64>,
+    confirm_token: Option<&str>,
+) -> Result<QueryResult, DbError> {
+    let settings = conn_settings(conn_name).await?;
+    let prepared = settings.use_prepared();
+    let mut target = SessionConn::acquire(conn_name, session_id).await?;
+    // 查询也可能修改数据, 例如 SELECT set_config(...) 或调用会写表的函数
+    // Queries can modify data too, e.g. SELECT set_config(...) or functions that write tables
+    if settings.is_read_only() {
+        check_read_only(sql, target.conn().db_type())?;
+    }
+    check_destructive(conn_name, sql, target.conn().db_type(), confirm_token)?;
+    // 事务控制语句和 exec 一样处理, 否则会在连接池的连接上留下事务, 会话记录的状态也会和服务端不一致
+    // Transaction control statements are handled like exec, otherwise they would leave a transaction
+    // on a pooled connection and the session's state would no longer match the server
+    if let Some(control) = parse_tx_control(sql, target.conn().db_type()) {
+        let start = Instant::now();
+        match &mut target {
+            SessionConn::Session(session) => session.apply(&control, sql).await?,
+            SessionConn::Pooled(_) => return Err(session_required()),
+        }
+        return Ok(QueryResult {
+            elapsed_ms: elapsed_ms(start),
+            ..Default::default()
+        });
+    }
+    let running = track(conn_name, statement_id, timeout_ms, &settings, &mut target).await?;
 
-    match db_conn {
-        DbConnection::Postgres(pool) => {
-            let mut tx = pool.begin().await?;
+    let res = match target.conn() {
+        PooledConn::Postgres(conn) => query_pg(conn_name, conn, sql, max_rows, prepared).await,
+        PooledConn::MySql(conn) => query_mysql(conn, sql, max_rows, prepared).await,
+        PooledConn::Sqlite(conn) => query_sqlite(conn, sql, max_rows, prepared).await,
+    };
+    target.after_statement(sql);
 
-            #[cfg(debug_assertions)]
-            {
-                print_sql("Transaction start", 1);
-            }
+    running.finish(&mut target, res).await
+}
 
-            let dialect = PostgreSqlDialect {};
-            let statements = split_sql_statements(sql, &dialect)?;
-            for stmt in statements {
-                let query = sqlx::query(&stmt);
+// 执行单条非查询语句, 在会话中执行的事务控制语句会更新会话的事务状态
+// Execute a single non query statement, transaction control statements run in a session update its transaction state
+// 破坏性的语句需要带上确认令牌, 见 check_destructive
+// Destructive statements need a confirmation token, see check_destructive
+pub async fn exec(
+    conn_name: &str,
+    sql: &str,
+    session_id: Option<&str>,
+    statement_id: Option<&str>,
+    timeout_ms: Option<u64>,
+    confirm_token: Option<&str>,
+) -> Result<ExecResult, DbError> {
+    let settings = conn_settings(conn_name).await?;
+    let mut target = SessionConn::acquire(conn_name, session_id).await?;
+    if settings.is_read_only() {
+        check_read_only(sql, target.conn().db_type())?;
+    }
+    check_destructive(conn_name, sql, target.conn().db_type(), confirm_token)?;
+    let running = track(conn_name, statement_id, timeout_ms, &settings, &mut target).await?;
+    let res = exec_on(&mut target, sql, settings.use_prepared()).await;
+    running.finish(&mut target, res).await
+}
 
-                #[cfg(debug_assertions)]
-                {
-                    let sql = format!("{};\n", query.sql());
-                    print_sql(&sql, 2);
-                }
-                query.execute(&mut *tx).await?;
+async fn exec_on(
+    target: &mut SessionConn,
+    sql: &str,
+    prepared: bool,
+) -> Result<ExecResult, DbError> {
+    #[cfg(debug_assertions)]
+    {
+        print_sql(sql, 7);
+    }
+
+    let start = Instant::now();
+    let control = parse_tx_control(sql, target.conn().db_type());
+    let (affected_rows, last_insert_id) = match control {
+        Some(control) => match target {
+            SessionConn::Session(session) => {
+                session.apply(&control, sql).await?;
+                (0, None)
             }
+            SessionConn::Pooled(_) => return Err(session_required()),
+        },
+        None => {
+            let res = target.conn().execute(sql, prepared).await;
+            target.after_statement(sql);
+            res?
+        }
+    };
+
+    Ok(ExecResult {
+        affected_rows,
+        last_insert_id,
+        elapsed_ms: elapsed_ms(start),
+        in_transaction: session_state(target),
+    })
+}
 
-            tx.commit().await?;
+/// 执行多条非查询语句 | Execute multiple non query statements
+///
+/// 语句在一个事务中执行, 任意一条出错时全部回滚
+/// 会话已在事务中时改用保存点, 出错只回滚这一批语句
+/// 会话中包含事务控制语句时不再额外包一层事务, 按语句逐条执行
+///
+/// Statements run in one transaction, all are rolled back if any fails
+/// When the session is already in a transaction a savepoint is used instead, so only this batch is rolled back
+/// When the batch run in a session contains transaction control statements, no extra transaction is added
+/// and statements run one by one
+///
+/// # 参数
+/// - `conn_name`: 连接名 | Connection name
+/// - `sql`: 要执行的多条 sql | The SQL statements to be executed
+/// - `session_id`: 会话 id, None 表示从连接池取一个连接 | Session id, None takes a connection from the pool
+/// - `statement_id`: 用于取消的语句 id | Statement id used for cancelling
+/// - `timeout_ms`: 每条语句的超时, None 使用连接的默认值 | Timeout of each statement, None uses the connection default
+/// - `confirm_token`: 执行破坏性语句的确认令牌 | Confirmation token for running destructive statements
+///
+pub async fn execute_many(
+    conn_name: &str,
+    sql: &str,
+    session_id: Option<&str>,
+    statement_id: Option<&str>,
+    timeout_ms: Option<u64>,
+    confirm_token: Option<&str>,
+) -> Result<ExecResult, DbError> {
+    let settings = conn_settings(conn_name).await?;
+    let mut target = SessionConn::acquire(conn_name, session_id).await?;
+    if settings.is_read_only() {
+        check_read_only(sql, target.conn().db_type())?;
+    }
+    check_destructive(conn_name, sql, target.conn().db_type(), confirm_token)?;
+    let running = track(conn_name, statement_id, timeout_ms, &settings, &mut target).await?;
+    let cancelled = running.cancel_flag();
+    let res = execute_many_on(&mut target, sql, settings.use_prepared(), &cancelled).await;
+    running.finish(&mut target, res).await
+}
 
-            #[cfg(debug_assertions)]
-            {
-                print_sql("Transaction end", 4);
+async fn execute_many_on(
+    target: &mut SessionConn,
+    sql: &str,
+    prepared: bool,
+    cancelled: &AtomicBool,
+) -> Result<ExecResult, DbError> {
+    let statements = split_sql_statements(sql, &*dialect(target.conn().db_type()))
+        .map_err(|e| DbError::from(e).locate(sql))?;
+    let controls: Vec<Option<TxControl>> =
+        statements.iter().map(TxControl::from_statement).collect();
+    let has_control = controls.iter().any(Option::is_some);
+
+    let start = Instant::now();
+    let mut res = ExecResult::default();
+    let mut guard = BatchGuard {
+        target,
+        finished: false,
+    };
+
+    if has_control {
+        let session = match &mut *guard.target {
+            SessionConn::Session(session) => session,
+            SessionConn::Pooled(_) => {
+                guard.finished = true;
+                return Err(session_required());
             }
+        };
 
-            Ok(ExecResult {
-                affected_rows: 0,
-                last_insert_id: 0, // PostgreSQL 需要 RETURNING 子句
-            })
-        }
-        DbConnection::MySql(pool) => {
-            let mut tx = pool.begin().await?;
+        for (index, (stmt, control)) in statements.iter().zip(&controls).enumerate() {
+            let stmt = stmt.to_string();
 
             #[cfg(debug_assertions)]
             {
-                print_sql("Transaction start", 1);
+                print_sql(&format!("{};\n", stmt), 2);
             }
 
-            let dialect = MySqlDialect {};
-            let statements = split_sql_statements(sql, &dialect)?;
-            for stmt in statements {
-                let query = sqlx::query(&stmt);
-
-                #[cfg(debug_assertions)]
-                {
-                    let sql = format!("{};\n", query.sql());
-                    print_sql(&sql, 2);
-                }
-                query.execute(&mut *tx).await?;
+            if cancelled.load(Ordering::Relaxed) {
+                guard.finished = true;
+                return Err(statement_error(batch_cancelled(), index, &stmt));
             }
 
-            tx.commit().await?;
+            let result = match control {
+                Some(control) => session.apply(control, &stmt).await,
+                None => {
+                    let result = session.conn().execute(&stmt, prepared).await;
+                    session.after_statement(&stmt);
+                    result
+                        .map(|result| add_result(&mut res, result))
+                        .map_err(DbError::from)
+                }
+            };
 
-            #[cfg(debug_assertions)]
-            {
-                print_sql("Transaction end", 4);
+            // 语句执行完 (无论成功与否) 会话记录的事务状态都和服务端一致
+            // Once a statement has completed, successfully or not, the session's transaction state matches the server
+            if let Err(e) = result {
+                guard.finished = true;
+                return Err(statement_error(e, index, &stmt));
             }
+        }
+    } else {
+        let in_transaction = guard.target.in_transaction();
+        let conn = guard.target.conn();
+        let (begin, end, rollback) = if in_transaction {
+            (
+                format!("SAVEPOINT {}", BATCH_SAVEPOINT),
+                format!("RELEASE SAVEPOINT {}", BATCH_SAVEPOINT),
+                format!("ROLLBACK TO SAVEPOINT {}", BATCH_SAVEPOINT),
+            )
+        } else {
+            (
+                "BEGIN".to_string(),
+                "COMMIT".to_string(),
+                "ROLLBACK".to_string(),
+            )
+        };
 
-            Ok(ExecResult {
-                affected_rows: 0,
-                last_insert_id: 0,
-            })
+        conn.execute_raw(&begin).await?;
+
+        #[cfg(debug_assertions)]
+        {
+            print_sql("Transaction start", 1);
         }
-        DbConnection::Sqlite(pool) => {
-            let mut tx = pool.begin().await?;
+
+        for (index, stmt) in statements.iter().enumerate() {
+            let stmt = stmt.to_string();
 
             #[cfg(debug_assertions)]
             {
-                print_sql("Transaction start", 1);
+                print_sql(&format!("{};\n", stmt), 2);
             }
 
-            let dialect = SQLiteDialect {};
-            let statements = split_sql_statements(sql, &dialect)?;
-            for stmt in statements {
-                let query = sqlx::query(&stmt);
-
-                #[cfg(debug_assertions)]
-                {
-                    let sql = format!("{};\n", query.sql());
-                    print_sql(&sql, 2);
+            // 已取消时不再执行后面的语句, 和语句出错一样回滚
+            // Once cancelled the remaining statements are skipped and rolled back like a failed statement
+            let result = if cancelled.load(Ordering::Relaxed) {
+                Err(batch_cancelled())
+            } else {
+                conn.execute(&stmt, prepared).await.map_err(DbError::from)
+            };
+
+            match result {
+                Ok(result) => add_result(&mut res, result),
+                Err(e) => {
+                    // 回滚失败时连接上还留着事务, 交给 guard 关闭连接
+                    // If the rollback fails the transaction is still open, the guard closes the connection
+                    let mut rolled_back = conn.execute_raw(&rollback).await.is_ok();
+                    // 回滚到保存点后还需要释放它 | The savepoint still has to be released after rolling back to it
+                    if rolled_back && in_transaction {
+                        rolled_back = conn.execute_raw(&end).await.is_ok();
+                    }
+                    guard.finished = rolled_back;
+                    return Err(statement_error(e, index, &stmt));
                 }
-                query.execute(&mut *tx).await?;
             }
+        }
 
-            tx.commit().await?;
+        conn.execute_raw(&end).await?;
 
-            #[cfg(debug_assertions)]
-            {
-                print_sql("Transaction end", 4);
-            }
+        #[cfg(debug_assertions)]
+        {
+            print_sql("Transaction end", 4);
+        }
+    }
 
-            Ok(ExecResult {
-                affected_rows: 0,
-                last_insert_id: 0,
-            })
+    guard.finished = true;
+    res.elapsed_ms = elapsed_ms(start);
+    res.in_transaction = session_state(guard.target);
+    Ok(res)
+}
+
+// 批量执行没有走到 COMMIT 或 ROLLBACK 就结束时 (future 被丢弃, 回滚失败等),
+// 连接上可能还留着事务或保存点, 丢弃时关闭连接而不是放回连接池
+// When a batch ends before reaching COMMIT or ROLLBACK (the future was dropped, the rollback failed, ...),
+// the connection may still hold a transaction or savepoint, so it is closed on drop instead of returned to the pool
+struct BatchGuard<'a> {
+    target: &'a mut SessionConn,
+    finished: bool,
+}
+
+impl Drop for BatchGuard<'_> {
+    fn drop(&mut self) {
+        if !self.finished {
+            self.target.abandon();
         }
     }
 }
+
+fn batch_cancelled() -> DbError {
+    DbError::new(
+        DbErrorCode::Cancelled,
+        "The batch was cancelled before this statement",
+    )
+}
+
+fn add_result(res: &mut ExecResult, (affected_rows, last_insert_id): (u64, Option<u64>)) {
+    res.affected_rows += affected_rows;
+    if last_insert_id.is_some() {
+        res.last_insert_id = last_insert_id;
+    }
+}
+
+fn session_state(target: &SessionConn) -> Option<bool> {
+    match target {
+        SessionConn::Session(session) => Some(session.in_transaction()),
+        SessionConn::Pooled(_) => None,
+    }
+}
+
+// 多条语句中某一条出错
+// 语句是由 sqlparser 重新生成的, 出错位置无法对应到原始 SQL, 这里改为标明是第几条语句
+// One of multiple statements failed
+// Statements are regenerated by sqlparser, so the error position can't be mapped back to the original SQL,
+// the message names the failing statement instead
+fn statement_error(mut err: DbError, index: usize, stmt: &str) -> DbError {
+    err.message = format!("Statement {} ({}): {}", index + 1, stmt, err.message);
+    err.details.position = None;
+    err
+}
@@ -0,0 +1,491 @@
+use super::{
+    sqlx_cancel::{backend_id, BackendId},
+    sqlx_common::{DbPool, DbType, PooledConn},
+    sqlx_error::{DbError, DbErrorCode},
+};
+use crate::types::SessionState;
+use once_cell::sync::Lazy;
+use sqlparser::ast::Statement;
+use sqlparser::dialect::MySqlDialect;
+use sqlparser::tokenizer::{Token, Tokenizer};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::sync::{Mutex, OwnedMutexGuard};
+
+// 事务控制语句
+// Transaction control statements
+#[derive(Debug, Clone)]
+pub enum TxControl {
+    Begin,
+    Commit { chain: bool },
+    Rollback { chain: bool },
+    Savepoint(String),
+    RollbackTo(String),
+    Release(String),
+}
+
+impl TxControl {
+    // 识别事务控制语句, 其它语句返回 None
+    // Recognize transaction control statements, None for other statements
+    pub fn from_statement(stmt: &Statement) -> Option<Self> {
+        match stmt {
+            Statement::StartTransaction { .. } => Some(TxControl::Begin),
+            Statement::Commit { chain, .. } => Some(TxControl::Commit { chain: *chain }),
+            Statement::Rollback {
+                savepoint: Some(name),
+                ..
+            } => Some(TxControl::RollbackTo(name.value.clone())),
+            Statement::Rollback { chain, .. } => Some(TxControl::Rollback { chain: *chain }),
+            Statement::Savepoint { name } => Some(TxControl::Savepoint(name.value.clone())),
+            Statement::ReleaseSavepoint { name } => Some(TxControl::Release(name.value.clone())),
+            _ => None,
+        }
+    }
+
+    // 生成对应的 SQL, 三种数据库的写法相同
+    // Generate the SQL, the syntax is the same for all three databases
+    pub fn sql(&self) -> Result<String, DbError> {
+        Ok(match self {
+            TxControl::Begin => "BEGIN".to_string(),
+            TxControl::Commit { .. } => "COMMIT".to_string(),
+            TxControl::Rollback { .. } => "ROLLBACK".to_string(),
+            TxControl::Savepoint(name) => format!("SAVEPOINT {}", check_name(name)?),
+            TxControl::RollbackTo(name) => format!("ROLLBACK TO SAVEPOINT {}", check_name(name)?),
+            TxControl::Release(name) => format!("RELEASE SAVEPOINT {}", check_name(name)?),
+        })
+    }
+}
+
+// 固定在一个连接上的会话, 对应编辑器的一个标签页
+// 临时表, SET 变量和事务都只在这个连接上有效
+// A session pinned to one connection, one per editor tab
+// Temp tables, SET variables and transactions only live on this connection
+pub struct Session {
+    conn: PooledConn,
+    // 连接在服务端的 id, 打开会话时查询一次, 关闭预处理语句时不查询
+    // Server side id of the connection, looked up once when the session opens, not when prepared statements are disabled
+    backend: Option<BackendId>,
+    in_transaction: bool,
+    savepoints: Vec<String>,
+    // 批量执行中途被中断, 事务状态未知, 只能关闭
+    // A batch was interrupted halfway, the transaction state is unknown so it can only be closed
+    interrupted: bool,
+}
+
+impl Session {
+    pub fn conn(&mut self) -> &mut PooledConn {
+        &mut self.conn
+    }
+
+    pub fn in_transaction(&self) -> bool {
+        self.in_transaction
+    }
+
+    // 事务状态已经和服务端对不上, 之后的语句都拒绝, 关闭时不放回连接池
+    // The transaction state no longer matches the server, later statements are refused
+    // and the connection isn't returned to the pool on close
+    fn abandon(&mut self) {
+        self.interrupted = true;
+        self.conn.close_on_drop();
+    }
+
+    // 执行事务控制语句并更新事务状态
+    // Execute a transaction control statement and update the transaction state
+    pub async fn apply(&mut self, control: &TxControl, sql: &str) -> Result<(), DbError> {
+        // MySQL 在事务中执行 BEGIN 会隐式提交, 这里直接拒绝
+        // MySQL implicitly commits on BEGIN inside a transaction, reject it here
+        if matches!(control, TxControl::Begin) && self.in_transaction {
+            return Err(DbError::new(
+                DbErrorCode::InvalidState,
+                "The session is already in a transaction",
+            ));
+        }
+
+        self.conn.execute_raw(sql).await?;
+
+        match control {
+            TxControl::Begin => {
+                self.in_transaction = true;
+                self.savepoints.clear();
+            }
+            TxControl::Commit { chain } | TxControl::Rollback { chain } => {
+                // AND CHAIN 结束后立即开始新事务
+                // AND CHAIN starts a new transaction right after
+                self.in_transaction = *chain;
+                self.savepoints.clear();
+            }
+            TxControl::Savepoint(name) => {
+                // SQLite 在事务外创建保存点会开始一个事务
+                // SQLite starts a transaction when a savepoint is created outside of one
+                self.in_transaction = true;
+                self.savepoints.push(name.clone());
+            }
+            TxControl::RollbackTo(name) => {
+                // 回滚到保存点后, 它之后创建的保存点失效, 它本身保留
+                // After rolling back to a savepoint, later savepoints are gone, the savepoint itself remains
+                if let Some(idx) = self.find_savepoint(name) {
+                    self.savepoints.truncate(idx + 1);
+                }
+            }
+            TxControl::Release(name) => {
+                if let Some(idx) = self.find_savepoint(name) {
+                    self.savepoints.truncate(idx);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // MySQL 执行 DDL 等语句前会隐式提交当前事务, 语句失败时也一样
+    // MySQL commits the current transaction implicitly before DDL and similar statements, even when they fail
+    pub fn after_statement(&mut self, sql: &str) {
+        if self.in_transaction && self.conn.db_type() == DbType::MySql && commits_implicitly(sql) {
+            self.in_transaction = false;
+            self.savepoints.clear();
+        }
+    }
+
+    fn find_savepoint(&self, name: &str) -> Option<usize> {
+        self.savepoints
+            .iter()
+            .rposition(|s| s.eq_ignore_ascii_case(name))
+    }
+
+    fn state(&self, id: &str) -> SessionState {
+        SessionState {
+            id: id.to_string(),
+            in_transaction: self.in_transaction,
+            savepoints: self.savepoints.clone(),
+        }
+    }
+}
+
+// 语句运行所在的连接: 会话固定的连接, 或从连接池临时取出的连接
+// The connection a statement runs on: the connection pinned by a session, or one taken from the pool
+pub enum SessionConn {
+    Pooled(PooledConn),
+    Session(OwnedMutexGuard<Session>),
+}
+
+impl SessionConn {
+    /// 获取执行语句的连接 | Get the connection to run statements on
+    ///
+    /// # 参数
+    /// - `conn_name`: 连接名 | Connection name
+    /// - `session_id`: 会话 id, None 表示从连接池取一个连接 | Session id, None takes a connection from the pool
+    ///
+    pub async fn acquire(conn_name: &str, session_id: Option<&str>) -> Result<Self, DbError> {
+        match session_id {
+            Some(id) => Ok(SessionConn::Session(lock_session(conn_name, id).await?)),
+            None => {
+                let db_conn = DbPool::global()
+                    .get(conn_name)
+                    .await
+                    .ok_or_else(|| DbError::connection_not_found(conn_name))?;
+                Ok(SessionConn::Pooled(db_conn.acquire().await?))
+            }
+        }
+    }
+
+    pub fn conn(&mut self) -> &mut PooledConn {
+        match self {
+            SessionConn::Pooled(conn) => conn,
+            SessionConn::Session(session) => session.conn(),
+        }
+    }
+
+    pub fn in_transaction(&self) -> bool {
+        match self {
+            SessionConn::Pooled(_) => false,
+            SessionConn::Session(session) => session.in_transaction(),
+        }
+    }
+
+    // 连接上可能留着未结束的事务, 丢弃时关闭连接而不是放回连接池, 会话之后只能关闭
+    // The connection may still hold an open transaction, close it on drop instead of
+    // returning it to the pool, the session can only be closed afterwards
+    pub fn abandon(&mut self) {
+        match self {
+            SessionConn::Pooled(conn) => conn.close_on_drop(),
+            SessionConn::Session(session) => session.abandon(),
+        }
+    }
+
+    // 单条非事务控制语句执行后调用 (无论成功与否), 同步会话记录的事务状态
+    // Called after a single non transaction control statement ran, successfully or not,
+    // to keep the session's transaction state in sync
+    pub fn after_statement(&mut self, sql: &str) {
+        if let SessionConn::Session(session) = self {
+            session.after_statement(sql);
+        }
+    }
+
+    // 连接在服务端的 id, 会话使用打开时查到的, 从连接池取出的连接现在查询
+    // Server side id of the connection, sessions use the one looked up on open, pooled connections look it up now
+    pub async fn backend_id(&mut self) -> Result<BackendId, DbError> {
+        match self {
+            SessionConn::Pooled(conn) => backend_id(conn).await,
+            SessionConn::Session(session) => match session.backend {
+                Some(backend) => Ok(backend),
+                None => backend_id(&mut session.conn).await,
+            },
+        }
+    }
+}
+
+type SessionMap = HashMap<String, Arc<Mutex<Session>>>;
+
+// 会话按连接名分组: 连接名 -> 会话 id -> 会话
+// Sessions grouped by connection name: connection name -> session id -> session
+static SESSIONS: Lazy<Mutex<HashMap<String, SessionMap>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+static NEXT_SESSION_ID: AtomicU64 = AtomicU64::new(1);
+
+// 从连接池取出一个连接作为会话, 返回会话 id
+// Check a connection out of the pool as a session, returns the session id
+pub async fn open_session(conn_name: &str) -> Result<String, DbError> {
+    let db_conn = DbPool::global()
+        .get(conn_name)
+        .await
+        .ok_or_else(|| DbError::connection_not_found(conn_name))?;
+
+    let mut conn = db_conn.acquire().await?;
+    // 刚取出的连接不在事务中, 查询不会因为事务中止而失败
+    // A freshly acquired connection isn't in a transaction, so the lookup can't fail on an aborted one
+    let use_prepared = DbPool::global()
+        .settings(conn_name)
+        .await
+        .is_some_and(|settings| settings.use_prepared());
+    let backend = match conn.db_type() {
+        DbType::Postgres | DbType::MySql if use_prepared => Some(backend_id(&mut conn).await?),
+        _ => None,
+    };
+
+    let session = Session {
+        conn,
+        backend,
+        in_transaction: false,
+        savepoints: Vec::new(),
+        interrupted: false,
+    };
+
+    let session_id = format!(
+        "session-{}",
+        NEXT_SESSION_ID.fetch_add(1, Ordering::Relaxed)
+    );
+    SESSIONS
+        .lock()
+        .await
+        .entry(conn_name.to_string())
+        .or_default()
+        .insert(session_id.clone(), Arc::new(Mutex::new(session)));
+
+    Ok(session_id)
+}
+
+// 关闭会话, 未提交的事务会被回滚
+// Close the session, the open transaction is rolled back
+pub async fn close_session(conn_name: &str, session_id: &str) -> bool {
+    let session = {
+        let mut sessions = SESSIONS.lock().await;
+        let removed = sessions
+            .get_mut(conn_name)
+            .and_then(|m| m.remove(session_id));
+        sessions.retain(|_, m| !m.is_empty());
+        removed
+    };
+
+    match session {
+        Some(session) => {
+            close_locked(session).await;
+            true
+        }
+        None => false,
+    }
+}
+
+// 关闭连接的所有会话, 断开连接时调用, 必须在关闭连接池之前
+// Close all sessions of the connection, called on disconnect, must happen before the pool is closed
+pub async fn close_sessions(conn_name: &str) {
+    detach_sessions(conn_name).await.await;
+}
+
+// 立即移除连接的所有会话, 返回关闭它们的 future
+// 连接断开时回滚可能要等很久, 调用方可以把关闭放到后台, 移除后再使用会话会得到 session_not_found
+// Remove all sessions of the connection right away and return a future that closes them
+// Rolling back can take long on a broken connection, so callers may close them in the background,
+// using a session after it's removed gives session_not_found
+pub async fn detach_sessions(conn_name: &str) -> impl Future<Output = ()> + Send + 'static {
+    let removed = SESSIONS.lock().await.remove(conn_name);
+    async move {
+        for (_, session) in removed.into_iter().flatten() {
+            close_locked(session).await;
+        }
+    }
+}
+
+// 重命名连接时把会话移到新名称下
+// Move the sessions to the new name when the connection is renamed
+pub async fn rename_sessions(conn_name: &str, new_name: &str) {
+    let mut sessions = SESSIONS.lock().await;
+    if let Some(m) = sessions.remove(conn_name) {
+        sessions.insert(new_name.to_string(), m);
+    }
+}
+
+// 执行事务控制命令并返回新的状态
+// Run a transaction control command and return the new state
+pub async fn control(
+    conn_name: &str,
+    session_id: &str,
+    control: TxControl,
+) -> Result<SessionState, DbError> {
+    let sql = control.sql()?;
+    let mut session = lock_session(conn_name, session_id).await?;
+    session.apply(&control, &sql).await?;
+    Ok(session.state(session_id))
+}
+
+pub async fn session_state(conn_name: &str, session_id: &str) -> Result<SessionState, DbError> {
+    Ok(lock_session(conn_name, session_id).await?.state(session_id))
+}
+
+// 只在查找会话时持有全局锁, 会话本身的锁一直持有到语句执行完
+// Only hold the global lock while looking up the session, the session lock is held until the statement finishes
+async fn lock_session(
+    conn_name: &str,
+    session_id: &str,
+) -> Result<OwnedMutexGuard<Session>, DbError> {
+    let session = SESSIONS
+        .lock()
+        .await
+        .get(conn_name)
+        .and_then(|m| m.get(session_id))
+        .cloned()
+        .ok_or_else(|| {
+            DbError::new(
+                DbErrorCode::SessionNotFound,
+                format!("Session '{}' not found", session_id),
+            )
+        })?;
+
+    let session = session.lock_owned().await;
+    if session.interrupted {
+        return Err(DbError::new(
+            DbErrorCode::InvalidState,
+            format!(
+                "Session '{}' was interrupted in the middle of a batch, close it and open a new one",
+                session_id
+            ),
+        ));
+    }
+
+    Ok(session)
+}
+
+// 等待正在执行的语句结束, 回滚未提交的事务并关闭连接
+// 会话可能改过 SET 变量或建了临时表, 所以不放回连接池
+// Wait for the running statement to finish, roll back the open transaction and close the connection
+// The session may have changed SET variables or created temp tables, so it isn't returned to the pool
+async fn close_locked(session: Arc<Mutex<Session>>) {
+    {
+        let mut session = session.lock().await;
+        if session.in_transaction && !session.interrupted {
+            let _ = session.conn.execute_raw("ROLLBACK").await;
+            session.in_transaction = false;
+            session.savepoints.clear();
+        }
+    }
+
+    // 会话已从列表中移除, 通常不会再有其它引用
+    // 极少数情况下还有语句在等待这个会话, 连接会在它结束后放回连接池
+    // The session has been removed from the list, so usually nothing else references it
+    // In rare cases a statement is still waiting on the session, the connection returns to the pool after it
+    if let Ok(session) = Arc::try_unwrap(session) {
+        session.into_inner().conn.close().await;
+    }
+}
+
+// MySQL 会隐式提交事务的语句, 按开头的关键字判断, 无法解析的语句也能识别
+// 服务端没有通用的变量能查到事务是否还在 (@@in_transaction 只有 MariaDB 有), 所以在客户端判断
+// 见 https://dev.mysql.com/doc/refman/8.0/en/implicit-commit.html
+// Statements that commit the transaction implicitly on MySQL, judged by the leading keywords
+// so statements that can't be parsed are recognized too
+// The server has no portable variable telling whether a transaction is open (@@in_transaction is MariaDB only),
+// so it is decided on the client
+// See https://dev.mysql.com/doc/refman/8.0/en/implicit-commit.html
+fn commits_implicitly(sql: &str) -> bool {
+    let Ok(tokens) = Tokenizer::new(&MySqlDialect {}, sql).tokenize() else {
+        return false;
+    };
+    let mut words = tokens.iter().filter_map(|token| match token {
+        Token::Word(word) => Some(word.value.to_uppercase()),
+        _ => None,
+    });
+
+    match (words.next().as_deref(), words.next().as_deref()) {
+        // 临时表不会提交事务 | Temporary tables don't commit the transaction
+        (Some("CREATE" | "DROP"), Some("TEMPORARY")) => false,
+        (
+            Some(
+                "CREATE" | "ALTER" | "DROP" | "RENAME" | "TRUNCATE" | "GRANT" | "REVOKE" | "LOCK"
+                | "UNLOCK" | "ANALYZE" | "CHECK" | "FLUSH" | "OPTIMIZE" | "REPAIR" | "RESET"
+                | "CACHE" | "INSTALL" | "UNINSTALL",
+            ),
+            _,
+        ) => true,
+        (Some("LOAD"), Some("INDEX")) => true,
+        _ => false,
+    }
+}
+
+// 保存点名称只允许普通标识符, 避免拼接 SQL 时被注入
+// Savepoint names must be plain identifiers, so they can be safely put into the SQL
+fn check_name(name: &str) -> Result<&str, DbError> {
+    let mut chars = name.chars();
+    let valid = matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+
+    if valid {
+        Ok(name)
+    } else {
+        Err(DbError::new(
+            DbErrorCode::InvalidArgument,
+            format!("Invalid savepoint name: {}", name),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mysql_implicit_commits() {
+        for sql in [
+            "CREATE TABLE t (id INT)",
+            "alter table t add column c int",
+            "DROP TABLE t",
+            "TRUNCATE t",
+            "/* ddl */ RENAME TABLE a TO b",
+            "LOCK TABLES t WRITE",
+            "LOAD INDEX INTO CACHE t",
+        ] {
+            assert!(commits_implicitly(sql), "{}", sql);
+        }
+        for sql in [
+            "INSERT INTO t VALUES (1)",
+            "CREATE TEMPORARY TABLE t (id INT)",
+            "DROP TEMPORARY TABLE t",
+            "LOAD DATA INFILE 'a.csv' INTO TABLE t",
+            "SELECT 'CREATE'",
+            "",
+        ] {
+            assert!(!commits_implicitly(sql), "{}", sql);
+        }
+    }
+}
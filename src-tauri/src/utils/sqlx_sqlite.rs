@@ -1,70 +1,126 @@
 use crate::types::QueryResult;
 use crate::utils::common::print_sql;
+use crate::utils::sqlx_common::{describe_columns, elapsed_ms, fetch_limited, resolve_columns};
+use crate::utils::sqlx_cursor::RowStream;
+use crate::utils::sqlx_error::DbError;
 use base64::engine::general_purpose::STANDARD;
 use base64::Engine;
+use futures_util::{StreamExt, TryStreamExt};
 use serde_json::json;
-use sqlx::{Column, Execute, Row, SqlitePool};
+use sqlx::sqlite::{SqliteConnection, SqliteRow};
+use sqlx::{Execute, Executor, Row, SqlitePool};
+use std::sync::Arc;
+use std::time::Instant;
 
 /// 处理 SQLite 的查询 | Handling SQLite queries
 ///
+/// 分页查询使用 `sqlx_cursor`
+/// Use `sqlx_cursor` for paginated queries
+///
 /// # 参数
+/// - `conn`: 执行查询的连接, 来自会话或连接池 | Connection to run the query on, from a session or the pool
 /// - `sql`: 要执行的 sql | The SQL to be executed"
-/// - `streaming`: 是否启用流式分页 | Whether to enable streaming pagination
-/// - `page`: 当前页码（从1开始） | Current page number (starting from 1)
-/// - `page_size`: 每页的条目数 | Number of entries per page
+/// - `max_rows`: 最多返回的行数, None 表示全部返回 | Maximum number of rows to return, None returns all
+/// - `prepared`: 是否使用预处理语句 | Whether to use prepared statements
 ///
 pub async fn query_sqlite(
-    pool: &SqlitePool,
+    conn: &mut SqliteConnection,
     sql: &str,
-    streaming: bool,
-    page: Option<usize>,
-    page_size: Option<usize>,
-) -> Result<QueryResult, Box<dyn std::error::Error>> {
-    // TODO: 实现流式查询
-
+    max_rows: Option<usize>,
+    prepared: bool,
+) -> Result<QueryResult, DbError> {
     let query = sqlx::query(sql);
     #[cfg(debug_assertions)]
     {
         print_sql(query.sql(), 7);
     }
 
-    let rows = query.fetch_all(pool).await?;
-    let mut json_rows = Vec::new();
-    let mut column_names: Vec<String> = Vec::new();
+    // 不使用预处理语句时直接执行 &str, 走文本协议, 也不能 describe
+    // Without prepared statements a plain &str is executed over the text protocol, describe isn't available either
+    let start = Instant::now();
+    let stream = if prepared {
+        query.fetch(&mut *conn)
+    } else {
+        conn.fetch(sql)
+    };
+    let (rows, truncated) = match max_rows {
+        Some(max) => fetch_limited(stream, max).await?,
+        None => (stream.try_collect().await?, false),
+    };
+    let elapsed_ms = elapsed_ms(start);
+
+    let described = if prepared {
+        describe_columns(conn.describe(sql).await)
+    } else {
+        None
+    };
+    let columns = resolve_columns(described, &rows);
+
+    Ok(QueryResult {
+        columns,
+        rows: process_rows(rows)?,
+        elapsed_ms,
+        truncated,
+    })
+}
 
-    if let Some(first_row) = rows.first() {
-        for column in first_row.columns() {
-            column_names.push(column.name().to_owned());
+// 创建游标使用的行流
+// Create the row stream used by cursors
+pub fn row_stream(pool: SqlitePool, sql: Arc<str>, prepared: bool) -> RowStream<SqliteRow> {
+    Box::pin(async_stream::stream! {
+        let query = sqlx::query(&sql);
+        #[cfg(debug_assertions)]
+        {
+            print_sql(query.sql(), 7);
         }
-    }
 
-    for row in rows {
-        let mut json_row = serde_json::Map::new();
-        for (idx, col_name) in column_names.iter().enumerate() {
-            // 类型的判断不能依赖 type_info, 因为 type_info().name() 返回的全部都是 NULL
-            // 这里遵循 SQLite 的 类型优先级规则动态解析
-            // Refer: https://www.sqlite.org/datatype3.html#type_conversions
-            let value = if let Ok(val) = row.try_get::<i64, _>(idx) {
-                json!(val)
-            } else if let Ok(val) = row.try_get::<f64, _>(idx) {
-                json!(val)
-            } else if let Ok(val) = row.try_get::<String, _>(idx) {
-                json!(val)
-            } else if let Ok(val) = row.try_get::<Vec<u8>, _>(idx) {
-                json!(STANDARD.encode(&val))
-            } else if row.try_get::<Option<i64>, _>(idx)?.is_none() {
-                json!(null)
-            } else {
-                json!("unknown_type")
-            };
+        let mut stream = if prepared {
+            query.persistent(true).fetch(&pool)
+        } else {
+            pool.fetch(&*sql)
+        };
+
+        while let Some(row) = stream.next().await {
+            yield row;
+        }
+    })
+}
 
-            json_row.insert(col_name.clone(), value);
+// 把结果行转为按列顺序排列的 json 数组
+// Convert result rows to JSON arrays in column order
+pub fn process_rows(rows: Vec<SqliteRow>) -> Result<Vec<Vec<serde_json::Value>>, DbError> {
+    let mut json_rows = Vec::with_capacity(rows.len());
+
+    for row in rows {
+        let mut json_row = Vec::with_capacity(row.len());
+        for idx in 0..row.len() {
+            json_row.push(convert_value_sqlite(&row, idx)?);
         }
-        json_rows.push(json!(json_row));
+        json_rows.push(json_row);
     }
 
-    Ok(QueryResult {
-        column_name: serde_json::to_string(&column_names)?,
-        data: serde_json::to_string(&json_rows)?,
-    })
+    Ok(json_rows)
+}
+
+// 把数据库类型转为 json 类型
+// Convert database type to JSON type
+fn convert_value_sqlite(row: &SqliteRow, idx: usize) -> Result<serde_json::Value, sqlx::Error> {
+    // 类型的判断不能依赖 type_info, 因为 type_info().name() 返回的全部都是 NULL
+    // 这里遵循 SQLite 的 类型优先级规则动态解析
+    // Refer: https://www.sqlite.org/datatype3.html#type_conversions
+    let value = if let Ok(val) = row.try_get::<i64, _>(idx) {
+        json!(val)
+    } else if let Ok(val) = row.try_get::<f64, _>(idx) {
+        json!(val)
+    } else if let Ok(val) = row.try_get::<String, _>(idx) {
+        json!(val)
+    } else if let Ok(val) = row.try_get::<Vec<u8>, _>(idx) {
+        json!(STANDARD.encode(&val))
+    } else if row.try_get::<Option<i64>, _>(idx)?.is_none() {
+        json!(null)
+    } else {
+        json!("unknown_type")
+    };
+
+    Ok(value)
 }
@@ -0,0 +1,1019 @@
+use super::sqlx_error::{DbError, DbErrorCode};
+use crate::types::{SshAuth, SshConfig};
+use base64::engine::general_purpose::{STANDARD, STANDARD_NO_PAD};
+use base64::Engine;
+use once_cell::sync::Lazy;
+use polling::{Event, Events, Poller};
+use ssh2::{
+    BlockDirections, Channel, CheckResult, ErrorCode, HashType, KnownHostFileKind, Session,
+};
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Read, Write};
+#[cfg(not(unix))]
+use std::net::{TcpListener as LocalListener, TcpStream as LocalStream};
+use std::net::{TcpStream, ToSocketAddrs};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener as LocalListener, UnixStream as LocalStream};
+use std::path::{Path, PathBuf};
+#[cfg(unix)]
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+const DEFAULT_SSH_PORT: u16 = 22;
+
+// 建立 TCP 连接, 握手和打开通道的超时
+// Timeout for the TCP connect, the handshake and opening a channel
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(15);
+
+// 心跳间隔, 单位秒, 避免空闲的隧道被防火墙断开
+// Keepalive interval in seconds, so idle tunnels aren't dropped by firewalls
+const KEEPALIVE_SECS: u32 = 30;
+
+// 转发线程等待的事件源的 key, 本地连接从 FIRST_STREAM_KEY 开始编号
+// Keys of the event sources the forwarding thread waits on, local connections are numbered from FIRST_STREAM_KEY
+const LISTENER_KEY: usize = 0;
+const SESSION_KEY: usize = 1;
+const FIRST_STREAM_KEY: usize = 2;
+
+const BUFFER_SIZE: usize = 32 * 1024;
+
+// 非阻塞模式下操作需要稍后重试 | The operation has to be retried later in non-blocking mode
+const LIBSSH2_ERROR_EAGAIN: i32 = -37;
+
+// 隧道在本地监听的地址
+// Unix 上是私有目录中的套接字, 数据库驱动连接它时仍使用真实的主机名做 TLS 的 SNI 和证书校验
+// 其它平台是 127.0.0.1 的端口, TLS 的主机名只能是 127.0.0.1
+// Local address the tunnel listens on
+// On Unix a socket in a private directory, so the database driver still uses the real host name
+// for TLS SNI and certificate verification when connecting through it
+// On other platforms a port on 127.0.0.1, where the TLS host name can only be 127.0.0.1
+#[derive(Clone, Debug)]
+pub enum LocalAddr {
+    // 套接字文件的路径, 文件名是 PostgreSQL 在目录中查找的 .s.PGSQL.<端口>
+    // Path of the socket file, named .s.PGSQL.<port> as PostgreSQL looks it up in the directory
+    #[cfg(unix)]
+    Socket(PathBuf),
+    #[cfg(not(unix))]
+    Port(u16),
+}
+
+// SSH 隧道: 本地地址转发到跳板机后面的数据库
+// SSH tunnel: a local address forwarded to the database behind the jump host
+pub struct Tunnel {
+    local_addr: LocalAddr,
+    stop: Arc<AtomicBool>,
+    // 设置 stop 后唤醒等待中的转发线程 | Wakes the waiting forwarding thread once stop is set
+    poller: Arc<Poller>,
+    // close 时取出并等待退出 | Taken and joined by close
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Tunnel {
+    /// 建立 SSH 隧道 | Start an SSH tunnel
+    ///
+    /// # 参数
+    /// - `ssh`: SSH 设置 | SSH settings
+    /// - `target_host`: 从跳板机看到的数据库主机 | Database host as seen from the jump host
+    /// - `target_port`: 数据库端口 | Database port
+    ///
+    pub async fn start(
+        ssh: &SshConfig,
+        target_host: &str,
+        target_port: u16,
+    ) -> Result<Self, DbError> {
+        // libssh2 是阻塞的, 握手和认证放到阻塞线程池中
+        // libssh2 is blocking, so the handshake and authentication run on the blocking pool
+        let ssh = ssh.clone();
+        let (session, socket) = tokio::task::spawn_blocking(move || handshake(&ssh))
+            .await
+            .map_err(|e| tunnel_error(e.to_string()))??;
+        let (listener, local_addr) = listen(target_port)
+            .map_err(|e| tunnel_error(format!("Failed to listen on a local address: {}", e)))?;
+
+        let poller = Arc::new(Poller::new().map_err(|e| tunnel_error(e.to_string()))?);
+        let stop = Arc::new(AtomicBool::new(false));
+        let flag = Arc::clone(&stop);
+        let waker = Arc::clone(&poller);
+        let target = (target_host.to_string(), target_port);
+        let addr = local_addr.clone();
+        let thread = thread::Builder::new()
+            .name("ssh-tunnel".to_string())
+            .spawn(move || {
+                let sockets = Sockets {
+                    poller: &waker,
+                    session: &socket,
+                    listener: &listener,
+                };
+                if let Err(e) = forward(&session, sockets, &target, &flag) {
+                    eprintln!("The SSH tunnel stopped forwarding: {:?}", e);
+                }
+                let _ = session.disconnect(None, "Tunnel closed", None);
+                drop(listener);
+                remove_local(&addr);
+            })
+            .map_err(|e| tunnel_error(e.to_string()))?;
+
+        Ok(Self {
+            local_addr,
+            stop,
+            poller,
+            thread: Some(thread),
+        })
+    }
+
+    // 本地监听的地址, 数据库连接到这里 | The local address, the database connection goes here
+    pub fn local_addr(&self) -> &LocalAddr {
+        &self.local_addr
+    }
+
+    // 停止转发并等待转发线程退出
+    // Stop forwarding and wait for the forwarding thread to exit
+    pub async fn close(mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        let _ = self.poller.notify();
+        if let Some(thread) = self.thread.take() {
+            let _ = tokio::task::spawn_blocking(move || thread.join()).await;
+        }
+    }
+}
+
+// 没有调用 close 就丢弃时 (例如建立连接的 future 被取消) 也要让转发线程退出
+// Also stop the forwarding thread when dropped without close, e.g. when the connecting future is cancelled
+impl Drop for Tunnel {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        let _ = self.poller.notify();
+    }
+}
+
+// 连接名 -> 隧道, 临界区内没有 await
+// Connection name -> tunnel, no await inside the critical sections
+static TUNNELS: Lazy<Mutex<HashMap<String, Tunnel>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// 为连接打开 SSH 隧道, 返回本地监听的地址 | Open an SSH tunnel for a connection, returns the local address it listens on
+///
+/// # 参数
+/// - `conn_name`: 连接名, 断开连接时用它关闭隧道 | Connection name, used to close the tunnel on disconnect
+/// - `ssh`: SSH 设置 | SSH settings
+/// - `target_host`: 从跳板机看到的数据库主机 | Database host as seen from the jump host
+/// - `target_port`: 数据库端口 | Database port
+///
+pub async fn open_tunnel(
+    conn_name: &str,
+    ssh: &SshConfig,
+    target_host: &str,
+    target_port: u16,
+) -> Result<LocalAddr, DbError> {
+    if TUNNELS.lock().unwrap().contains_key(conn_name) {
+        return Err(duplicate_tunnel(conn_name));
+    }
+
+    let tunnel = Tunnel::start(ssh, target_host, target_port).await?;
+    let local_addr = tunnel.local_addr().clone();
+
+    let duplicate = {
+        let mut tunnels = TUNNELS.lock().unwrap();
+        if tunnels.contains_key(conn_name) {
+            Some(tunnel)
+        } else {
+            tunnels.insert(conn_name.to_string(), tunnel);
+            None
+        }
+    };
+    if let Some(tunnel) = duplicate {
+        // 同名连接同时在建立隧道, 保留先完成的那个
+        // A connection with the same name opened a tunnel meanwhile, keep the one that finished first
+        tunnel.close().await;
+        return Err(duplicate_tunnel(conn_name));
+    }
+
+    Ok(local_addr)
+}
+
+// 关闭连接的隧道, 断开连接时在关闭连接池之后调用
+// Close the tunnel of the connection, called on disconnect after the pool is closed
+pub async fn close_tunnel(conn_name: &str) -> bool {
+    let tunnel = TUNNELS.lock().unwrap().remove(conn_name);
+
+    match tunnel {
+        Some(tunnel) => {
+            tunnel.close().await;
+            true
+        }
+        None => false,
+    }
+}
+
+// 重命名连接时把隧道移到新名称下, 新名称下已有隧道时报错
+// Move the tunnel to the new name when the connection is renamed, fails when the new name already has a tunnel
+pub fn rename_tunnel(conn_name: &str, new_name: &str) -> Result<(), DbError> {
+    let mut tunnels = TUNNELS.lock().unwrap();
+    if tunnels.contains_key(new_name) {
+        return Err(duplicate_tunnel(new_name));
+    }
+    if let Some(tunnel) = tunnels.remove(conn_name) {
+        tunnels.insert(new_name.to_string(), tunnel);
+    }
+    Ok(())
+}
+
+// 用新的隧道替换连接的隧道, 返回旧的隧道, 由调用方在旧连接池关闭后关闭它
+// Replace the tunnel of the connection and return the old one, the caller closes it after the old pool
+pub fn replace_tunnel(conn_name: &str, tunnel: Option<Tunnel>) -> Option<Tunnel> {
+    let mut tunnels = TUNNELS.lock().unwrap();
+    match tunnel {
+        Some(tunnel) => tunnels.insert(conn_name.to_string(), tunnel),
+        None => tunnels.remove(conn_name),
+    }
+}
+
+// 每个隧道的套接字目录的序号 | Sequence number of the socket directory of each tunnel
+#[cfg(unix)]
+static NEXT_SOCKET_DIR: AtomicU64 = AtomicU64::new(1);
+
+// 在本地监听, Unix 上的套接字放在只有当前用户能访问的新目录中
+// Listen locally, on Unix the socket goes in a new directory only the current user can access
+#[cfg(unix)]
+fn listen(target_port: u16) -> std::io::Result<(LocalListener, LocalAddr)> {
+    use std::os::unix::fs::DirBuilderExt;
+
+    let dir = std::env::temp_dir().join(format!(
+        "dibim-tunnel-{}-{}",
+        std::process::id(),
+        NEXT_SOCKET_DIR.fetch_add(1, Ordering::Relaxed)
+    ));
+    // 同一个进程 id 之前留下的目录 | Left behind by an earlier process with the same id
+    let _ = fs::remove_dir_all(&dir);
+    fs::DirBuilder::new().mode(0o700).create(&dir)?;
+    let path = dir.join(format!(".s.PGSQL.{}", target_port));
+    let listener = LocalListener::bind(&path)?;
+    listener.set_nonblocking(true)?;
+    Ok((listener, LocalAddr::Socket(path)))
+}
+
+#[cfg(not(unix))]
+fn listen(_target_port: u16) -> std::io::Result<(LocalListener, LocalAddr)> {
+    let listener = LocalListener::bind(("127.0.0.1", 0))?;
+    listener.set_nonblocking(true)?;
+    let port = listener.local_addr()?.port();
+    Ok((listener, LocalAddr::Port(port)))
+}
+
+// 转发线程退出后删除套接字和它的目录 | Remove the socket and its directory once the forwarding thread exits
+fn remove_local(addr: &LocalAddr) {
+    match addr {
+        #[cfg(unix)]
+        LocalAddr::Socket(path) => {
+            if let Some(dir) = path.parent() {
+                let _ = fs::remove_dir_all(dir);
+            }
+        }
+        #[cfg(not(unix))]
+        LocalAddr::Port(_) => {}
+    }
+}
+
+// 返回认证后的会话和它的 TCP 连接的副本, 转发线程用副本等待会话可读写
+// Returns the authenticated session and a clone of its TCP connection,
+// the forwarding thread waits on the clone for the session to become readable or writable
+fn handshake(ssh: &SshConfig) -> Result<(Session, TcpStream), DbError> {
+    let port = ssh.port.unwrap_or(DEFAULT_SSH_PORT);
+    let addr = (ssh.host.as_str(), port)
+        .to_socket_addrs()
+        .ok()
+        .and_then(|mut addrs| addrs.next())
+        .ok_or_else(|| tunnel_error(format!("Failed to resolve the SSH host '{}'", ssh.host)))?;
+    let tcp = TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT).map_err(|e| {
+        tunnel_error(format!(
+            "Failed to connect to the SSH host '{}': {}",
+            ssh.host, e
+        ))
+    })?;
+
+    let socket = tcp.try_clone().map_err(|e| tunnel_error(e.to_string()))?;
+    let mut session = Session::new()?;
+    session.set_tcp_stream(tcp);
+    session.set_timeout(CONNECT_TIMEOUT.as_millis() as u32);
+    session.handshake()?;
+
+    // 认证前先验证主机密钥, 避免把密码发给冒充的主机
+    // Verify the host key before authenticating, so the password isn't sent to an impostor
+    verify_host_key(&session, ssh, port)?;
+
+    match &ssh.auth {
+        SshAuth::Password { password } => session.userauth_password(&ssh.user, password)?,
+        SshAuth::Key {
+            private_key,
+            passphrase,
+        } => session.userauth_pubkey_file(
+            &ssh.user,
+            None,
+            Path::new(private_key),
+            passphrase.as_deref(),
+        )?,
+        SshAuth::Agent => session.userauth_agent(&ssh.user)?,
+    }
+    if !session.authenticated() {
+        return Err(tunnel_error(format!(
+            "SSH authentication failed for user '{}'",
+            ssh.user
+        )));
+    }
+
+    // 之后由转发线程以非阻塞方式使用 | Used in non-blocking mode by the forwarding thread from here
+    session.set_timeout(0);
+    session.set_keepalive(false, KEEPALIVE_SECS);
+    Ok((session, socket))
+}
+
+fn verify_host_key(session: &Session, ssh: &SshConfig, port: u16) -> Result<(), DbError> {
+    let (key, _) = session
+        .host_key()
+        .ok_or_else(|| tunnel_error("The SSH host didn't send a host key"))?;
+    let fingerprint = session
+        .host_key_hash(HashType::Sha256)
+        .map(|hash| format!("SHA256:{}", STANDARD_NO_PAD.encode(hash)))
+        .unwrap_or_default();
+
+    let path = known_hosts_path(ssh)?;
+    let mut known_hosts = session.known_hosts()?;
+    if path.exists() {
+        known_hosts.read_file(&path, KnownHostFileKind::OpenSSH)?;
+    }
+
+    let mut err = match known_hosts.check_port(&ssh.host, port, key) {
+        CheckResult::Match => return Ok(()),
+        CheckResult::NotFound if ssh.accept_new_host.unwrap_or(false) => {
+            return add_known_host(&path, &ssh.host, port, key);
+        }
+        CheckResult::NotFound => DbError::new(
+            DbErrorCode::UnknownHostKey,
+            format!(
+                "The SSH host '{}' is not in {}",
+                ssh.host,
+                path.display()
+            ),
+        ),
+        CheckResult::Mismatch => tunnel_error(format!(
+            "The host key of '{}' doesn't match {}, the key may have changed or the connection is being intercepted",
+            ssh.host,
+            path.display()
+        )),
+        CheckResult::Failure => tunnel_error(format!(
+            "Failed to check the host key of '{}'",
+            ssh.host
+        )),
+    };
+    // 前端把指纹展示给用户确认 | The frontend shows the fingerprint for the user to confirm
+    err.details.detail = Some(fingerprint);
+    Err(err)
+}
+
+// 追加到 known_hosts 的末尾, 不重写整个文件, 以保留已有的注释和条目
+// Append to the end of known_hosts instead of rewriting the file, so existing comments and entries are kept
+fn add_known_host(path: &Path, host: &str, port: u16, key: &[u8]) -> Result<(), DbError> {
+    let host = if port == DEFAULT_SSH_PORT {
+        host.to_string()
+    } else {
+        format!("[{}]:{}", host, port)
+    };
+
+    // 密钥的开头是带长度前缀的算法名, 例如 ssh-ed25519
+    // The key starts with the length prefixed algorithm name, e.g. ssh-ed25519
+    let key_type = key
+        .get(..4)
+        .map(|len| u32::from_be_bytes([len[0], len[1], len[2], len[3]]) as usize)
+        .and_then(|len| key.get(4..4 + len))
+        .and_then(|name| std::str::from_utf8(name).ok())
+        .ok_or_else(|| tunnel_error("Unrecognized SSH host key"))?;
+
+    let write = || -> std::io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        writeln!(file, "{} {} {}", host, key_type, STANDARD.encode(key))
+    };
+    write().map_err(|e| tunnel_error(format!("Failed to write to {}: {}", path.display(), e)))
+}
+
+fn known_hosts_path(ssh: &SshConfig) -> Result<PathBuf, DbError> {
+    if let Some(path) = &ssh.known_hosts {
+        return Ok(PathBuf::from(path));
+    }
+
+    std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .map(|home| PathBuf::from(home).join(".ssh").join("known_hosts"))
+        .ok_or_else(|| {
+            DbError::new(
+                DbErrorCode::InvalidConfig,
+                "Can't find the home directory, set knownHosts explicitly",
+            )
+        })
+}
+
+// 转发线程等待的事件源 | Event sources the forwarding thread waits on
+struct Sockets<'a> {
+    poller: &'a Poller,
+    // SSH 会话的 TCP 连接 | TCP connection of the SSH session
+    session: &'a TcpStream,
+    listener: &'a LocalListener,
+}
+
+// 转发线程: 接受本地连接, 为每个连接打开一个 direct-tcpip 通道并双向复制数据
+// libssh2 的会话不能被多个线程同时阻塞使用, 所以所有通道在一个线程里以非阻塞方式处理,
+// 没有进展时等待本地连接或会话的 TCP 连接可读写, 直到下一次心跳
+// Forwarding thread: accept local connections, open a direct-tcpip channel for each and copy data both ways
+// A libssh2 session can't be blocked on by several threads at once, so all channels are handled
+// in non-blocking mode on one thread, which waits for the local connections or the session's TCP connection
+// to become readable or writable when nothing progresses, until the next keepalive is due
+fn forward(
+    session: &Session,
+    sockets: Sockets,
+    target: &(String, u16),
+    stop: &AtomicBool,
+) -> std::io::Result<()> {
+    session.set_blocking(false);
+    let poller = sockets.poller;
+    // 注册后每次等待前按需要的方向重新设置 | Registered once, the wanted directions are set again before each wait
+    unsafe {
+        poller.add(sockets.listener, Event::none(LISTENER_KEY))?;
+        poller.add(sockets.session, Event::none(SESSION_KEY))?;
+    }
+
+    let mut forwards: Vec<Forward> = Vec::new();
+    let mut next_key = FIRST_STREAM_KEY;
+    let mut buf = vec![0; BUFFER_SIZE];
+    let mut events = Events::new();
+
+    let res = loop {
+        if stop.load(Ordering::Relaxed) {
+            break Ok(());
+        }
+        let mut busy = false;
+
+        match sockets.listener.accept() {
+            Ok((stream, _)) => {
+                busy = true;
+                match open_channel(session, &target.0, target.1) {
+                    Ok(channel) if stream.set_nonblocking(true).is_ok() => {
+                        unsafe { poller.add(&stream, Event::none(next_key))? };
+                        forwards.push(Forward::new(stream, channel, next_key));
+                        next_key += 1;
+                    }
+                    Ok(_) => {}
+                    Err(e) => eprintln!("Failed to open the SSH channel: {:?}", e),
+                }
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+            Err(e) => eprintln!("Failed to accept the tunnel connection: {:?}", e),
+        }
+
+        for f in forwards.iter_mut() {
+            busy |= f.pump(&mut buf);
+        }
+        for f in forwards.iter().filter(|f| f.done) {
+            poller.delete(&f.stream)?;
+        }
+        forwards.retain(|f| !f.done);
+
+        // 到了间隔才会真正发送, 会话出错说明跳板机已经断开, 停止转发让数据库连接尽快失败
+        // Only actually sent once the interval has passed, a session error means the jump host is gone,
+        // so stop forwarding and let the database connections fail quickly
+        let keepalive_in = match session.keepalive_send() {
+            Ok(secs) => secs,
+            Err(e) if e.code() == ErrorCode::Session(LIBSSH2_ERROR_EAGAIN) => 1,
+            Err(e) => {
+                eprintln!("The SSH session of the tunnel failed: {:?}", e);
+                break Ok(());
+            }
+        };
+
+        if !busy {
+            if let Err(e) = wait(
+                poller,
+                &sockets,
+                session,
+                &forwards,
+                &mut events,
+                keepalive_in,
+            ) {
+                break Err(e);
+            }
+        }
+    };
+
+    for mut f in forwards {
+        let _ = poller.delete(&f.stream);
+        let _ = f.channel.close();
+    }
+    let _ = poller.delete(sockets.listener);
+    let _ = poller.delete(sockets.session);
+    res
+}
+
+// 等待到有连接可以接受, 有数据可以复制, 或者到了下一次心跳
+// 会话缓冲中的数据在上一轮已经读完, 所以只需等待 TCP 连接
+// Wait until a connection can be accepted, data can be copied, or the next keepalive is due
+// Data buffered in the session was drained in the last round, so waiting on the TCP connections is enough
+fn wait(
+    poller: &Poller,
+    sockets: &Sockets,
+    session: &Session,
+    forwards: &[Forward],
+    events: &mut Events,
+    keepalive_in: u32,
+) -> std::io::Result<()> {
+    // 通道的数据总是可能到达, libssh2 有待发送的数据时还要等待可写
+    // Channel data can always arrive, and libssh2 also waits for writability while it has data to send
+    let session_writable = matches!(
+        session.block_directions(),
+        BlockDirections::Outbound | BlockDirections::Both
+    );
+    poller.modify(sockets.listener, Event::readable(LISTENER_KEY))?;
+    poller.modify(
+        sockets.session,
+        Event::new(SESSION_KEY, true, session_writable),
+    )?;
+    for f in forwards {
+        poller.modify(&f.stream, f.interest())?;
+    }
+
+    events.clear();
+    let timeout = Duration::from_secs(u64::from(keepalive_in.max(1)));
+    match poller.wait(events, Some(timeout)) {
+        Err(e) if e.kind() != ErrorKind::Interrupted => Err(e),
+        _ => Ok(()),
+    }
+}
+
+// 打开通道时临时切换到阻塞模式, 非阻塞模式下需要反复重试
+// Switch to blocking mode while opening the channel, non-blocking mode would need repeated retries
+fn open_channel(session: &Session, host: &str, port: u16) -> Result<Channel, ssh2::Error> {
+    session.set_blocking(true);
+    session.set_timeout(CONNECT_TIMEOUT.as_millis() as u32);
+    let channel = session.channel_direct_tcpip(host, port, None);
+    session.set_timeout(0);
+    session.set_blocking(false);
+    channel
+}
+
+// 一个本地连接和它对应的通道
+// A local connection and its channel
+struct Forward {
+    stream: LocalStream,
+    channel: Channel,
+    // 本地连接在 poller 中的 key | Key of the local connection in the poller
+    key: usize,
+    // 等待写入通道的数据 | Data waiting to be written to the channel
+    upstream: Vec<u8>,
+    // 等待写回本地连接的数据 | Data waiting to be written back to the local connection
+    downstream: Vec<u8>,
+    stream_eof: bool,
+    done: bool,
+}
+
+impl Forward {
+    fn new(stream: LocalStream, channel: Channel, key: usize) -> Self {
+        Self {
+            stream,
+            channel,
+            key,
+            upstream: Vec::new(),
+            downstream: Vec::new(),
+            stream_eof: false,
+            done: false,
+        }
+    }
+
+    // 在两个方向上各复制一次数据, 返回是否有进展
+    // Copy data once in each direction, returns whether anything happened
+    fn pump(&mut self, buf: &mut [u8]) -> bool {
+        let mut busy = false;
+
+        // 本地连接 -> 通道 | Local connection -> channel
+        if self.upstream.is_empty() && !self.stream_eof {
+            match self.stream.read(buf) {
+                Ok(0) => {
+                    self.stream_eof = true;
+                    let _ = self.channel.send_eof();
+                    busy = true;
+                }
+                Ok(n) => {
+                    self.upstream.extend_from_slice(&buf[..n]);
+                    busy = true;
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+                Err(_) => return self.close(),
+            }
+        }
+        if !self.upstream.is_empty() {
+            match self.channel.write(&self.upstream) {
+                Ok(n) => {
+                    self.upstream.drain(..n);
+                    busy = true;
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+                Err(_) => return self.close(),
+            }
+        }
+
+        // 通道 -> 本地连接 | Channel -> local connection
+        if self.downstream.is_empty() {
+            match self.channel.read(buf) {
+                Ok(0) if self.channel.eof() => return self.close(),
+                Ok(0) => {}
+                Ok(n) => {
+                    self.downstream.extend_from_slice(&buf[..n]);
+                    busy = true;
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+                Err(_) => return self.close(),
+            }
+        }
+        if !self.downstream.is_empty() {
+            match self.stream.write(&self.downstream) {
+                Ok(n) => {
+                    self.downstream.drain(..n);
+                    busy = true;
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+                Err(_) => return self.close(),
+            }
+        }
+
+        busy
+    }
+
+    // 本地连接需要等待的方向: 能再读入数据时等待可读, 有数据要写回时等待可写
+    // Directions to wait for on the local connection: readable when more data can be taken,
+    // writable while there is data to write back
+    fn interest(&self) -> Event {
+        Event::new(
+            self.key,
+            self.upstream.is_empty() && !self.stream_eof,
+            !self.downstream.is_empty(),
+        )
+    }
+
+    fn close(&mut self) -> bool {
+        let _ = self.channel.close();
+        self.done = true;
+        true
+    }
+}
+
+fn duplicate_tunnel(conn_name: &str) -> DbError {
+    DbError::new(
+        DbErrorCode::DuplicateConnection,
+        format!("Duplicate connection name: '{}'", conn_name),
+    )
+}
+
+fn tunnel_error(message: impl Into<String>) -> DbError {
+    DbError::new(DbErrorCode::TunnelError, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use russh::keys::ssh_key::rand_core::OsRng;
+    use russh::keys::ssh_key::LineEnding;
+    use russh::keys::{Algorithm, PrivateKey, PublicKey};
+    use russh::server::{self, Auth, Msg, Server as _};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    const USER: &str = "dibim";
+    const PASSWORD: &str = "secret";
+
+    // 进程内的 SSH 服务端, 接受固定的密码和一个客户端公钥, 把 direct-tcpip 通道转发到目标地址
+    // In-process SSH server that accepts a fixed password and one client key,
+    // direct-tcpip channels are forwarded to the target address
+    #[derive(Clone)]
+    struct TestServer {
+        client_key: PublicKey,
+    }
+
+    impl server::Server for TestServer {
+        type Handler = Self;
+
+        fn new_client(&mut self, _: Option<std::net::SocketAddr>) -> Self {
+            self.clone()
+        }
+    }
+
+    impl server::Handler for TestServer {
+        type Error = russh::Error;
+
+        async fn auth_password(&mut self, user: &str, password: &str) -> Result<Auth, Self::Error> {
+            if user == USER && password == PASSWORD {
+                Ok(Auth::Accept)
+            } else {
+                Ok(Auth::reject())
+            }
+        }
+
+        async fn auth_publickey(
+            &mut self,
+            user: &str,
+            key: &PublicKey,
+        ) -> Result<Auth, Self::Error> {
+            if user == USER && key.key_data() == self.client_key.key_data() {
+                Ok(Auth::Accept)
+            } else {
+                Ok(Auth::reject())
+            }
+        }
+
+        async fn channel_open_direct_tcpip(
+            &mut self,
+            channel: russh::Channel<Msg>,
+            host_to_connect: &str,
+            port_to_connect: u32,
+            _originator_address: &str,
+            _originator_port: u32,
+            _session: &mut server::Session,
+        ) -> Result<bool, Self::Error> {
+            let addr = format!("{}:{}", host_to_connect, port_to_connect);
+            tokio::spawn(async move {
+                if let Ok(mut target) = tokio::net::TcpStream::connect(addr).await {
+                    let mut stream = channel.into_stream();
+                    let _ = tokio::io::copy_bidirectional(&mut stream, &mut target).await;
+                }
+            });
+            Ok(true)
+        }
+    }
+
+    struct Fixture {
+        port: u16,
+        host_key: PublicKey,
+        client_key: PrivateKey,
+        dir: PathBuf,
+    }
+
+    impl Fixture {
+        // 启动服务端, 每个测试使用单独的临时目录存放 known_hosts 和私钥
+        // Start the server, every test gets its own temp directory for known_hosts and the private key
+        async fn start(name: &str) -> Self {
+            let host_key = PrivateKey::random(&mut OsRng, Algorithm::Ed25519).unwrap();
+            let client_key = PrivateKey::random(&mut OsRng, Algorithm::Ed25519).unwrap();
+            let host_public = host_key.public_key().clone();
+
+            let config = Arc::new(server::Config {
+                keys: vec![host_key],
+                auth_rejection_time: Duration::ZERO,
+                auth_rejection_time_initial: Some(Duration::ZERO),
+                ..Default::default()
+            });
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let port = listener.local_addr().unwrap().port();
+            let mut server = TestServer {
+                client_key: client_key.public_key().clone(),
+            };
+            tokio::spawn(async move { server.run_on_socket(config, &listener).await });
+
+            let dir = std::env::temp_dir().join(format!(
+                "dibim-tunnel-{}-{}-{}",
+                name,
+                std::process::id(),
+                port
+            ));
+            fs::create_dir_all(&dir).unwrap();
+
+            Self {
+                port,
+                host_key: host_public,
+                client_key,
+                dir,
+            }
+        }
+
+        fn known_hosts(&self) -> PathBuf {
+            self.dir.join("known_hosts")
+        }
+
+        // 把服务端的主机密钥写入 known_hosts | Write the server's host key to known_hosts
+        fn trust(&self, key: &PublicKey) {
+            let line = format!("[127.0.0.1]:{} {}\n", self.port, key.to_openssh().unwrap());
+            fs::write(self.known_hosts(), line).unwrap();
+        }
+
+        fn config(&self, auth: SshAuth) -> SshConfig {
+            SshConfig {
+                host: "127.0.0.1".to_string(),
+                port: Some(self.port),
+                user: USER.to_string(),
+                auth,
+                known_hosts: Some(self.known_hosts().to_string_lossy().into_owned()),
+                accept_new_host: None,
+            }
+        }
+
+        fn password(&self) -> SshConfig {
+            self.config(SshAuth::Password {
+                password: PASSWORD.to_string(),
+            })
+        }
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    async fn start(ssh: &SshConfig) -> Result<Tunnel, DbError> {
+        Tunnel::start(ssh, "127.0.0.1", 9).await
+    }
+
+    #[cfg(unix)]
+    async fn connect_local(tunnel: &Tunnel) -> tokio::net::UnixStream {
+        let LocalAddr::Socket(path) = tunnel.local_addr();
+        tokio::net::UnixStream::connect(path).await.unwrap()
+    }
+
+    #[cfg(not(unix))]
+    async fn connect_local(tunnel: &Tunnel) -> tokio::net::TcpStream {
+        let LocalAddr::Port(port) = tunnel.local_addr();
+        tokio::net::TcpStream::connect(("127.0.0.1", *port))
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn password_auth() {
+        let fixture = Fixture::start("password").await;
+        fixture.trust(&fixture.host_key);
+
+        start(&fixture.password()).await.unwrap().close().await;
+
+        let wrong = fixture.config(SshAuth::Password {
+            password: "wrong".to_string(),
+        });
+        let err = start(&wrong).await.err().unwrap();
+        assert_eq!(err.code, DbErrorCode::TunnelError);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn key_auth() {
+        let fixture = Fixture::start("key").await;
+        fixture.trust(&fixture.host_key);
+
+        let key_path = fixture.dir.join("id_ed25519");
+        let pem = fixture.client_key.to_openssh(LineEnding::LF).unwrap();
+        fs::write(&key_path, pem.as_bytes()).unwrap();
+
+        let ssh = fixture.config(SshAuth::Key {
+            private_key: key_path.to_string_lossy().into_owned(),
+            passphrase: None,
+        });
+        start(&ssh).await.unwrap().close().await;
+    }
+
+    #[cfg(unix)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn agent_auth() {
+        use russh::keys::agent::{client::AgentClient, server::serve};
+        use tokio_stream::wrappers::UnixListenerStream;
+
+        let fixture = Fixture::start("agent").await;
+        fixture.trust(&fixture.host_key);
+
+        // 进程内的 ssh-agent, 只有这个测试读取 SSH_AUTH_SOCK
+        // In-process ssh-agent, this is the only test reading SSH_AUTH_SOCK
+        let sock = fixture.dir.join("agent.sock");
+        let listener = tokio::net::UnixListener::bind(&sock).unwrap();
+        tokio::spawn(serve(UnixListenerStream::new(listener), ()));
+        let mut agent = AgentClient::connect_uds(&sock).await.unwrap();
+        agent.add_identity(&fixture.client_key, &[]).await.unwrap();
+        std::env::set_var("SSH_AUTH_SOCK", &sock);
+
+        start(&fixture.config(SshAuth::Agent))
+            .await
+            .unwrap()
+            .close()
+            .await;
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn known_host_mismatch() {
+        let fixture = Fixture::start("mismatch").await;
+        let other = PrivateKey::random(&mut OsRng, Algorithm::Ed25519).unwrap();
+        fixture.trust(other.public_key());
+
+        // accept-new 不会覆盖不匹配的密钥 | accept-new never overrides a mismatching key
+        let mut ssh = fixture.password();
+        ssh.accept_new_host = Some(true);
+        let err = start(&ssh).await.err().unwrap();
+        assert_eq!(err.code, DbErrorCode::TunnelError);
+        assert!(err.message.contains("doesn't match"), "{}", err.message);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn unknown_host_then_accept_new() {
+        let fixture = Fixture::start("accept-new").await;
+
+        let err = start(&fixture.password()).await.err().unwrap();
+        assert_eq!(err.code, DbErrorCode::UnknownHostKey);
+        assert!(err.details.detail.unwrap().starts_with("SHA256:"));
+        assert!(!fixture.known_hosts().exists());
+
+        let mut ssh = fixture.password();
+        ssh.accept_new_host = Some(true);
+        start(&ssh).await.unwrap().close().await;
+
+        // 加入后不再需要 acceptNewHost | acceptNewHost is no longer needed once added
+        let added = fs::read_to_string(fixture.known_hosts()).unwrap();
+        assert!(added.starts_with(&format!("[127.0.0.1]:{} ssh-ed25519 ", fixture.port)));
+        start(&fixture.password()).await.unwrap().close().await;
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn forward_round_trip() {
+        let fixture = Fixture::start("forward").await;
+        fixture.trust(&fixture.host_key);
+
+        // 隧道后面的回显服务 | Echo service behind the tunnel
+        let echo = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let echo_port = echo.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = echo.accept().await {
+                tokio::spawn(async move {
+                    let (mut r, mut w) = stream.split();
+                    let _ = tokio::io::copy(&mut r, &mut w).await;
+                });
+            }
+        });
+
+        let tunnel = Tunnel::start(&fixture.password(), "127.0.0.1", echo_port)
+            .await
+            .unwrap();
+
+        let payload = vec![7u8; BUFFER_SIZE * 3];
+        let mut stream = connect_local(&tunnel).await;
+        stream.write_all(&payload).await.unwrap();
+        let mut received = vec![0u8; payload.len()];
+        stream.read_exact(&mut received).await.unwrap();
+        assert_eq!(received, payload);
+
+        drop(stream);
+        let local_addr = tunnel.local_addr().clone();
+        tunnel.close().await;
+        // 关闭后套接字的目录被删除 | The socket directory is removed once closed
+        #[cfg(unix)]
+        {
+            let LocalAddr::Socket(path) = local_addr;
+            assert!(!path.parent().unwrap().exists());
+        }
+        #[cfg(not(unix))]
+        let _ = local_addr;
+    }
+
+    #[tokio::test]
+    async fn idle_tunnel_wakes_up() {
+        let fixture = Fixture::start("idle").await;
+        fixture.trust(&fixture.host_key);
+
+        let echo = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let echo_port = echo.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = echo.accept().await {
+                tokio::spawn(async move {
+                    let (mut r, mut w) = stream.split();
+                    let _ = tokio::io::copy(&mut r, &mut w).await;
+                });
+            }
+        });
+
+        let tunnel = Tunnel::start(&fixture.password(), "127.0.0.1", echo_port)
+            .await
+            .unwrap();
+
+        // 空闲时转发线程在等待, 新连接和它的数据要能唤醒它
+        // The forwarding thread waits while idle, a new connection and its data must wake it up
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        let round_trip = async {
+            let mut stream = connect_local(&tunnel).await;
+            stream.write_all(b"ping").await.unwrap();
+            let mut received = [0u8; 4];
+            stream.read_exact(&mut received).await.unwrap();
+            received
+        };
+        let received = tokio::time::timeout(Duration::from_secs(5), round_trip)
+            .await
+            .unwrap();
+        assert_eq!(&received, b"ping");
+
+        // close 不用等到下一次心跳 | close doesn't wait for the next keepalive
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        tokio::time::timeout(Duration::from_secs(5), tunnel.close())
+            .await
+            .unwrap();
+    }
+}
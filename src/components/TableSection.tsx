@@ -7,6 +7,7 @@ import { getTab } from "@/context";
 import { execMany } from "@/databases/adapter,";
 import { modifyTableData } from "@/databases/adapter_uils";
 import { RowData } from "@/databases/types";
+import { dbErrorMessage } from "@/databases/utils";
 import { useActiveTabStore } from "@/hooks/useActiveTabStore";
 import { cn } from "@/lib/utils";
 import { addNotification, coreState } from "@/store/core";
@@ -123,22 +124,18 @@ export function TableSection({ width, getData, initData, btnExt, ref }: TableSec
   }
 
   async function handleConfirm() {
-    const res = await execMany(willExecCmd);
-    if (res) {
-      if (res.errorMessage !== "") {
-        let message = res.errorMessage.replace(ERROR_FROM_DB_PREFIX, "");
-        setErrorMessage(message);
-        addNotification(message, "error");
-      } else {
-        setOkMessage("Ok");
-        // 清理数据
-        handleCancel();
-        setTimeout(() => {
-          setShowDialogAlter(false);
-        }, 500);
-      }
-    } else {
-      setErrorMessage("The result of exec is null"); // TODO: 添加翻译
+    try {
+      await execMany(willExecCmd);
+      setOkMessage("Ok");
+      // 清理数据
+      handleCancel();
+      setTimeout(() => {
+        setShowDialogAlter(false);
+      }, 500);
+    } catch (err) {
+      let message = dbErrorMessage(err).replace(ERROR_FROM_DB_PREFIX, "");
+      setErrorMessage(message);
+      addNotification(message, "error");
     }
 
     initData();
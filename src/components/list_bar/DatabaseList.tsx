@@ -10,6 +10,7 @@ import { LIST_BAR_TABLE, MAIN_AREA_EDIT_CONNECTION, MAIN_AREA_TABLE_EDITOR } fro
 import { getTab } from "@/context";
 import { connect } from "@/databases/adapter,";
 import { DB_MYSQL, DB_POSTGRESQL, DB_SQLITE } from "@/databases/constants";
+import { dbErrorMessage, isDbError } from "@/databases/utils";
 import { invoker } from "@/invoker";
 import { addNotification, coreState } from "@/store/core";
 import { DbConnections } from "@/types/conf_file";
@@ -48,26 +49,29 @@ export function DatabaseList() {
     tabState.setConnColor(conn.color);
     tabState.setConnName(conn.name);
 
-    const res = await connect({
-      dbName: conn.dbName,
-      host: conn.host,
-      password: conn.password,
-      port: conn.port,
-      user: conn.user,
-      filePath: conn.filePath,
-    });
+    try {
+      await connect({
+        dbName: conn.dbName,
+        host: conn.host,
+        password: conn.password,
+        port: conn.port,
+        user: conn.user,
+        filePath: conn.filePath,
+        sslmode: conn.sslmode,
+      });
+    } catch (err) {
+      // 已经连接过的直接切换过去 | Switch to a connection that is already open
+      if (!isDbError(err) || err.code !== "duplicate_connection") {
+        addNotification(dbErrorMessage(err), "error");
+        return;
+      }
+    }
 
-    if (!res) {
-      addNotification("The result of connect is null", "error");
-    } else if (res.errorMessage !== "" && !res.errorMessage.includes("Duplicate connection name")) {
-      addNotification(res.errorMessage, "error");
-    } else {
-      tabState.setDbName(conn.dbName);
-      tabState.setMainAreaType(MAIN_AREA_TABLE_EDITOR);
-      tabState.setConnColor(conn.color);
+    tabState.setDbName(conn.dbName);
+    tabState.setMainAreaType(MAIN_AREA_TABLE_EDITOR);
+    tabState.setConnColor(conn.color);
 
-      coreState.setListBarType(LIST_BAR_TABLE);
-    }
+    coreState.setListBarType(LIST_BAR_TABLE);
   }
 
   const [listData, setListData] = useState<ListItem[]>([]);
@@ -13,6 +13,7 @@ import {
   getAllTableName,
   getAllTableSize,
 } from "@/databases/adapter,";
+import { dbErrorMessage } from "@/databases/utils";
 import { useActiveTabStore } from "@/hooks/useActiveTabStore";
 import { addNotification, coreState, setTabTitle } from "@/store/core";
 import { ConfirmDialog } from "../ConfirmDialog";
@@ -186,17 +187,14 @@ export function TableList() {
   }
 
   async function handleConfirm() {
-    const res = await execMany(willExecCmd);
-
-    if (!res) {
-      addNotification("The result of exec is null", "error");
-      setErrorMessage("The result of exec is null");
-    } else if (res.errorMessage !== "") {
-      addNotification(res.errorMessage, "error");
-      setErrorMessage(res.errorMessage);
-    } else {
+    try {
+      await execMany(willExecCmd);
       setOkMessage("Ok");
       getData();
+    } catch (err) {
+      const message = dbErrorMessage(err);
+      addNotification(message, "error");
+      setErrorMessage(message);
     }
 
     closeDialog();
@@ -207,11 +205,18 @@ export function TableList() {
   const [listData, setListData] = useState<ListItem[]>([]);
 
   async function getData() {
-    // 获取表名
-    const res = await getAllTableName();
+    let res: Awaited<ReturnType<typeof getAllTableName>>;
+    let sizeRes: Awaited<ReturnType<typeof getAllTableSize>>;
+    try {
+      // 获取表名和表格大小 | Get the table names and table sizes
+      res = await getAllTableName();
+      sizeRes = await getAllTableSize();
+    } catch (err) {
+      addNotification(dbErrorMessage(err), "error");
+      return;
+    }
+
     if (res && res.data) {
-      // 获取表格大小
-      const sizeRes = await getAllTableSize();
       if (sizeRes && sizeRes.data) {
         const arrTb: TableData[] = [];
 
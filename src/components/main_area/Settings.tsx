@@ -71,9 +71,13 @@ export function Settings() {
   }
 
   async function getData() {
-    const res = await getTableDdl(tabState.tableName);
-    if (res && res.data) {
-      // setTableData(res.data);
+    try {
+      const res = await getTableDdl(tabState.tableName);
+      if (res && res.data) {
+        // setTableData(res.data);
+      }
+    } catch (err) {
+      console.log("getTableDdl error: ", err);
     }
   }
 
@@ -9,11 +9,20 @@ import { useSnapshot } from "valtio";
 import Editor, { BeforeMount, OnChange, OnMount } from "@monaco-editor/react";
 import { DEFAULT_PAGE_SIZE, ERROR_FROM_DB_PREFIX, RE_IS_SINGLET_QUERY } from "@/constants";
 import { getTab } from "@/context";
-import { exec, getAllTableName, getPageCount, query } from "@/databases/adapter,";
-import { RowData } from "@/databases/types";
-import { extractConditionClause } from "@/databases/utils";
+import {
+  closeCursor,
+  closeSession,
+  exec,
+  fetchPage,
+  getAllTableName,
+  getPageCount,
+  openCursor,
+  openSession,
+  query,
+} from "@/databases/adapter,";
+import { columnNames, dbErrorMessage, extractConditionClause, isDbError, rowsToObjects } from "@/databases/utils";
 import { coreState } from "@/store/core";
-import { DbResult, TextNotificationData } from "@/types/types";
+import { QueryResult, TextNotificationData } from "@/types/types";
 import { formatSql } from "@/utils/format_sql";
 import { genPanelPercent } from "@/utils/util";
 import { TableSection, TableSectionMethods } from "../TableSection";
@@ -58,6 +67,78 @@ export function SqlEditor() {
   }
 
   // ========== 执行语句 | Execute statements ==========
+  // 当前查询的游标, 翻页时复用, 语句或连接变化后重新打开
+  // Cursor of the current query, reused when paging and reopened once the statement or connection changes
+  const cursorRef = useRef<{ connName: string; sql: string; id: string } | null>(null);
+
+  async function closeQueryCursor() {
+    const cursor = cursorRef.current;
+    cursorRef.current = null;
+    if (cursor) await closeCursor(cursor.connName, cursor.id);
+  }
+
+  // 每个编辑器标签页使用一个会话, 事务可以跨多次执行, 标签页关闭时关闭会话, 未提交的事务会回滚
+  // Each editor tab uses one session so a transaction can span several runs,
+  // it is closed with the tab and an uncommitted transaction is rolled back
+  const sessionRef = useRef<{ connName: string; id: string } | null>(null);
+  // 会话上次执行后是否处于事务中 | Whether the session was in a transaction after the last run
+  const inTransactionRef = useRef(false);
+
+  async function closeEditorSession() {
+    const session = sessionRef.current;
+    sessionRef.current = null;
+    inTransactionRef.current = false;
+    if (session) await closeSession(session.connName, session.id);
+  }
+
+  async function editorSession() {
+    const { currentConnName } = coreState;
+    let session = sessionRef.current;
+    if (session === null || session.connName !== currentConnName) {
+      await closeEditorSession();
+      session = { connName: currentConnName, id: await openSession() };
+      sessionRef.current = session;
+    }
+    return session.id;
+  }
+
+  // 会话被后端关闭后 (例如重连), 下次执行时重新打开
+  // Once the backend closed the session, e.g. on reconnect, it is reopened on the next run
+  function forgetClosedSession(err: unknown) {
+    if (isDbError(err) && err.code === "session_not_found") {
+      sessionRef.current = null;
+      inTransactionRef.current = false;
+    }
+  }
+
+  async function fetchQueryPage(code: string, page: number) {
+    const { currentConnName } = coreState;
+    // 事务中的查询要在会话的连接上执行才能看到未提交的修改, 游标使用的是另外的连接
+    // Inside a transaction the query must run on the session's connection to see uncommitted changes,
+    // cursors use a connection of their own
+    if (inTransactionRef.current && sessionRef.current?.connName === currentConnName) {
+      const dbRes = await query(code, page * DEFAULT_PAGE_SIZE, sessionRef.current.id);
+      return { ...dbRes, rows: dbRes.rows.slice((page - 1) * DEFAULT_PAGE_SIZE) };
+    }
+
+    let cursor = cursorRef.current;
+    if (cursor === null || cursor.sql !== code || cursor.connName !== currentConnName) {
+      await closeQueryCursor();
+      cursor = { connName: currentConnName, sql: code, id: await openCursor(code) };
+      cursorRef.current = cursor;
+    }
+
+    try {
+      return await fetchPage(cursor.id, page, DEFAULT_PAGE_SIZE);
+    } catch (err) {
+      // 游标空闲超时被关闭后重新打开 | Reopen the cursor after it was closed for idling
+      if (!isDbError(err) || err.code !== "cursor_not_found") throw err;
+      cursor = { connName: currentConnName, sql: code, id: await openCursor(code) };
+      cursorRef.current = cursor;
+      return await fetchPage(cursor.id, page, DEFAULT_PAGE_SIZE);
+    }
+  }
+
   async function queryPage(page: number) {
     if (coreState.currentConnName === "") {
       addMessageData({
@@ -68,37 +149,28 @@ export function SqlEditor() {
     }
 
     const code = getEditorCode();
-    const dbRes = await query(code, true, page, DEFAULT_PAGE_SIZE);
-    if (dbRes) {
-      const data = dbRes.data ? (JSON.parse(dbRes.data) as RowData[]) : [];
+    let dbRes: QueryResult;
+    let res: Awaited<ReturnType<typeof getPageCount>>;
+    try {
+      dbRes = await fetchQueryPage(code, page);
 
       const condition = extractConditionClause(code);
-      const res = await getPageCount(
-        coreState.currentConnName,
-        condition.tableName,
-        DEFAULT_PAGE_SIZE,
-        condition.condition,
-      );
-
-      if (res) {
-        if (tableRef.current) {
-          tableRef.current.setFieldNames(dbRes.columnName ? (JSON.parse(dbRes.columnName) as string[]) : []);
-          tableRef.current.setTableData(data);
-          tableRef.current.setPageTotal(res.pageTotal);
-          tableRef.current.setItemsTotal(res.itemsTotal);
-        }
-      } else {
-        addMessageData({
-          message: "The query returned null.",
-          type: "info",
-        });
-      }
+      res = await getPageCount(coreState.currentConnName, condition.tableName, DEFAULT_PAGE_SIZE, condition.condition);
+    } catch (err) {
+      forgetClosedSession(err);
+      addMessageData({
+        message: dbErrorMessage(err).replace(ERROR_FROM_DB_PREFIX, " "),
+        type: "error",
+      });
+      return;
+    }
 
-      if (dbRes.errorMessage !== "") {
-        addMessageData({
-          message: dbRes.errorMessage.replace(ERROR_FROM_DB_PREFIX, " "),
-          type: "error",
-        });
+    if (res) {
+      if (tableRef.current) {
+        tableRef.current.setFieldNames(columnNames(dbRes));
+        tableRef.current.setTableData(rowsToObjects(dbRes));
+        tableRef.current.setPageTotal(res.pageTotal);
+        tableRef.current.setItemsTotal(res.itemsTotal);
       }
     } else {
       addMessageData({
@@ -129,21 +201,14 @@ export function SqlEditor() {
         console.log(t("Not a single-table query"));
       }
     } else {
-      const res = await exec(code);
-
-      if (res) {
-        const resData = res as unknown as DbResult;
-        if (resData.errorMessage !== "") {
-          addMessageData({
-            message: resData.errorMessage.replace(ERROR_FROM_DB_PREFIX, " "),
-            type: "error",
-          });
-        } else {
-          //  TODO: 显示影响的行数
-        }
-      } else {
+      try {
+        const res = await exec(code, await editorSession());
+        inTransactionRef.current = res.inTransaction ?? false;
+        //  TODO: 显示影响的行数
+      } catch (err) {
+        forgetClosedSession(err);
         addMessageData({
-          message: "The query returned null.",
+          message: dbErrorMessage(err).replace(ERROR_FROM_DB_PREFIX, " "),
           type: "error",
         });
       }
@@ -352,6 +417,11 @@ export function SqlEditor() {
   useEffect(() => {
     resizeLayout();
     resizeEditor();
+
+    return () => {
+      closeQueryCursor();
+      closeEditorSession();
+    };
   }, []);
 
   const tooltipSectionData = [
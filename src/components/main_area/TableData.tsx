@@ -5,7 +5,7 @@ import { useSnapshot } from "valtio";
 import { DEFAULT_PAGE_SIZE } from "@/constants";
 import { getTab } from "@/context";
 import { getTableData } from "@/databases/adapter,";
-import { getDefultOrderField } from "@/databases/utils";
+import { dbErrorMessage, getDefultOrderField } from "@/databases/utils";
 import { useActiveTabStore } from "@/hooks/useActiveTabStore";
 import { addNotification, coreState } from "@/store/core";
 import { TableSection, TableSectionMethods } from "../TableSection";
@@ -129,14 +129,20 @@ export function TableData() {
     }
 
     const sortField = getDefultOrderField(tabState.tableStructure);
-    const res = await getTableData({
-      tableName: tabState.tableName,
-      currentPage: page,
-      fields: isInit ? ALL_FIELD : checkedField,
-      pageSize: DEFAULT_PAGE_SIZE,
-      where: "",
-      sortField: [{ fieldName: sortField, direction: "ASC" }],
-    });
+    let res: Awaited<ReturnType<typeof getTableData>>;
+    try {
+      res = await getTableData({
+        tableName: tabState.tableName,
+        currentPage: page,
+        fields: isInit ? ALL_FIELD : checkedField,
+        pageSize: DEFAULT_PAGE_SIZE,
+        where: "",
+        sortField: [{ fieldName: sortField, direction: "ASC" }],
+      });
+    } catch (err) {
+      addNotification(dbErrorMessage(err), "error");
+      return [];
+    }
 
     if (res) {
       if (tableRef.current) {
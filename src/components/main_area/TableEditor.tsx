@@ -14,9 +14,9 @@ import {
 import { getTab } from "@/context";
 import { getTableDdl, getTableStructure } from "@/databases/adapter,";
 import { AllAlterAction, TableAlterAction } from "@/databases/types";
-import { getUniqueFieldName } from "@/databases/utils";
+import { dbErrorMessage, getUniqueFieldName } from "@/databases/utils";
 import { useActiveTabStore } from "@/hooks/useActiveTabStore";
-import { coreState } from "@/store/core";
+import { addNotification, coreState } from "@/store/core";
 import { Input } from "../ui/input";
 import { Tabs, TabsContent, TabsList, TabsTrigger } from "../ui/tabs";
 import { TableConstraint } from "./TableConstraint";
@@ -40,16 +40,23 @@ export function TableEditor() {
       return;
     }
 
-    // 获取表结构, 会在多个地方用, 在这里记录到 store
-    const res = await getTableStructure(tabState.tableName);
+    let res: Awaited<ReturnType<typeof getTableStructure>>;
+    let resDdl: Awaited<ReturnType<typeof getTableDdl>>;
+    try {
+      // 获取表结构和建表语句, 会在多个地方用, 在这里记录到 store
+      res = await getTableStructure(tabState.tableName);
+      resDdl = await getTableDdl(tabState.tableName);
+    } catch (err) {
+      addNotification(dbErrorMessage(err), "error");
+      return;
+    }
+
     if (res && res.data) {
       tabState.setTableStructure(res.data);
       tabState.setUniqueFieldName(getUniqueFieldName(res.data));
       if (tabState.mainAreaTab === STR_EMPTY) tabState.setMainAreaTab(MAIN_AREA_TAB_DATA);
     }
 
-    // 获取建表语句, 会在多个地方用, 在这里记录到 store
-    const resDdl = await getTableDdl(tabState.tableName);
     if (resDdl && resDdl.data) {
       let sql = resDdl.data;
       if (sql === "") sql = t("No DDL found");
@@ -6,6 +6,7 @@ import { DIR_H, ERROR_FROM_DB_PREFIX, HEDAER_H, STR_ADD, STR_DELETE, STR_EDIT, S
 import { getTab } from "@/context";
 import { execMany, fieldTypeOptions, genAlterCmd } from "@/databases/adapter,";
 import { AllAlterAction, AlterAction, FieldAlterAction } from "@/databases/types";
+import { dbErrorMessage } from "@/databases/utils";
 import { useActiveTabStore } from "@/hooks/useActiveTabStore";
 import { cn } from "@/lib/utils";
 import { addNotification, coreState } from "@/store/core";
@@ -380,22 +381,22 @@ export function TableStructure({
   }
 
   async function handleConfirm() {
-    const res = await execMany(willExecCmd);
-    if (!res) {
-      addNotification("The result of execMany is null", "error");
-    } else if (res.errorMessage === "") {
-      setShowDialogAlter(false);
-      await getData();
-      resetData(true);
-      setOkMessage("OK");
-      addNotification("OK", "success");
-
-      //  TODO: 显示影响的行数
-    } else {
-      let message = res.errorMessage.replace(ERROR_FROM_DB_PREFIX, "");
+    try {
+      await execMany(willExecCmd);
+    } catch (err) {
+      let message = dbErrorMessage(err).replace(ERROR_FROM_DB_PREFIX, "");
       setErrorMessage(message);
       addNotification(message, "error");
+      return;
     }
+
+    setShowDialogAlter(false);
+    await getData();
+    resetData(true);
+    setOkMessage("OK");
+    addNotification("OK", "success");
+
+    //  TODO: 显示影响的行数
   }
 
   const tooltipSectionData = [
@@ -1,4 +1,6 @@
+import { ask } from "@tauri-apps/plugin-dialog";
 import { BuilderPg } from "@/databases/postgresql/builder";
+import i18n from "@/i18n";
 import { invoker } from "@/invoker";
 import { coreState } from "@/store/core";
 import { DB_MYSQL, DB_POSTGRESQL, DB_SQLITE } from "./constants";
@@ -11,6 +13,7 @@ import { getDataTypeCategorySqlite } from "./sqlite/icon";
 import { fieldTypeOptionsSqlite } from "./sqlite/select_options";
 import { BuilderSqilte } from "./sqlite/builder";
 import { AllAlterAction, DbConnectionParam, FieldWithValue, GetTableDataParam } from "./types";
+import { isDbError } from "./utils";
 
 // 连接数据库
 export async function connect(p: DbConnectionParam) {
@@ -25,22 +28,78 @@ export async function disconnect(currentConnName: string) {
   return await invoker.disconnectSql(currentConnName);
 }
 
-// 查询语句
-export async function query(sql: string, streaming?: boolean, page?: number, pageSize?: number) {
+// 查询语句, 传入 sessionId 时在会话的连接上执行 | Query, run on the session's connection when sessionId is given
+export async function query(sql: string, maxRows?: number, sessionId?: string) {
   const { currentConnName } = coreState;
-  return await invoker.querySql(currentConnName, sql, streaming, page, pageSize);
+  return await withConfirmation((confirmToken) =>
+    invoker.querySql(currentConnName, sql, maxRows, sessionId, confirmToken),
+  );
+}
+
+// 打开游标, 分页读取查询结果 | Open a cursor to read the query results page by page
+export async function openCursor(sql: string) {
+  const { currentConnName } = coreState;
+  return await withConfirmation((confirmToken) => invoker.openCursor(currentConnName, sql, confirmToken));
+}
+
+// 读取游标的一页数据 | Read a page from the cursor
+export async function fetchPage(cursorId: string, page: number, pageSize: number) {
+  const { currentConnName } = coreState;
+  return await invoker.fetchPage(currentConnName, cursorId, page, pageSize);
+}
+
+// 关闭游标 | Close the cursor
+export async function closeCursor(connName: string, cursorId: string) {
+  return await invoker.closeCursor(connName, cursorId);
+}
+
+/**
+ * 破坏性的语句先请用户确认, 同意后带着后端给出的确认令牌重新执行
+ * Destructive statements are confirmed with the user first, then run again with the token from the backend
+ * @param run 执行语句, 重试时传入确认令牌 | Runs the statements, given the confirmation token on retry
+ * @returns
+ */
+async function withConfirmation<T>(run: (confirmToken?: string) => Promise<T>) {
+  try {
+    return await run();
+  } catch (err) {
+    if (!isDbError(err) || err.code !== "confirmation_required" || !err.confirmToken) throw err;
+
+    const confirmed = await ask(err.message, {
+      title: i18n.t("Confirm"),
+      kind: "warning",
+      okLabel: i18n.t("Confirm"),
+      cancelLabel: i18n.t("Cancel"),
+    });
+    if (!confirmed) throw err;
+
+    return await run(err.confirmToken);
+  }
 }
 
 // 执行语句
-export async function exec(sql: string) {
+export async function exec(sql: string, sessionId?: string) {
   const { currentConnName } = coreState;
-  return await invoker.execSql(currentConnName, sql);
+  return await withConfirmation((confirmToken) => invoker.execSql(currentConnName, sql, confirmToken, sessionId));
 }
 
 // 执行语句
-export async function execMany(sql: string) {
+export async function execMany(sql: string, sessionId?: string) {
   const { currentConnName } = coreState;
-  return await invoker.execManySql(currentConnName, sql);
+  return await withConfirmation((confirmToken) =>
+    invoker.execManySql(currentConnName, sql, confirmToken, sessionId),
+  );
+}
+
+// 打开会话 | Open a session
+export async function openSession() {
+  const { currentConnName } = coreState;
+  return await invoker.openSession(currentConnName);
+}
+
+// 关闭会话, 未提交的事务会回滚 | Close the session, an uncommitted transaction is rolled back
+export async function closeSession(connName: string, sessionId: string) {
+  return await invoker.closeSession(connName, sessionId);
 }
 
 // 获取所有表名
@@ -174,22 +233,22 @@ export function genUpdateFieldCmd(tbName: string, uniqueField: FieldWithValue, f
 }
 
 // 生成删除多行的字段
-export function genDeleteRowsCmd(tbName: string, fieldName: string, fieldValues: any[]) {
+export function genDeleteRowsCmd(tbName: string, fieldName: string, fieldValues: any[], fieldType?: string) {
   const { currentConnType } = coreState;
 
   if (currentConnType === DB_MYSQL) return BuilderMysql.genDeleteRowsCmd(tbName, fieldName, fieldValues);
-  if (currentConnType === DB_POSTGRESQL) return BuilderPg.genDeleteRowsCmd(tbName, fieldName, fieldValues);
+  if (currentConnType === DB_POSTGRESQL) return BuilderPg.genDeleteRowsCmd(tbName, fieldName, fieldValues, fieldType);
   if (currentConnType === DB_SQLITE) return BuilderSqilte.genDeleteRowsCmd(tbName, fieldName, fieldValues);
 
   return "";
 }
 
 // 生成插入多行数据
-export function genInsertRowsCmd(tbName: string, fieldNames: string[], fieldValues: any[]) {
+export function genInsertRowsCmd(tbName: string, fieldNames: string[], fieldValues: any[], fieldTypes?: string[]) {
   const { currentConnType } = coreState;
 
   if (currentConnType === DB_MYSQL) return BuilderMysql.genInsertRowsCmd(tbName, fieldNames, fieldValues);
-  if (currentConnType === DB_POSTGRESQL) return BuilderPg.genInsertRowsCmd(tbName, fieldNames, fieldValues);
+  if (currentConnType === DB_POSTGRESQL) return BuilderPg.genInsertRowsCmd(tbName, fieldNames, fieldValues, fieldTypes);
   if (currentConnType === DB_SQLITE) return BuilderSqilte.genInsertRowsCmd(tbName, fieldNames, fieldValues);
 
   return "";
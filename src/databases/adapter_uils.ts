@@ -17,6 +17,8 @@ export function modifyTableData(
   const tbState = tab.state;
 
   const tbName = tbState.tableName;
+  // 列的类型, 用于生成该类型的字面量 | Types of the columns, used to build literals of them
+  const fieldType = (name: string) => tbState.tableStructure.find((item) => item.name === name)?.type;
 
   // 处理变更数据的行
   const rowDataMap = new Map<number, TableDataChange[]>();
@@ -35,12 +37,14 @@ export function modifyTableData(
     const uniqueField: FieldWithValue = {
       field: tbState.uniqueFieldName,
       value: tableData[rowIndex][tbState.uniqueFieldName],
+      type: fieldType(tbState.uniqueFieldName),
     };
     const fieldArr: FieldWithValue[] = [];
     for (const c of changes) {
       fieldArr.push({
         field: c.field,
         value: c.new,
+        type: fieldType(c.field),
       });
     }
 
@@ -55,7 +59,9 @@ export function modifyTableData(
       arr.push(row[tbState.uniqueFieldName]);
     }
   }
-  if (arr.length > 0) sqls.push(genDeleteRowsCmd(tbName, tbState.uniqueFieldName, arr));
+  if (arr.length > 0) {
+    sqls.push(genDeleteRowsCmd(tbName, tbState.uniqueFieldName, arr, fieldType(tbState.uniqueFieldName)));
+  }
 
   // 处理新添加的行
   if (addedRows.length > 0) {
@@ -65,7 +71,7 @@ export function modifyTableData(
       values.push(fieldNames.map((item) => r[item].value));
     }
 
-    sqls.push(genInsertRowsCmd(tbName, fieldNames, values));
+    sqls.push(genInsertRowsCmd(tbName, fieldNames, values, fieldNames.map(fieldType)));
   }
 
   return sqls;
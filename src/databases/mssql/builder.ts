@@ -8,6 +8,7 @@ import {
   GetTableDataParam,
   getAllTableSizeRes,
 } from "../types";
+import { columnNames, rowsToObjects } from "../utils";
 import { formatToSqlValueMssql } from "./format";
 import "./types";
 
@@ -19,7 +20,10 @@ export class GeneratorMssql {
    *
    */
   static async connect(connName: string, p: DbConnectionParam) {
-    return await invoker.connectSql(connName, `mssql://${p.user}:${p.password}@${p.host}:${p.port}/${p.dbName}`);
+    // 后端还不支持这个引擎, 解析 URL 时会返回 invalid_config
+    // The backend doesn't support this engine yet, parsing the URL fails with invalid_config
+    const url = `mssql://${encodeURIComponent(p.user)}:${encodeURIComponent(p.password)}@${p.host}:${p.port}/${p.dbName}`;
+    return await invoker.connectSql(connName, await invoker.parseUrl(url));
   }
 
   // 获取所有表格名
@@ -37,10 +41,10 @@ export class GeneratorMssql {
     const dbRes = await invoker.querySql(connName, sql);
 
     // 把表名整理成一维数组
-    const dataArr = dbRes.data ? (JSON.parse(dbRes.data) as { tableName: string }[]) : [];
+    const dataArr = rowsToObjects<{ tableName: string }>(dbRes);
 
     return {
-      columnName: dbRes.columnName ? (JSON.parse(dbRes.columnName) as string[]) : [],
+      columnName: columnNames(dbRes),
       data: dataArr.map((item) => item.tableName),
     };
   }
@@ -67,8 +71,8 @@ export class GeneratorMssql {
 
     const dbRes = await invoker.querySql(connName, sql);
     return {
-      columnName: dbRes.columnName ? (JSON.parse(dbRes.columnName) as string[]) : [],
-      data: dbRes.data ? (JSON.parse(dbRes.data) as getAllTableSizeRes[]) : [],
+      columnName: columnNames(dbRes),
+      data: rowsToObjects<getAllTableSizeRes>(dbRes),
     };
   }
 
@@ -137,9 +141,9 @@ export class GeneratorMssql {
     ]);
 
     // 处理字段信息
-    const columns = columnRes.data ? (JSON.parse(columnRes.data) as FieldStructure[]) : [];
+    const columns = rowsToObjects<FieldStructure>(columnRes);
     // 处理索引信息
-    const indexes = indexRes.data ? (JSON.parse(indexRes.data) as FieldIndex[]) : [];
+    const indexes = rowsToObjects<FieldIndex>(indexRes);
 
     // 将索引信息合并到字段信息中
     const columnIndexMap: Record<string, FieldIndex[]> = {};
@@ -158,7 +162,7 @@ export class GeneratorMssql {
     }));
 
     return {
-      columnName: columnRes.columnName ? (JSON.parse(columnRes.columnName) as string[]) : [],
+      columnName: columnNames(columnRes),
       data: result,
     };
   }
@@ -173,10 +177,10 @@ export class GeneratorMssql {
     const dbRes = await invoker.querySql(connName, sql);
 
     const keyStr = "?column?";
-    const jjj = dbRes.data ? (JSON.parse(dbRes.data) as { [key: string]: string }[]) : [];
+    const jjj = rowsToObjects<{ [key: string]: string }>(dbRes);
 
     return {
-      columnName: dbRes.columnName ? (JSON.parse(dbRes.columnName) as string[]) : [],
+      columnName: columnNames(dbRes),
       data: jjj.length > 0 && keyStr in jjj[0] ? jjj[0][keyStr] : "",
     };
   }
@@ -195,8 +199,8 @@ export class GeneratorMssql {
       `SELECT COUNT(*) AS total FROM [${tableName}] ${condition ? condition : ""};`,
     );
     let itemsTotal = 0; // 总条数
-    if (dbResTotal && dbResTotal.data) {
-      const bbCountRes = JSON.parse(dbResTotal.data) as DbCountRes[];
+    if (dbResTotal) {
+      const bbCountRes = rowsToObjects<DbCountRes>(dbResTotal);
 
       if (bbCountRes.length > 0) itemsTotal = bbCountRes[0].total;
     }
@@ -227,8 +231,8 @@ export class GeneratorMssql {
     return {
       itemsTotal,
       pageTotal,
-      columnName: dbRes.columnName ? (JSON.parse(dbRes.columnName) as string[]) : [],
-      data: dbRes.data ? (JSON.parse(dbRes.data) as object[]) : [],
+      columnName: columnNames(dbRes),
+      data: rowsToObjects<object>(dbRes),
     };
   }
 
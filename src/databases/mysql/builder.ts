@@ -8,6 +8,7 @@ import {
   GetTableDataParam,
   getAllTableSizeRes,
 } from "../types";
+import { columnNames, rowsToObjects } from "../utils";
 import { formatToSqlValueMysql } from "./format";
 import "./types";
 
@@ -19,7 +20,15 @@ export class BuilderMysql {
    *
    */
   static async connect(connName: string, p: DbConnectionParam) {
-    return await invoker.connectSql(connName, `mysql://${p.user}:${p.password}@${p.host}:${p.port}/${p.dbName}`);
+    return await invoker.connectSql(connName, {
+      engine: "mysql",
+      host: p.host,
+      port: p.port,
+      user: p.user,
+      password: p.password,
+      database: p.dbName,
+      sslMode: p.sslmode,
+    });
   }
 
   // 获取所有表格名
@@ -37,10 +46,10 @@ export class BuilderMysql {
     const dbRes = await invoker.querySql(connName, sql);
 
     // 把表名整理成一维数组
-    const dataArr = dbRes.data ? (JSON.parse(dbRes.data) as { tableName: string }[]) : [];
+    const dataArr = rowsToObjects<{ tableName: string }>(dbRes);
 
     return {
-      columnName: dbRes.columnName ? (JSON.parse(dbRes.columnName) as string[]) : [],
+      columnName: columnNames(dbRes),
       data: dataArr.map((item) => item.tableName),
     };
   }
@@ -69,8 +78,8 @@ export class BuilderMysql {
 
     const dbRes = await invoker.querySql(connName, sql);
     return {
-      columnName: dbRes.columnName ? (JSON.parse(dbRes.columnName) as string[]) : [],
-      data: dbRes.data ? (JSON.parse(dbRes.data) as getAllTableSizeRes[]) : [],
+      columnName: columnNames(dbRes),
+      data: rowsToObjects<getAllTableSizeRes>(dbRes),
     };
   }
 
@@ -121,9 +130,9 @@ export class BuilderMysql {
     ]);
 
     // 处理字段信息
-    const columns = columnRes.data ? (JSON.parse(columnRes.data) as FieldStructure[]) : [];
+    const columns = rowsToObjects<FieldStructure>(columnRes);
     // 处理索引信息
-    const indexes = indexRes.data ? (JSON.parse(indexRes.data) as FieldIndex[]) : [];
+    const indexes = rowsToObjects<FieldIndex>(indexRes);
 
     // 将索引信息合并到字段信息中
     const columnIndexMap: Record<string, FieldIndex[]> = {};
@@ -142,7 +151,7 @@ export class BuilderMysql {
     }));
 
     return {
-      columnName: columnRes.columnName ? (JSON.parse(columnRes.columnName) as string[]) : [],
+      columnName: columnNames(columnRes),
       data: result,
     };
   }
@@ -183,10 +192,10 @@ export class BuilderMysql {
     const dbRes = await invoker.querySql(connName, sql);
 
     const keyStr = "?column?";
-    const jjj = dbRes.data ? (JSON.parse(dbRes.data) as { [key: string]: string }[]) : [];
+    const jjj = rowsToObjects<{ [key: string]: string }>(dbRes);
 
     return {
-      columnName: dbRes.columnName ? (JSON.parse(dbRes.columnName) as string[]) : [],
+      columnName: columnNames(dbRes),
       data: jjj.length > 0 && keyStr in jjj[0] ? jjj[0][keyStr] : "",
     };
   }
@@ -205,8 +214,8 @@ export class BuilderMysql {
       `SELECT COUNT(*) AS total FROM \`${tableName}\` ${condition ? condition : ""};`,
     );
     let itemsTotal = 0; // 总条数
-    if (dbResTotal && dbResTotal.data) {
-      const bbCountRes = JSON.parse(dbResTotal.data) as DbCountRes[];
+    if (dbResTotal) {
+      const bbCountRes = rowsToObjects<DbCountRes>(dbResTotal);
 
       if (bbCountRes.length > 0) itemsTotal = bbCountRes[0].total;
     }
@@ -237,8 +246,8 @@ export class BuilderMysql {
     return {
       itemsTotal,
       pageTotal,
-      columnName: dbRes.columnName ? (JSON.parse(dbRes.columnName) as string[]) : [],
-      data: dbRes.data ? (JSON.parse(dbRes.data) as object[]) : [],
+      columnName: columnNames(dbRes),
+      data: rowsToObjects<object>(dbRes),
     };
   }
 
@@ -1,6 +1,6 @@
 import { invoker } from "@/invoker";
 import { SqlValueCommon } from "../types";
-import { formatToSqlValueCommon } from "../utils";
+import { columnNames, formatToSqlValueCommon, rowsToObjects } from "../utils";
 
 const testConnName = "testPg";
 
@@ -46,7 +46,7 @@ export async function getTableStructureMysql(tbName: string) {
   const dbRes = await invoker.querySql(testConnName, sql);
 
   return {
-    columnName: dbRes.columnName ? (JSON.parse(dbRes.columnName) as string[]) : [],
-    data: dbRes.data ? (JSON.parse(dbRes.data) as { tablename: string }[]) : [],
+    columnName: columnNames(dbRes),
+    data: rowsToObjects<{ tablename: string }>(dbRes),
   };
 }
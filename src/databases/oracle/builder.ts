@@ -8,6 +8,7 @@ import {
   GetTableDataParam,
   getAllTableSizeRes,
 } from "../types";
+import { columnNames, rowsToObjects } from "../utils";
 import { formatToSqlValueOracle } from "./format";
 import "./types";
 
@@ -19,7 +20,10 @@ export class BuilderOricle {
    *
    */
   static async connect(connName: string, p: DbConnectionParam) {
-    return await invoker.connectSql(connName, `oracle://${p.user}:${p.password}@${p.host}:${p.port}/${p.dbName}`);
+    // 后端还不支持这个引擎, 解析 URL 时会返回 invalid_config
+    // The backend doesn't support this engine yet, parsing the URL fails with invalid_config
+    const url = `oracle://${encodeURIComponent(p.user)}:${encodeURIComponent(p.password)}@${p.host}:${p.port}/${p.dbName}`;
+    return await invoker.connectSql(connName, await invoker.parseUrl(url));
   }
 
   // 获取所有表格名
@@ -36,10 +40,10 @@ export class BuilderOricle {
     const dbRes = await invoker.querySql(connName, sql);
 
     // 把表名整理成一维数组
-    const dataArr = dbRes.data ? (JSON.parse(dbRes.data) as { tableName: string }[]) : [];
+    const dataArr = rowsToObjects<{ tableName: string }>(dbRes);
 
     return {
-      columnName: dbRes.columnName ? (JSON.parse(dbRes.columnName) as string[]) : [],
+      columnName: columnNames(dbRes),
       data: dataArr.map((item) => item.tableName),
     };
   }
@@ -62,8 +66,8 @@ export class BuilderOricle {
 
     const dbRes = await invoker.querySql(connName, sql);
     return {
-      columnName: dbRes.columnName ? (JSON.parse(dbRes.columnName) as string[]) : [],
-      data: dbRes.data ? (JSON.parse(dbRes.data) as getAllTableSizeRes[]) : [],
+      columnName: columnNames(dbRes),
+      data: rowsToObjects<getAllTableSizeRes>(dbRes),
     };
   }
 
@@ -145,9 +149,9 @@ export class BuilderOricle {
     ]);
 
     // 处理字段信息
-    const columns = columnRes.data ? (JSON.parse(columnRes.data) as FieldStructure[]) : [];
+    const columns = rowsToObjects<FieldStructure>(columnRes);
     // 处理索引信息
-    const indexes = indexRes.data ? (JSON.parse(indexRes.data) as FieldIndex[]) : [];
+    const indexes = rowsToObjects<FieldIndex>(indexRes);
 
     // 将索引信息合并到字段信息中
     const columnIndexMap: Record<string, FieldIndex[]> = {};
@@ -166,7 +170,7 @@ export class BuilderOricle {
     }));
 
     return {
-      columnName: columnRes.columnName ? (JSON.parse(columnRes.columnName) as string[]) : [],
+      columnName: columnNames(columnRes),
       data: result,
     };
   }
@@ -183,10 +187,10 @@ export class BuilderOricle {
     const dbRes = await invoker.querySql(connName, sql);
 
     const keyStr = "?column?";
-    const jjj = dbRes.data ? (JSON.parse(dbRes.data) as { [key: string]: string }[]) : [];
+    const jjj = rowsToObjects<{ [key: string]: string }>(dbRes);
 
     return {
-      columnName: dbRes.columnName ? (JSON.parse(dbRes.columnName) as string[]) : [],
+      columnName: columnNames(dbRes),
       data: jjj.length > 0 && keyStr in jjj[0] ? jjj[0][keyStr] : "",
     };
   }
@@ -205,8 +209,8 @@ export class BuilderOricle {
       `SELECT COUNT(*) AS total FROM "${tableName}" ${condition ? condition : ""};`,
     );
     let itemsTotal = 0; // 总条数
-    if (dbResTotal && dbResTotal.data) {
-      const bbCountRes = JSON.parse(dbResTotal.data) as DbCountRes[];
+    if (dbResTotal) {
+      const bbCountRes = rowsToObjects<DbCountRes>(dbResTotal);
 
       if (bbCountRes.length > 0) itemsTotal = bbCountRes[0].total;
     }
@@ -236,8 +240,8 @@ export class BuilderOricle {
     return {
       itemsTotal,
       pageTotal,
-      columnName: dbRes.columnName ? (JSON.parse(dbRes.columnName) as string[]) : [],
-      data: dbRes.data ? (JSON.parse(dbRes.data) as object[]) : [],
+      columnName: columnNames(dbRes),
+      data: rowsToObjects<object>(dbRes),
     };
   }
 
@@ -25,6 +25,7 @@ import {
   GetTableDataParam,
   getAllTableSizeRes,
 } from "../types";
+import { columnNames, rowsToObjects } from "../utils";
 import { genAlterCmdPg } from "./builder_alter_table";
 import { formatToSqlValuePg } from "./format";
 import "./types";
@@ -37,7 +38,15 @@ export class BuilderPg {
    *
    */
   static async connect(connName: string, p: DbConnectionParam) {
-    return await invoker.connectSql(connName, `postgres://${p.user}:${p.password}@${p.host}:${p.port}/${p.dbName}`);
+    return await invoker.connectSql(connName, {
+      engine: "postgres",
+      host: p.host,
+      port: p.port,
+      user: p.user,
+      password: p.password,
+      database: p.dbName,
+      sslMode: p.sslmode,
+    });
   }
 
   // 获取所有表格名
@@ -54,10 +63,10 @@ export class BuilderPg {
     const dbRes = await invoker.querySql(connName, sql);
 
     // 把表名整理成一维数组
-    const dataArr = dbRes.data ? (JSON.parse(dbRes.data) as { tableName: string }[]) : [];
+    const dataArr = rowsToObjects<{ tableName: string }>(dbRes);
 
     return {
-      columnName: dbRes.columnName ? (JSON.parse(dbRes.columnName) as string[]) : [],
+      columnName: columnNames(dbRes),
       data: dataArr.map((item) => item.tableName),
     };
   }
@@ -83,8 +92,8 @@ export class BuilderPg {
 
     const dbRes = await invoker.querySql(connName, sql);
     return {
-      columnName: dbRes.columnName ? (JSON.parse(dbRes.columnName) as string[]) : [],
-      data: dbRes.data ? (JSON.parse(dbRes.data) as getAllTableSizeRes[]) : [],
+      columnName: columnNames(dbRes),
+      data: rowsToObjects<getAllTableSizeRes>(dbRes),
     };
   }
 
@@ -170,9 +179,9 @@ export class BuilderPg {
     ]);
 
     // 处理字段信息
-    const columns = columnRes.data ? (JSON.parse(columnRes.data) as FieldStructure[]) : [];
+    const columns = rowsToObjects<FieldStructure>(columnRes);
     // 处理索引信息
-    const indexes = indexRes.data ? (JSON.parse(indexRes.data) as FieldIndex[]) : [];
+    const indexes = rowsToObjects<FieldIndex>(indexRes);
 
     // 将索引信息合并到字段信息中
     const columnIndexMap: Record<string, FieldIndex[]> = {};
@@ -191,7 +200,7 @@ export class BuilderPg {
     }));
 
     return {
-      columnName: columnRes.columnName ? (JSON.parse(columnRes.columnName) as string[]) : [],
+      columnName: columnNames(columnRes),
       data: result,
     };
   }
@@ -300,10 +309,10 @@ export class BuilderPg {
     const dbRes = await invoker.querySql(connName, sql);
 
     const keyStr = "?column?";
-    const jjj = dbRes.data ? (JSON.parse(dbRes.data) as { [key: string]: string }[]) : [];
+    const jjj = rowsToObjects<{ [key: string]: string }>(dbRes);
 
     return {
-      columnName: dbRes.columnName ? (JSON.parse(dbRes.columnName) as string[]) : [],
+      columnName: columnNames(dbRes),
       data: jjj.length > 0 && keyStr in jjj[0] ? jjj[0][keyStr] : "",
     };
   }
@@ -322,8 +331,8 @@ export class BuilderPg {
       `SELECT COUNT(*) AS total FROM "${tableName}" ${condition ? condition : ""};`,
     );
     let itemsTotal = 0; // 总条数
-    if (dbResTotal && dbResTotal.data) {
-      const bbCountRes = JSON.parse(dbResTotal.data) as DbCountRes[];
+    if (dbResTotal) {
+      const bbCountRes = rowsToObjects<DbCountRes>(dbResTotal);
 
       if (bbCountRes.length > 0) itemsTotal = bbCountRes[0].total;
     }
@@ -353,8 +362,8 @@ export class BuilderPg {
     return {
       itemsTotal,
       pageTotal,
-      columnName: dbRes.columnName ? (JSON.parse(dbRes.columnName) as string[]) : [],
-      data: dbRes.data ? (JSON.parse(dbRes.data) as object[]) : [],
+      columnName: columnNames(dbRes),
+      data: rowsToObjects<object>(dbRes),
     };
   }
 
@@ -385,7 +394,7 @@ export class BuilderPg {
 
   // 生成变更一行的字段
   static genUpdateFieldCmd(tbName: string, uniqueField: FieldWithValue, fieldArr: FieldWithValue[]) {
-    const fda = fieldArr.map((item) => `"${item.field}" = ${formatToSqlValuePg(item.value)}`);
+    const fda = fieldArr.map((item) => `"${item.field}" = ${formatToSqlValuePg(item.value, false, item.type)}`);
 
     return `
       UPDATE "${tbName}"
@@ -396,17 +405,17 @@ export class BuilderPg {
   }
 
   // 生成删除多行的字段
-  static genDeleteRowsCmd(tbName: string, fieldName: string, fieldValues: any[]) {
-    const values = fieldValues.map((item) => formatToSqlValuePg(item)).join(",");
+  static genDeleteRowsCmd(tbName: string, fieldName: string, fieldValues: any[], fieldType?: string) {
+    const values = fieldValues.map((item) => formatToSqlValuePg(item, false, fieldType)).join(",");
     return `DELETE FROM "${tbName}" WHERE "${fieldName}" IN (${values});`;
   }
 
   // 生成插入多行数据
-  static genInsertRowsCmd(tbName: string, fieldNames: string[], fieldValues: any[][]) {
+  static genInsertRowsCmd(tbName: string, fieldNames: string[], fieldValues: any[][], fieldTypes?: string[]) {
     const fields = fieldNames.join(`","`);
     const valArr: any[] = [];
     fieldValues.map((itemRow) => {
-      const valRow = itemRow.map((item) => formatToSqlValuePg(item, true));
+      const valRow = itemRow.map((item, index) => formatToSqlValuePg(item, true, fieldTypes?.[index]));
       valArr.push(valRow);
     });
 
@@ -14,18 +14,153 @@ export function isPostgresFunctionCall(str: string): boolean {
   return /^[a-z_][a-z0-9_]*\(.*\)$/i.test(str);
 }
 
+// 几何类型的数字, 后端把 NaN 和无穷转成了 null | Number of a geometric type, the backend turned NaN and infinities into null
+function geometryNumber(n: number | null) {
+  return n === null ? "NaN" : String(n);
+}
+
+function geometryPoint(p: { x: number | null; y: number | null }) {
+  return `(${geometryNumber(p.x)},${geometryNumber(p.y)})`;
+}
+
+/**
+ * 把后端转换后的几何类型的 json 还原为 PostgreSQL 的文本格式, 不是几何类型时返回 null
+ * Turn the JSON of a geometric type from the backend back into PostgreSQL's text format, null for other types
+ * @param value 后端转换后的值, 例如 {x: 1, y: 2} | Value converted by the backend, e.g. {x: 1, y: 2}
+ * @param pgType 列的类型名 (pg_type.typname), 例如 point | Type name of the column (pg_type.typname), e.g. point
+ * @returns
+ */
+function geometryText(value: Record<string, any>, pgType: string): string | null {
+  switch (pgType) {
+    case "point":
+      return geometryPoint(value as { x: number; y: number });
+    case "line":
+      return `{${geometryNumber(value.a)},${geometryNumber(value.b)},${geometryNumber(value.c)}}`;
+    case "lseg":
+      return `[${geometryPoint(value.start)},${geometryPoint(value.end)}]`;
+    case "box":
+      return `${geometryPoint(value.high)},${geometryPoint(value.low)}`;
+    case "path": {
+      // 闭合的路径用圆括号, 开放的用方括号 | Closed paths use parentheses, open ones brackets
+      const points = value.points.map(geometryPoint).join(",");
+      return value.closed ? `(${points})` : `[${points}]`;
+    }
+    case "polygon":
+      return `(${value.points.map(geometryPoint).join(",")})`;
+    case "circle":
+      return `<${geometryPoint(value.center)},${geometryNumber(value.radius)}>`;
+  }
+  return null;
+}
+
+// 文本格式中加双引号的值, 其中的双引号和反斜杠要转义
+// A double quoted value in the text format, with double quotes and backslashes escaped
+function quoteText(value: string | number | boolean) {
+  return `"${String(value).replace(/["\\]/g, "\\$&")}"`;
+}
+
+/**
+ * 把后端转换后的范围还原为 PostgreSQL 的文本格式, 例如 [1,10) 或 empty
+ * Turn a range from the backend back into PostgreSQL's text format, e.g. [1,10) or empty
+ * @param value 例如 {empty: false, lower: 1, upper: 10, lowerInclusive: true, upperInclusive: false}
+ * e.g. {empty: false, lower: 1, upper: 10, lowerInclusive: true, upperInclusive: false}
+ * @returns
+ */
+function rangeText(value: Record<string, any>) {
+  if (value.empty) return "empty";
+  // 边界为 null 时没有边界 | A null bound is unbounded
+  const lower = value.lower === null ? "" : quoteText(value.lower);
+  const upper = value.upper === null ? "" : quoteText(value.upper);
+  return `${value.lowerInclusive ? "[" : "("}${lower},${upper}${value.upperInclusive ? "]" : ")"}`;
+}
+
+// 数组元素的文本格式, 字符串和结构化的值加双引号 | Text format of an array element, strings and structured values are double quoted
+function arrayElementText(element: any, elementType: string): string {
+  if (element === null) return "NULL";
+  if (typeof element === "string") return quoteText(element);
+  if (typeof element !== "object") return String(element);
+  // 嵌套的数组是多维数组的下一维, 多范围本身就是数组 | Nested arrays are the next dimension, multiranges are arrays themselves
+  if (Array.isArray(element) && !elementType.endsWith("multirange")) return arrayText(element, elementType);
+  return quoteText(pgText(element, elementType) ?? JSON.stringify(element));
+}
+
+/**
+ * 把后端转换后的数组还原为 PostgreSQL 的文本格式, 例如 {{1,2},{3,4}} 或 [0:1]={1,2}
+ * Turn an array from the backend back into PostgreSQL's text format, e.g. {{1,2},{3,4}} or [0:1]={1,2}
+ * @param value 嵌套的数组, 下标不从 1 开始时为 {lowerBounds, elements}
+ * Nested arrays, {lowerBounds, elements} when the subscripts don't start at 1
+ * @param elementType 元素的类型名, 未知时为空字符串 | Type name of the elements, an empty string when unknown
+ * @returns
+ */
+function arrayText(value: Record<string, any>, elementType: string): string {
+  if (!Array.isArray(value)) {
+    // 每一维的下标范围, 上界由该维的长度得出 | Subscript range of each dimension, the upper bound follows from its length
+    let level = value.elements;
+    const bounds = value.lowerBounds.map((lower: number) => {
+      const upper = lower + level.length - 1;
+      level = level[0];
+      return `[${lower}:${upper}]`;
+    });
+    return `${bounds.join("")}=${arrayText(value.elements, elementType)}`;
+  }
+
+  // BOX 的文本中含有逗号, 所以用分号分隔 | The text of a BOX contains commas, so semicolons separate them
+  const delimiter = elementType === "box" ? ";" : ",";
+  return `{${value.map((element) => arrayElementText(element, elementType)).join(delimiter)}}`;
+}
+
+/**
+ * 按列的类型把结构化的值转为文本格式, 不能转换时返回 null
+ * Turn a structured value into the text format of the column type, null when it can't be converted
+ * @param value 后端转换后的值 | Value converted by the backend
+ * @param pgType 列的类型名 (pg_type.typname) | Type name of the column (pg_type.typname)
+ * @returns
+ */
+function pgText(value: Record<string, any>, pgType: string): string | null {
+  // 数组类型的类型名以下划线开头 | Type names of array types start with an underscore
+  if (pgType.startsWith("_") && (Array.isArray(value) || "lowerBounds" in value)) {
+    return arrayText(value, pgType.slice(1));
+  }
+  // 多范围是范围的数组, 要先于范围判断 | A multirange is an array of ranges, so it is checked before ranges
+  if (pgType.endsWith("multirange") && Array.isArray(value)) {
+    return `{${value.map(rangeText).join(",")}}`;
+  }
+  if (pgType.endsWith("range") && !Array.isArray(value) && "empty" in value) {
+    return rangeText(value);
+  }
+  return geometryText(value, pgType);
+}
+
 /**
  * 格式化 PostgreSQL 的数据类型
  * @param value
  * @param allowFuncAcll 是否允许函数调用的形式
+ * @param pgType 列的类型名 (pg_type.typname), 范围, 几何类型等结构化的值按它转为字面量, 为空时按 jsonb 处理
+ * Type name of the column (pg_type.typname), structured values such as ranges become literals of it,
+ * they are treated as jsonb when empty
  * @returns
  */
-export function formatToSqlValuePg(value: PgValue, allowFuncAcll?: boolean): string {
+export function formatToSqlValuePg(value: PgValue, allowFuncAcll?: boolean, pgType?: string): string {
   // 首先检查是否是调用 PostgreSQL 函数
   if (allowFuncAcll && typeof value === "string" && isPostgresFunctionCall(value)) {
     return value;
   }
 
+  // 后端把数组, 范围, 几何类型等转成了 json, 要还原为该类型的字面量
+  // The backend turned arrays, ranges, geometric types and others into JSON,
+  // they must become literals of that type again
+  if (pgType && typeof value === "object" && value !== null) {
+    const text = pgText(value, pgType.toLowerCase());
+    if (text !== null) return `'${text.replace(/'/g, "''")}'`;
+  }
+
+  // 不知道列的类型时数组也写成文本格式, 由 PostgreSQL 按列的类型转换
+  // Arrays are written in the text format when the column type is unknown too,
+  // PostgreSQL converts them to the column type
+  if (Array.isArray(value)) {
+    return `'${arrayText(value, "").replace(/'/g, "''")}'`;
+  }
+
   // 尝试通用格式化
   try {
     return formatToSqlValueCommon(value as SqlValueCommon);
@@ -55,24 +190,6 @@ export function formatToSqlValuePg(value: PgValue, allowFuncAcll?: boolean): str
     return `'${JSON.stringify(value)}'::jsonb`;
   }
 
-  // 处理 PostgreSQL 数组类型（多维数组）
-  if (Array.isArray(value)) {
-    const formattedArray = value
-      .map((v) => {
-        // 特殊处理数组中的 null 值
-        if (v === null) return "NULL";
-
-        // 递归格式化每个元素
-        const element = formatToSqlValuePg(v);
-
-        // 处理元素中的引号和转义
-        return typeof v === "string" ? `"${element.replace(/"/g, '""')}"` : element;
-      })
-      .join(", ");
-
-    return `ARRAY[${formattedArray}]`;
-  }
-
   // 默认处理（不应执行到这里）
   throw new Error(`Unsupported PostgreSQL type: ${typeof value}`);
 }
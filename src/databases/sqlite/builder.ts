@@ -25,6 +25,7 @@ import {
   GetTableDataParam,
   getAllTableSizeRes,
 } from "../types";
+import { columnNames, rowsToObjects } from "../utils";
 import { genAlterCmdSqlite } from "./builder_alter_table";
 import { formatToSqlValueSqlite } from "./format";
 import "./types";
@@ -37,7 +38,7 @@ export class BuilderSqilte {
    *
    */
   static async connect(connName: string, p: DbConnectionParam) {
-    return await invoker.connectSql(connName, `sqlite:${p.filePath}`);
+    return await invoker.connectSql(connName, { engine: "sqlite", database: p.filePath });
   }
 
   // 获取所有表格名
@@ -46,10 +47,10 @@ export class BuilderSqilte {
     const dbRes = await invoker.querySql(connName, sql);
 
     // 把表名整理成一维数组
-    const dataArr = dbRes.data ? (JSON.parse(dbRes.data) as { name: string }[]) : [];
+    const dataArr = rowsToObjects<{ name: string }>(dbRes);
 
     return {
-      columnName: dbRes.columnName ? (JSON.parse(dbRes.columnName) as string[]) : [],
+      columnName: columnNames(dbRes),
       data: dataArr.map((item) => item.name),
     };
   }
@@ -85,8 +86,8 @@ export class BuilderSqilte {
     const dbRes = await invoker.querySql(connName, sql);
 
     return {
-      columnName: dbRes.columnName ? (JSON.parse(dbRes.columnName) as string[]) : [],
-      data: dbRes.data ? (JSON.parse(dbRes.data) as getAllTableSizeRes[]) : [],
+      columnName: columnNames(dbRes),
+      data: rowsToObjects<getAllTableSizeRes>(dbRes),
     };
   }
 
@@ -235,9 +236,9 @@ export class BuilderSqilte {
     ]);
 
     // 处理字段信息
-    const columns = columnRes.data ? (JSON.parse(columnRes.data) as TableStructureSqlite[]) : [];
+    const columns = rowsToObjects<TableStructureSqlite>(columnRes);
     // 处理索引信息
-    const indexes = indexRes.data ? (JSON.parse(indexRes.data) as FieldIndex[]) : [];
+    const indexes = rowsToObjects<FieldIndex>(indexRes);
 
     // 将索引信息合并到字段信息中
     const columnIndexMap: Record<string, FieldIndex[]> = {};
@@ -278,8 +279,8 @@ export class BuilderSqilte {
 
     let res = "";
 
-    if (dbRes.data && dbRes.data.length > 0) {
-      const data = JSON.parse(dbRes.data) as { sql: string }[];
+    const data = rowsToObjects<{ sql: string }>(dbRes);
+    if (data.length > 0) {
       res = data[0].sql;
     }
 
@@ -303,8 +304,8 @@ export class BuilderSqilte {
       `SELECT COUNT(*) AS total FROM "${tableName}" ${condition ? condition : ""};`,
     );
     let itemsTotal = 0; // 总条数
-    if (dbResTotal && dbResTotal.data) {
-      const bbCountRes = JSON.parse(dbResTotal.data) as DbCountRes[];
+    if (dbResTotal) {
+      const bbCountRes = rowsToObjects<DbCountRes>(dbResTotal);
 
       if (bbCountRes.length > 0) itemsTotal = bbCountRes[0].total;
     }
@@ -334,8 +335,8 @@ export class BuilderSqilte {
     return {
       itemsTotal,
       pageTotal,
-      columnName: dbRes.columnName ? (JSON.parse(dbRes.columnName) as string[]) : [],
-      data: dbRes.data ? (JSON.parse(dbRes.data) as object[]) : [],
+      columnName: columnNames(dbRes),
+      data: rowsToObjects<object>(dbRes),
     };
   }
 
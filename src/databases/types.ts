@@ -177,4 +177,5 @@ export type TableAlterAction = {
 export type AllAlterAction = FieldAlterAction | TableAlterAction;
 
 // 字段名及其值
-export type FieldWithValue = { field: string; value: any };
+// type 是列的类型名, 用于生成该类型的字面量 | type is the type name of the column, used to build literals of it
+export type FieldWithValue = { field: string; value: any; type?: string };
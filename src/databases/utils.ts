@@ -1,5 +1,52 @@
 import { RE_IS_SINGLET_QUERY, RE_WHERE_CLAUSE } from "@/constants";
-import { FieldStructure, SqlValueCommon } from "./types";
+import { DbError, QueryResult } from "@/types/types";
+import { FieldStructure, RowData, SqlValueCommon } from "./types";
+
+/**
+ * 查询结果的列名 | Column names of a query result
+ * @param res 查询结果 | Query result
+ * @returns
+ */
+export function columnNames(res: QueryResult) {
+  return res.columns.map((col) => col.name);
+}
+
+/**
+ * 把按列顺序排列的行转为以列名为键的对象, 列名重复时后面的列覆盖前面的
+ * Turn rows in column order into objects keyed by column name, later columns win on duplicate names
+ * @param res 查询结果 | Query result
+ * @returns
+ */
+export function rowsToObjects<T = RowData>(res: QueryResult) {
+  const names = columnNames(res);
+  return res.rows.map((row) => {
+    const obj: RowData = {};
+    names.forEach((name, idx) => {
+      obj[name] = row[idx];
+    });
+    return obj as T;
+  });
+}
+
+/**
+ * 判断 invoke 抛出的是不是数据库命令的错误 | Whether what invoke threw is a database command error
+ * @param err 捕获的错误 | The caught error
+ * @returns
+ */
+export function isDbError(err: unknown): err is DbError {
+  return typeof err === "object" && err !== null && "code" in err && "message" in err;
+}
+
+/**
+ * 取出 invoke 抛出的错误消息 | Get the message of an error thrown by invoke
+ * @param err 捕获的错误 | The caught error
+ * @returns
+ */
+export function dbErrorMessage(err: unknown) {
+  if (isDbError(err)) return err.message;
+  if (err instanceof Error) return err.message;
+  return `${err}`;
+}
 
 /**
  * 类型守卫函数
@@ -1,5 +1,5 @@
 import { invoke } from "@tauri-apps/api/core";
-import { DbResult } from "./types/types";
+import { ConnConfig, ConnSettings, ConnectInfo, ExecResult, QueryResult, SessionState } from "./types/types";
 
 // AES-GCM 加密解密的结果 | The result of AES-GCM encryption and decryption
 export type AesRes = { result: string; errorMessage: string };
@@ -32,41 +32,106 @@ export const invoker = {
   /**
    * 连接 SQL 数据库 | Connect to SQL database
    * @param connName 数据库连接的名字 | Name of database connection
-   * @param url 连接数据库的配置 | Database connection configuration
+   * @param config 连接数据库的配置 | Database connection configuration
+   * @param settings 连接的设置, 为空时使用默认值 | Connection settings, defaults are used when empty
    * @returns
    */
-  connectSql: (connName: string, url: string) => invoke<DbResult>("sqlx_connect", { connName, url }),
-  disconnectSql: (connName: string) => invoke<DbResult>("sqlx_disconnect", { connName }),
+  connectSql: (connName: string, config: ConnConfig, settings?: ConnSettings) =>
+    invoke<ConnectInfo>("sqlx_connect", { connName, config, settings }),
+  /**
+   * 把连接 URL 解析为连接参数 | Parse a connection URL into connection parameters
+   * @param url 连接 URL, 例如 postgres://user@localhost/db | Connection URL, e.g. postgres://user@localhost/db
+   * @returns
+   */
+  parseUrl: (url: string) => invoke<ConnConfig>("sqlx_parse_url", { url }),
+  disconnectSql: (connName: string) => invoke<boolean>("sqlx_disconnect", { connName }),
 
   /**
    * 执行 SQL 查询 | Execute SQL query
+   * 失败时抛出 DbError | Throws a DbError on failure
    * @param connName 数据库连接的名字 | Name of database connection
    * @param sql 要执行的语句 | Statement to be executed
-   * @param streaming 是否使用流式查询(用于在sql编辑器查询结果)
-   * @param page 流式查询的页码
-   * @param pageSize 流式查询的页大小
+   * @param maxRows 最多返回的行数, 超过时 truncated 为 true | Maximum rows to return, truncated is true beyond it
+   * @param sessionId 在该会话的连接上执行, 为空时使用连接池 | Run on this session's connection, the pool is used when empty
+   * @param confirmToken 确认令牌, 同 execSql | Confirmation token, same as execSql
    * @returns
    */
-  querySql: (connName: string, sql: string, streaming?: boolean, page?: number, pageSize?: number) => {
-    streaming ??= false;
-    page ??= 1;
-    pageSize ??= 100;
-    return invoke<DbResult>("sqlx_query", { connName, sql, streaming, page, pageSize });
-  },
+  querySql: (connName: string, sql: string, maxRows?: number, sessionId?: string, confirmToken?: string) =>
+    invoke<QueryResult>("sqlx_query", { connName, sql, maxRows, sessionId, confirmToken }),
+  /**
+   * 为查询打开游标, 用于分页浏览结果 | Open a cursor for a query, used to page through the results
+   * @param connName 数据库连接的名字 | Name of database connection
+   * @param sql 要执行的查询 | Query to be executed
+   * @param confirmToken 确认令牌, 同 execSql | Confirmation token, same as execSql
+   * @returns 游标 id | The cursor id
+   */
+  openCursor: (connName: string, sql: string, confirmToken?: string) =>
+    invoke<string>("sqlx_open_cursor", { connName, sql, confirmToken }),
+  /**
+   * 读取游标的一页数据, 还有剩余行时 truncated 为 true
+   * Read a page from the cursor, truncated is true while rows remain
+   * @param connName 数据库连接的名字 | Name of database connection
+   * @param cursorId openCursor 返回的 id | The id returned by openCursor
+   * @param page 页码, 从 1 开始 | Page number, starting from 1
+   * @param pageSize 每页的条目数 | Number of entries per page
+   * @returns
+   */
+  fetchPage: (connName: string, cursorId: string, page: number, pageSize: number) =>
+    invoke<QueryResult>("sqlx_fetch_page", { connName, cursorId, page, pageSize }),
+  closeCursor: (connName: string, cursorId: string) => invoke<boolean>("sqlx_close_cursor", { connName, cursorId }),
   /**
    * 执行非查询语句 | Execute non query statements
+   * 失败时抛出 DbError | Throws a DbError on failure
    * @param connName 数据库连接的名字 | Name of database connection
    * @param sql 要执行的语句 | Statement to be executed
+   * @param confirmToken 确认令牌, 破坏性语句返回 confirmation_required 时在 confirmToken 中给出
+   * Confirmation token, given in confirmToken when a destructive statement returns confirmation_required
+   * @param sessionId 在该会话的连接上执行, 事务控制语句必须传入
+   * Run on this session's connection, required for transaction control statements
    * @returns
    */
-  execSql: (connName: string, sql: string) => invoke<DbResult>("sqlx_exec", { connName, sql }),
+  execSql: (connName: string, sql: string, confirmToken?: string, sessionId?: string) =>
+    invoke<ExecResult>("sqlx_exec", { connName, sql, confirmToken, sessionId }),
   /**
    * 执行多条语句 | Execute multiple statements
    * 后端会开启事务, 直接传入语句即可
    * The backend will start the transaction, just pass in the statement directly
    * @param connName 数据库连接的名字 | Name of database connection
    * @param sql 要执行的语句 | Statements to be executed
+   * @param confirmToken 确认令牌, 同 execSql | Confirmation token, same as execSql
+   * @param sessionId 会话 id, 同 execSql | Session id, same as execSql
    * @returns
    */
-  execManySql: (connName: string, sql: string) => invoke<DbResult>("sqlx_exec_many", { connName, sql }),
+  execManySql: (connName: string, sql: string, confirmToken?: string, sessionId?: string) =>
+    invoke<ExecResult>("sqlx_exec_many", { connName, sql, confirmToken, sessionId }),
+
+  // Session
+  /**
+   * 打开会话, 会话独占一个连接, 事务可以跨多次调用
+   * Open a session, it holds one connection so a transaction can span several calls
+   * @param connName 数据库连接的名字 | Name of database connection
+   * @returns 会话 id | The session id
+   */
+  openSession: (connName: string) => invoke<string>("sqlx_session_open", { connName }),
+  /**
+   * 关闭会话, 未提交的事务会回滚 | Close the session, an uncommitted transaction is rolled back
+   * @param connName 数据库连接的名字 | Name of database connection
+   * @param sessionId openSession 返回的 id | The id returned by openSession
+   * @returns 会话是否存在 | Whether the session existed
+   */
+  closeSession: (connName: string, sessionId: string) => invoke<boolean>("sqlx_session_close", { connName, sessionId }),
+  sessionState: (connName: string, sessionId: string) =>
+    invoke<SessionState>("sqlx_session_state", { connName, sessionId }),
+  sessionBegin: (connName: string, sessionId: string) =>
+    invoke<SessionState>("sqlx_session_begin", { connName, sessionId }),
+  sessionCommit: (connName: string, sessionId: string) =>
+    invoke<SessionState>("sqlx_session_commit", { connName, sessionId }),
+  sessionRollback: (connName: string, sessionId: string) =>
+    invoke<SessionState>("sqlx_session_rollback", { connName, sessionId }),
+  sessionSavepoint: (connName: string, sessionId: string, name: string) =>
+    invoke<SessionState>("sqlx_session_savepoint", { connName, sessionId, name }),
+  sessionRollbackTo: (connName: string, sessionId: string, name: string) =>
+    invoke<SessionState>("sqlx_session_rollback_to", { connName, sessionId, name }),
+  sessionRelease: (connName: string, sessionId: string, name: string) =>
+    invoke<SessionState>("sqlx_session_release", { connName, sessionId, name }),
 };
@@ -0,0 +1,70 @@
+import { expect, test } from "vitest";
+import { formatToSqlValuePg } from "@/databases/postgresql/format";
+
+test("should turn geometric values back into PostgreSQL literals", () => {
+  const p1 = { x: 1, y: 2 };
+  const p2 = { x: 3.5, y: -4 };
+  expect(formatToSqlValuePg(p1, false, "point")).toBe("'(1,2)'");
+  expect(formatToSqlValuePg({ a: 1, b: -1, c: 0 }, false, "line")).toBe("'{1,-1,0}'");
+  expect(formatToSqlValuePg({ start: p1, end: p2 }, false, "lseg")).toBe("'[(1,2),(3.5,-4)]'");
+  expect(formatToSqlValuePg({ high: p2, low: p1 }, false, "box")).toBe("'(3.5,-4),(1,2)'");
+  expect(formatToSqlValuePg({ closed: false, points: [p1, p2] }, false, "path")).toBe("'[(1,2),(3.5,-4)]'");
+  expect(formatToSqlValuePg({ closed: true, points: [p1, p2] }, false, "path")).toBe("'((1,2),(3.5,-4))'");
+  expect(formatToSqlValuePg({ points: [p1, p2, p1] }, false, "polygon")).toBe("'((1,2),(3.5,-4),(1,2))'");
+  expect(formatToSqlValuePg({ center: p1, radius: 3 }, false, "circle")).toBe("'<(1,2),3>'");
+  expect(formatToSqlValuePg({ x: null, y: 0 }, false, "point")).toBe("'(NaN,0)'");
+});
+
+test("should keep treating objects as jsonb without a geometric column type", () => {
+  expect(formatToSqlValuePg({ x: 1, y: 2 })).toBe(`'{"x":1,"y":2}'::jsonb`);
+  expect(formatToSqlValuePg({ x: 1, y: 2 }, false, "jsonb")).toBe(`'{"x":1,"y":2}'::jsonb`);
+});
+
+test("should turn ranges back into PostgreSQL literals with double quoted bounds", () => {
+  const range = (lower: any, upper: any, lowerInclusive: boolean, upperInclusive: boolean) => ({
+    empty: false,
+    lower,
+    upper,
+    lowerInclusive,
+    upperInclusive,
+  });
+  expect(formatToSqlValuePg(range(1, 10, true, false), false, "int4range")).toBe(`'["1","10")'`);
+  expect(formatToSqlValuePg(range(null, "2024-01-02", false, false), false, "daterange")).toBe(`'(,"2024-01-02")'`);
+  expect(formatToSqlValuePg(range("a\"b", "c\\d", true, true), false, "textrange")).toBe(`'["a\\"b","c\\\\d"]'`);
+  expect(formatToSqlValuePg(range("it's", null, true, false), false, "textrange")).toBe(`'["it''s",)'`);
+  const empty = { empty: true, lower: null, upper: null, lowerInclusive: false, upperInclusive: false };
+  expect(formatToSqlValuePg(empty, false, "numrange")).toBe("'empty'");
+  const multirange = [range(1, 3, true, false), range(5, 7, true, false)];
+  expect(formatToSqlValuePg(multirange, false, "int4multirange")).toBe(`'{["1","3"),["5","7")}'`);
+  expect(formatToSqlValuePg([], false, "int4multirange")).toBe("'{}'");
+});
+
+test("should turn arrays back into PostgreSQL array literals", () => {
+  expect(formatToSqlValuePg([1, null, 3], false, "_int4")).toBe("'{1,NULL,3}'");
+  expect(
+    formatToSqlValuePg(
+      [
+        ["a", "it's"],
+        ['say "hi"', "NULL"],
+      ],
+      false,
+      "_text",
+    ),
+  ).toBe(`'{{"a","it''s"},{"say \\"hi\\"","NULL"}}'`);
+  expect(formatToSqlValuePg({ lowerBounds: [0], elements: [1, 2, 3] }, false, "_int4")).toBe("'[0:2]={1,2,3}'");
+  const bounded = {
+    lowerBounds: [0, -1],
+    elements: [
+      [1, 2],
+      [3, 4],
+    ],
+  };
+  expect(formatToSqlValuePg(bounded, false, "_int4")).toBe("'[0:1][-1:0]={{1,2},{3,4}}'");
+  expect(formatToSqlValuePg([], false, "_int4")).toBe("'{}'");
+  // 元素是结构化的值 | Structured elements
+  const box = { high: { x: 1, y: 1 }, low: { x: 0, y: 0 } };
+  expect(formatToSqlValuePg([box, box], false, "_box")).toBe(`'{"(1,1),(0,0)";"(1,1),(0,0)"}'`);
+  expect(formatToSqlValuePg([{ a: 1 }], false, "_jsonb")).toBe(`'{"{\\"a\\":1}"}'`);
+  // 不知道列的类型时也是文本格式 | The text format is used without the column type too
+  expect(formatToSqlValuePg([["a"], ["b"]])).toBe(`'{{"a"},{"b"}}'`);
+});
@@ -0,0 +1,33 @@
+import { expect, test } from "vitest";
+import { columnNames, dbErrorMessage, rowsToObjects } from "@/databases/utils";
+import { QueryResult } from "@/types/types";
+
+const res: QueryResult = {
+  columns: [
+    { name: "id", typeName: "INT4", nullable: false, ordinal: 0 },
+    { name: "name", typeName: "TEXT", ordinal: 1 },
+  ],
+  rows: [
+    [1, "a"],
+    [2, null],
+  ],
+  elapsedMs: 3,
+  truncated: false,
+};
+
+test("should list column names in order", () => {
+  expect(columnNames(res)).toStrictEqual(["id", "name"]);
+});
+
+test("should turn positional rows into objects keyed by column name", () => {
+  expect(rowsToObjects(res)).toStrictEqual([
+    { id: 1, name: "a" },
+    { id: 2, name: null },
+  ]);
+});
+
+test("should read the message of a DbError and of other thrown values", () => {
+  expect(dbErrorMessage({ code: "read_only", message: "read only" })).toBe("read only");
+  expect(dbErrorMessage(new Error("boom"))).toBe("boom");
+  expect(dbErrorMessage("plain")).toBe("plain");
+});
@@ -19,31 +19,136 @@ import {
   STR_EMPTY,
 } from "@/constants";
 
-// 数据库返回的数据 | Data returned from the database
-export type DbResult = {
-  // 列表数组的 json, 仅查询的时候有数据
-  // JSON for list arrays, with data available only during queries
-  columnName: string;
-  // 查询结果, 实际是 json 字符串
-  // The query result is actually a JSON string
-  data: string;
-  errorMessage: string;
+// 后端支持的数据库引擎 | Database engines supported by the backend
+export type DbEngine = "postgres" | "mysql" | "sqlite";
+
+// SSH 认证方式 | SSH authentication method
+export type SshAuth =
+  | { method: "password"; password: string }
+  | { method: "key"; privateKey: string; passphrase?: string }
+  | { method: "agent" };
+
+// 连接参数, 由后端组装连接, 不再在前端拼接 URL
+// Connection parameters, the backend builds the connection instead of the frontend concatenating a URL
+export type ConnConfig = {
+  engine: DbEngine;
+  host?: string;
+  port?: number;
+  user?: string;
+  password?: string;
+  // SQLite 是数据库文件路径 | The database file path for SQLite
+  database?: string;
+  // disable / prefer / require / verify-ca / verify-full
+  sslMode?: string;
+  // Unix 套接字路径 | Unix socket path
+  socket?: string;
+  tls?: { caCert?: string; clientCert?: string; clientKey?: string };
+  // 通过 SSH 跳板机连接 | Connect through an SSH jump host
+  ssh?: {
+    host: string;
+    port?: number;
+    user: string;
+    auth: SshAuth;
+    knownHosts?: string;
+    acceptNewHost?: boolean;
+  };
+  // 其它连接参数, 例如 application_name / charset | Extra connection options, e.g. application_name / charset
+  options?: Record<string, string>;
+};
+
+// 连接的设置 | Connection settings
+export type ConnSettings = {
+  statementTimeoutMs?: number;
+  preparedStatements?: boolean;
+  initSql?: string;
+  readOnly?: boolean;
+  pool?: {
+    minConnections?: number;
+    maxConnections?: number;
+    acquireTimeoutMs?: number;
+    idleTimeoutMs?: number;
+    maxLifetimeMs?: number;
+    testBeforeAcquire?: boolean;
+  };
+};
+
+// 连接成功后返回的信息 | Information returned after connecting
+export type ConnectInfo = {
+  // 协商的 TLS 状态, SQLite 没有 | Negotiated TLS state, missing for SQLite
+  tls?: { enabled: boolean; version?: string; cipher?: string };
+};
+
+// 结果集中一列的信息 | Information about a column in the result set
+export type ColumnInfo = {
+  name: string;
+  // 数据库报告的类型名, 例如 INT4, VARCHAR | Type name reported by the database, e.g. INT4, VARCHAR
+  typeName: string;
+  // 驱动无法判断时没有这个字段 | Missing when the driver can't tell
+  nullable?: boolean;
+  ordinal: number;
 };
 
 // 数据库的查询(query)结果 | Returned (query) results from database
 export type QueryResult = {
-  // 列表数组的 json
-  // JSON for list arrays
-  columnName: string;
-  // 查询结果, 实际是 json 字符串
-  // The query result is actually a JSON string
-  data: string;
+  columns: ColumnInfo[];
+  // 每一行是按列顺序排列的值 | Each row holds the values in column order
+  rows: any[][];
+  elapsedMs: number;
+  // 超过 maxRows 被截断 | Cut off after maxRows
+  truncated: boolean;
 };
 
 // 数据库的执行(exec)结果 | Execution (exec) results of the database
 export type ExecResult = {
   affectedRows: number;
-  lastInsertId: number;
+  lastInsertId?: number;
+  elapsedMs: number;
+  // 在会话中执行时, 执行后是否处于事务中 | When run in a session, whether a transaction is open afterwards
+  inTransaction?: boolean;
+};
+
+// 会话的事务状态 | Transaction state of a session
+export type SessionState = {
+  id: string;
+  inTransaction: boolean;
+  // 当前事务中的保存点, 按创建顺序排列 | Savepoints of the current transaction, in creation order
+  savepoints: string[];
+};
+
+// 数据库命令失败时 invoke 抛出的错误 | Error thrown by invoke when a database command fails
+export type DbErrorCode =
+  | "duplicate_connection"
+  | "connection_not_found"
+  | "cursor_not_found"
+  | "session_not_found"
+  | "invalid_argument"
+  | "invalid_state"
+  | "invalid_config"
+  | "parse_error"
+  | "read_only"
+  | "confirmation_required"
+  | "cancelled"
+  | "timeout"
+  | "tunnel_error"
+  | "unknown_host_key"
+  | "driver_error";
+
+export type DbError = {
+  code: DbErrorCode;
+  message: string;
+  sqlState?: string;
+  // 出错位置在 SQL 中的字符序号, 从 1 开始 | Character position of the error in the SQL, starting from 1
+  position?: number;
+  detail?: string;
+  hint?: string;
+  constraint?: string;
+  schema?: string;
+  table?: string;
+  column?: string;
+  // 数据库自己的错误编号, 目前只有 MySQL 提供 | The database's own error number, only MySQL provides it for now
+  vendorCode?: number;
+  // confirmation_required 时发出的确认令牌 | Confirmation token issued with confirmation_required
+  confirmToken?: string;
 };
 
 // 列表栏的类型